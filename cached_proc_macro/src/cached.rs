@@ -3,7 +3,40 @@ use darling::FromMeta;
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::spanned::Spanned;
-use syn::{parse_macro_input, parse_str, AttributeArgs, Block, Ident, ItemFn, ReturnType, Type};
+use syn::{parse_quote, Block, Expr, Ident, ItemFn, Member, ReturnType, Type};
+
+/// How the execution of uncached values is synchronized
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum SyncWrites {
+    /// concurrent misses each run the function
+    #[default]
+    Disabled,
+    /// the cache stays locked while the function runs
+    Cache,
+    /// concurrent misses on the same key wait on one computation
+    ByKey,
+}
+
+impl FromMeta for SyncWrites {
+    fn from_word() -> darling::Result<Self> {
+        Ok(SyncWrites::Cache)
+    }
+
+    fn from_bool(value: bool) -> darling::Result<Self> {
+        Ok(if value {
+            SyncWrites::Cache
+        } else {
+            SyncWrites::Disabled
+        })
+    }
+
+    fn from_string(value: &str) -> darling::Result<Self> {
+        match value {
+            "by_key" => Ok(SyncWrites::ByKey),
+            _ => Err(darling::Error::unknown_value(value)),
+        }
+    }
+}
 
 #[derive(FromMeta)]
 struct MacroArgs {
@@ -14,36 +47,67 @@ struct MacroArgs {
     #[darling(default)]
     size: Option<usize>,
     #[darling(default)]
+    policy: Option<String>,
+    #[darling(default)]
+    max_weight: Option<usize>,
+    #[darling(default)]
+    weigher: Option<Syntax<Expr>>,
+    #[darling(default)]
+    shards: Option<usize>,
+    #[darling(default)]
     time: Option<u64>,
     #[darling(default)]
+    time_ms: Option<u64>,
+    #[darling(default)]
     time_refresh: bool,
     #[darling(default)]
-    key: Option<String>,
+    flush_interval: Option<u64>,
+    #[darling(default)]
+    stale_while_revalidate: Option<u64>,
+    #[darling(default)]
+    serve_stale_on_error: Option<u64>,
+    #[darling(default)]
+    cache_err_for: Option<u64>,
+    #[darling(default)]
+    key: Option<Syntax<Type>>,
     #[darling(default)]
-    convert: Option<String>,
+    convert: Option<Syntax<Block>>,
     #[darling(default)]
     result: bool,
     #[darling(default)]
     option: bool,
     #[darling(default)]
-    sync_writes: bool,
+    cache_if: Option<Syntax<Expr>>,
+    #[darling(default)]
+    ttl_from: Option<Syntax<Expr>>,
+    #[darling(default)]
+    sync_writes: SyncWrites,
     #[darling(default)]
     with_cached_flag: bool,
+    #[darling(default)]
+    in_impl: bool,
+    #[darling(default)]
+    ignore_self: bool,
+    #[darling(default)]
+    cache_field: Option<Syntax<Member>>,
     #[darling(default, rename = "type")]
-    cache_type: Option<String>,
+    cache_type: Option<Syntax<Type>>,
     #[darling(default, rename = "create")]
-    cache_create: Option<String>,
+    cache_create: Option<Syntax<Block>>,
 }
 
 pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
-    let attr_args = parse_macro_input!(args as AttributeArgs);
-    let args = match MacroArgs::from_list(&attr_args) {
-        Ok(v) => v,
-        Err(e) => {
-            return TokenStream::from(e.write_errors());
-        }
-    };
-    let input = parse_macro_input!(input as ItemFn);
+    match expand(args, input) {
+        Ok(expanded) => expanded,
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream> {
+    let attr_args = parse_attr_args(args)?;
+    let spans = ArgSpans::new(&attr_args);
+    let args = MacroArgs::from_list(&attr_args)?;
+    let input = syn::parse::<ItemFn>(input)?;
 
     // pull out the parts of the input
     let mut attributes = input.attrs;
@@ -56,10 +120,45 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
     let inputs = signature.inputs.clone();
     let output = signature.output.clone();
     let asyncness = signature.asyncness;
+    let generics = signature.generics.clone();
+    let where_clause = &generics.where_clause;
 
     let input_tys = get_input_types(&inputs);
     let input_names = get_input_names(&inputs);
 
+    // functions in `impl` blocks can't declare statics next to them
+    let receiver = has_receiver(&inputs);
+    let in_impl = args.in_impl || receiver;
+    check_receiver_key(
+        &spans,
+        &inputs,
+        args.ignore_self,
+        Some(&args.convert),
+        &args.cache_field,
+    )?;
+    if args.cache_field.is_some()
+        && (args.unbound
+            || args.size.is_some()
+            || args.time.is_some()
+            || args.time_ms.is_some()
+            || args.max_weight.is_some()
+            || args.shards.is_some()
+            || args.flush_interval.is_some()
+            || args.cache_type.is_some()
+            || args.cache_create.is_some())
+    {
+        return Err(spans.error(
+            "cache_field",
+            "cache_field cannot be combined with attributes defining the cache store",
+        ));
+    }
+    if receiver && args.stale_while_revalidate.is_some() {
+        return Err(spans.error(
+            "stale_while_revalidate",
+            "stale_while_revalidate is not supported on methods taking `self`",
+        ));
+    }
+
     // pull out the output type
     let output_ty = match &output {
         ReturnType::Default => quote! {()},
@@ -73,10 +172,12 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
     let output_type_display = output_ts.to_string().replace(' ', "");
 
     if check_with_cache_flag(args.with_cached_flag, output_string) {
-        return with_cache_flag_error(output_span, output_type_display);
+        return Err(with_cache_flag_error(output_span, output_type_display));
     }
 
-    let cache_value_ty = find_value_type(args.result, args.option, &output, output_ty);
+    let cache_value_ty = find_value_type(&spans, args.result, args.option, &output, output_ty)?;
+
+    check_generics(&generics, &inputs, true, &args.convert, &cache_value_ty)?;
 
     // make the cache identifier
     let cache_ident = match args.name {
@@ -85,18 +186,166 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
     };
 
     let (cache_key_ty, key_convert_block) = make_cache_key_type(
+        &spans,
         &args.key,
         &args.convert,
         &args.cache_type,
         input_tys,
         &input_names,
-    );
+    )?;
+
+    let lifespan = make_lifespan(&spans, args.time, args.time_ms)?;
+
+    // values are kept for an extra grace period after they turn stale,
+    // `fresh_lifespan` is the age at which they stop being fresh
+    let fresh_lifespan = lifespan.clone();
+    let swr_error = |message| Err(spans.error("stale_while_revalidate", message));
+    let lifespan = match (lifespan, args.stale_while_revalidate) {
+        (_, Some(0)) => return swr_error("stale_while_revalidate must be greater than zero"),
+        (None, Some(_)) => {
+            return swr_error("stale_while_revalidate requires time or time_ms to be set")
+        }
+        (Some(_), Some(_)) if args.time_refresh => {
+            return swr_error("stale_while_revalidate and time_refresh are mutually exclusive")
+        }
+        (Some(_), Some(_)) if args.shards.is_some() => {
+            return swr_error("stale_while_revalidate cannot be combined with shards")
+        }
+        (Some(lifespan), Some(grace)) => {
+            Some(quote! { #lifespan + ::std::time::Duration::from_secs(#grace) })
+        }
+        (lifespan, None) => lifespan,
+    };
+
+    // the same goes for values that are only returned when the function fails
+    let stale_error = |message| Err(spans.error("serve_stale_on_error", message));
+    let lifespan = match (lifespan, args.serve_stale_on_error) {
+        (_, Some(0)) => return stale_error("serve_stale_on_error must be greater than zero"),
+        (_, Some(_)) if !args.result => {
+            return stale_error("serve_stale_on_error requires result = true")
+        }
+        (None, Some(_)) => {
+            return stale_error("serve_stale_on_error requires time or time_ms to be set")
+        }
+        (Some(_), Some(_)) if args.stale_while_revalidate.is_some() => {
+            return stale_error(
+                "serve_stale_on_error and stale_while_revalidate are mutually exclusive",
+            )
+        }
+        (Some(_), Some(_)) if args.time_refresh => {
+            return stale_error("serve_stale_on_error and time_refresh are mutually exclusive")
+        }
+        (Some(_), Some(_)) if args.shards.is_some() => {
+            return stale_error("serve_stale_on_error cannot be combined with shards")
+        }
+        (Some(lifespan), Some(grace)) => {
+            Some(quote! { #lifespan + ::std::time::Duration::from_secs(#grace) })
+        }
+        (lifespan, None) => lifespan,
+    };
+
+    // errors are cached for a lifespan of their own in a separate store
+    let err_error = |message| Err(spans.error("cache_err_for", message));
+    let errors_ty = match args.cache_err_for {
+        Some(0) => return err_error("cache_err_for must be greater than zero"),
+        Some(_) if !args.result => return err_error("cache_err_for requires result = true"),
+        Some(_) if args.cache_field.is_some() => {
+            return err_error("cache_err_for cannot be combined with cache_field")
+        }
+        Some(_) if args.serve_stale_on_error.is_some() => {
+            return err_error("cache_err_for and serve_stale_on_error are mutually exclusive")
+        }
+        Some(_) if cache_key_ty.is_empty() => {
+            return err_error("cache_err_for requires `key` to be set when `type` is used")
+        }
+        Some(secs) => {
+            let error_ty = find_error_type(&spans, "cache_err_for", &output, &generics)?;
+            Some((
+                quote! { cached::TimedCache<#cache_key_ty, #error_ty> },
+                quote! { cached::TimedCache::with_lifespan_duration(::std::time::Duration::from_secs(#secs)) },
+            ))
+        }
+        None => None,
+    };
+
+    if args.policy.is_some() && (args.size.is_none() || lifespan.is_some()) {
+        return Err(spans.error(
+            "policy",
+            "policy requires size to be set and cannot be combined with time",
+        ));
+    }
+
+    if args.max_weight.is_some() != args.weigher.is_some() {
+        let arg = if args.max_weight.is_some() {
+            "max_weight"
+        } else {
+            "weigher"
+        };
+        return Err(spans.error(arg, "max_weight and weigher must be set together"));
+    }
+    if args.max_weight.is_some()
+        && (args.unbound
+            || args.size.is_some()
+            || lifespan.is_some()
+            || args.cache_type.is_some()
+            || args.cache_create.is_some())
+    {
+        return Err(spans.error(
+            "max_weight",
+            "cache types (unbound, size and/or time, max_weight, or type and create) are mutually exclusive",
+        ));
+    }
+
+    if args.shards == Some(0) {
+        return Err(spans.error("shards", "shards must be greater than zero"));
+    }
+    if args.shards.is_some() && args.sync_writes == SyncWrites::Cache && asyncness.is_some() {
+        return Err(spans.error(
+            "sync_writes",
+            "sync_writes cannot be combined with shards on async functions",
+        ));
+    }
+
+    if args.flush_interval == Some(0) {
+        return Err(spans.error("flush_interval", "flush_interval must be greater than zero"));
+    }
+    if args.flush_interval.is_some()
+        && lifespan.is_none()
+        && args.cache_type.is_none()
+        && args.ttl_from.is_none()
+    {
+        return Err(spans.error(
+            "flush_interval",
+            "flush_interval requires time, time_ms, ttl_from or a custom cache type to be set",
+        ));
+    }
+
+    // values set with a lifespan of their own are kept in a `TtlCache`
+    if args.ttl_from.is_some()
+        && (args.unbound
+            || args.size.is_some()
+            || lifespan.is_some()
+            || args.time_refresh
+            || args.max_weight.is_some()
+            || args.shards.is_some())
+    {
+        return Err(spans.error(
+            "ttl_from",
+            "ttl_from cannot be combined with unbound, size, time, time_ms, time_refresh, max_weight or shards",
+        ));
+    }
+
+    // a sharded cache splits `size` across its shards
+    let size = match (args.size, args.shards) {
+        (Some(size), Some(shards)) => Some(size.div_ceil(shards)),
+        (size, _) => size,
+    };
 
     // make the cache type and create statement
     let (cache_ty, cache_create) = match (
         &args.unbound,
-        &args.size,
-        &args.time,
+        &size,
+        &lifespan,
         &args.cache_type,
         &args.cache_create,
         &args.time_refresh,
@@ -106,50 +355,95 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
             let cache_create = quote! {cached::UnboundCache::new()};
             (cache_ty, cache_create)
         }
-        (false, Some(size), None, None, None, _) => {
-            let cache_ty = quote! {cached::SizedCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::SizedCache::with_size(#size)};
-            (cache_ty, cache_create)
-        }
-        (false, None, Some(time), None, None, time_refresh) => {
+        (false, Some(size), None, None, None, _) => match args.policy.as_deref() {
+            None | Some("lru") => {
+                let cache_ty = quote! {cached::SizedCache<#cache_key_ty, #cache_value_ty>};
+                let cache_create = quote! {cached::SizedCache::with_size(#size)};
+                (cache_ty, cache_create)
+            }
+            Some("lfu") => {
+                let cache_ty = quote! {cached::LfuCache<#cache_key_ty, #cache_value_ty>};
+                let cache_create = quote! {cached::LfuCache::with_size(#size)};
+                (cache_ty, cache_create)
+            }
+            Some(policy) => {
+                return Err(spans.error(
+                    "policy",
+                    format!("unknown cache policy `{}`, expected `lru` or `lfu`", policy),
+                ))
+            }
+        },
+        (false, None, Some(lifespan), None, None, time_refresh) => {
             let cache_ty = quote! {cached::TimedCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create =
-                quote! {cached::TimedCache::with_lifespan_and_refresh(#time, #time_refresh)};
+            let cache_create = quote! {cached::TimedCache::with_lifespan_duration_and_refresh(#lifespan, #time_refresh)};
             (cache_ty, cache_create)
         }
-        (false, Some(size), Some(time), None, None, time_refresh) => {
+        (false, Some(size), Some(lifespan), None, None, time_refresh) => {
             let cache_ty = quote! {cached::TimedSizedCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::TimedSizedCache::with_size_and_lifespan_and_refresh(#size, #time, #time_refresh)};
-            (cache_ty, cache_create)
-        }
-        (false, None, None, None, None, _) => {
-            let cache_ty = quote! {cached::UnboundCache<#cache_key_ty, #cache_value_ty>};
-            let cache_create = quote! {cached::UnboundCache::new()};
+            let cache_create = quote! {cached::TimedSizedCache::with_size_and_lifespan_duration_and_refresh(#size, #lifespan, #time_refresh)};
             (cache_ty, cache_create)
         }
-        (false, None, None, Some(type_str), Some(create_str), _) => {
-            let cache_type = parse_str::<Type>(type_str).expect("unable to parse cache type");
-
-            let cache_create =
-                parse_str::<Block>(create_str).expect("unable to parse cache create block");
-
-            (quote! { #cache_type }, quote! { #cache_create })
+        (false, None, None, None, None, _) => match (&args.max_weight, &args.weigher) {
+            (Some(max_weight), Some(weigher)) => {
+                let cache_ty = quote! {cached::WeightedCache<#cache_key_ty, #cache_value_ty>};
+                let cache_create =
+                    quote! {cached::WeightedCache::with_max_weight(#max_weight, #weigher)};
+                (cache_ty, cache_create)
+            }
+            _ if args.ttl_from.is_some() => {
+                let cache_ty = quote! {cached::TtlCache<#cache_key_ty, #cache_value_ty>};
+                let cache_create =
+                    quote! {cached::TtlCache::with_default_ttl(::std::time::Duration::MAX)};
+                (cache_ty, cache_create)
+            }
+            _ => {
+                let cache_ty = quote! {cached::UnboundCache<#cache_key_ty, #cache_value_ty>};
+                let cache_create = quote! {cached::UnboundCache::new()};
+                (cache_ty, cache_create)
+            }
+        },
+        (false, None, None, Some(cache_type), Some(cache_create), _) => {
+            (quote! { #cache_type }, cache_create.to_expr())
         }
         (false, None, None, Some(_), None, _) => {
-            panic!("type requires create to also be set")
+            return Err(spans.error("type", "type requires create to also be set"))
         }
         (false, None, None, None, Some(_), _) => {
-            panic!("create requires type to also be set")
+            return Err(spans.error("create", "create requires type to also be set"))
+        }
+        _ => {
+            let arg = match (&args.cache_type, &args.cache_create) {
+                (Some(_), _) => "type",
+                (None, Some(_)) => "create",
+                (None, None) => "unbound",
+            };
+            return Err(spans.error(
+                arg,
+                "cache types (unbound, size and/or time, max_weight, or type and create) are mutually exclusive",
+            ));
         }
-        _ => panic!(
-            "cache types (unbound, size and/or time, or type and create) are mutually exclusive"
-        ),
     };
 
+    let errors_ident = Ident::new(&format!("{}_ERRORS", cache_ident), cache_ident.span());
+
     // make the set cache and return cache blocks
+    let cache_set_block = |value_ref| {
+        let cache_set = gen_cache_set(
+            &args.ttl_from,
+            &cache_value_ty,
+            quote! { result.clone() },
+            value_ref,
+        );
+        quote! { #cache_set; }
+    };
     let (set_cache_block, return_cache_block) = match (&args.result, &args.option) {
         (false, false) => {
-            let set_cache_block = quote! { cache.cache_set(key, result.clone()); };
+            let set_cache_block = gen_cache_if_block(
+                &args.cache_if,
+                &cache_value_ty,
+                quote! { &result },
+                cache_set_block(quote! { &result }),
+            );
             let return_cache_block = if args.with_cached_flag {
                 quote! { let mut r = result.clone(); r.was_cached = true; return r }
             } else {
@@ -158,10 +452,26 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
             (set_cache_block, return_cache_block)
         }
         (true, false) => {
-            let set_cache_block = quote! {
-                if let Ok(result) = &result {
-                    cache.cache_set(key, result.clone());
-                }
+            let set_cache_block = gen_cache_if_block(
+                &args.cache_if,
+                &cache_value_ty,
+                quote! { result },
+                cache_set_block(quote! { result }),
+            );
+            let set_cache_block = match &errors_ty {
+                Some(_) => quote! {
+                    match &result {
+                        Ok(result) => { #set_cache_block }
+                        Err(error) => {
+                            ::cached::Cached::cache_set(&mut *#errors_ident.lock().unwrap(), key, error.clone());
+                        }
+                    }
+                },
+                None => quote! {
+                    if let Ok(result) = &result {
+                        #set_cache_block
+                    }
+                },
             };
             let return_cache_block = if args.with_cached_flag {
                 quote! { let mut r = result.clone(); r.was_cached = true; return Ok(r) }
@@ -171,9 +481,15 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
             (set_cache_block, return_cache_block)
         }
         (false, true) => {
+            let set_cache_block = gen_cache_if_block(
+                &args.cache_if,
+                &cache_value_ty,
+                quote! { result },
+                cache_set_block(quote! { result }),
+            );
             let set_cache_block = quote! {
                 if let Some(result) = &result {
-                    cache.cache_set(key, result.clone());
+                    #set_cache_block
                 }
             };
             let return_cache_block = if args.with_cached_flag {
@@ -183,7 +499,7 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
             };
             (set_cache_block, return_cache_block)
         }
-        _ => panic!("the result and option attributes are mutually exclusive"),
+        (true, true) => unreachable!("rejected by find_value_type"),
     };
 
     let set_cache_and_return = quote! {
@@ -191,27 +507,69 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
         result
     };
 
+    // expired values are flushed from a background thread, locking the
+    // cache like any other caller. Async caches are locked with
+    // `blocking_lock` so that no particular async runtime is required.
+    let reaper = match args.flush_interval {
+        Some(interval) => {
+            let flush = if args.shards.is_some() {
+                quote! {
+                    ::cached::ConcurrentCached::cache_flush(&*#cache_ident);
+                    true
+                }
+            } else if asyncness.is_some() {
+                quote! {
+                    ::cached::Cached::cache_flush(&mut *#cache_ident.blocking_lock());
+                    true
+                }
+            } else {
+                quote! {
+                    match #cache_ident.lock() {
+                        Ok(mut cache) => {
+                            ::cached::Cached::cache_flush(&mut *cache);
+                            true
+                        }
+                        Err(_) => false,
+                    }
+                }
+            };
+            quote! {
+                ::cached::Reaper::spawn(::std::time::Duration::from_secs(#interval), || { #flush }).detach();
+            }
+        }
+        None => quote! {},
+    };
+
     let no_cache_fn_ident = Ident::new(&format!("{}_no_cache", &fn_ident), fn_ident.span());
 
-    let lock;
+    let no_cache_callee = gen_no_cache_callee(&no_cache_fn_ident, in_impl, receiver);
+
+    let mut lock;
     let function_no_cache;
     let function_call;
-    let cache_type;
+    let mut cache_static_ty;
+    let mut cache_static_init;
     if asyncness.is_some() {
         lock = quote! {
             let mut cache = #cache_ident.lock().await;
         };
 
         function_no_cache = quote! {
-            async fn #no_cache_fn_ident(#inputs) #output #body
+            async fn #no_cache_fn_ident #generics (#inputs) #output #where_clause #body
         };
 
         function_call = quote! {
-            let result = #no_cache_fn_ident(#(#input_names),*).await;
+            let result = #no_cache_callee(#(#input_names),*).await;
         };
 
-        cache_type = quote! {
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<::cached::async_sync::Mutex<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| ::cached::async_sync::Mutex::new(#cache_create));
+        cache_static_ty = quote! {
+            ::cached::once_cell::sync::Lazy<::cached::async_sync::Mutex<#cache_ty>>
+        };
+        cache_static_init = quote! {
+            ::cached::once_cell::sync::Lazy::new(|| {
+                #reaper
+                ::cached::async_sync::Mutex::new(#cache_create)
+            })
         };
     } else {
         lock = quote! {
@@ -219,16 +577,165 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
         };
 
         function_no_cache = quote! {
-            fn #no_cache_fn_ident(#inputs) #output #body
+            fn #no_cache_fn_ident #generics (#inputs) #output #where_clause #body
         };
 
         function_call = quote! {
-            let result = #no_cache_fn_ident(#(#input_names),*);
+            let result = #no_cache_callee(#(#input_names),*);
         };
 
-        cache_type = quote! {
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<std::sync::Mutex<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| std::sync::Mutex::new(#cache_create));
+        cache_static_ty = quote! {
+            ::cached::once_cell::sync::Lazy<std::sync::Mutex<#cache_ty>>
+        };
+        cache_static_init = quote! {
+            ::cached::once_cell::sync::Lazy::new(|| {
+                #reaper
+                std::sync::Mutex::new(#cache_create)
+            })
+        };
+    }
+
+    // a stale value is returned right away while a single refresh per key
+    // runs on a thread, or a tokio task for async functions
+    let mut revalidating_type = quote! {};
+    let cache_get_block = match (
+        args.stale_while_revalidate,
+        args.serve_stale_on_error,
+        &fresh_lifespan,
+    ) {
+        (Some(_), _, Some(fresh_lifespan)) => {
+            let revalidating_ident =
+                Ident::new(&format!("{}_REVALIDATING", cache_ident), cache_ident.span());
+            revalidating_type = quote! {
+                static #revalidating_ident: ::cached::once_cell::sync::Lazy<std::sync::Mutex<std::collections::HashSet<#cache_key_ty>>> = ::cached::once_cell::sync::Lazy::new(|| std::sync::Mutex::new(std::collections::HashSet::new()));
+            };
+            // the key is released by a guard created before the call, so a
+            // refresh that panics doesn't keep the key from being refreshed again
+            let revalidating_guard = quote! {
+                struct Revalidating(Option<#cache_key_ty>);
+                impl Drop for Revalidating {
+                    fn drop(&mut self) {
+                        if let Some(key) = self.0.take() {
+                            #revalidating_ident.lock().unwrap().remove(&key);
+                        }
+                    }
+                }
+                let revalidating = Revalidating(Some(key.clone()));
+            };
+            let refresh = if asyncness.is_some() {
+                quote! {
+                    ::cached::async_sync::spawn(async move {
+                        #revalidating_guard
+                        #function_call
+                        let mut cache = #cache_ident.lock().await;
+                        drop(revalidating);
+                        #set_cache_block
+                    });
+                }
+            } else {
+                quote! {
+                    std::thread::spawn(move || {
+                        #revalidating_guard
+                        #function_call
+                        let mut cache = #cache_ident.lock().unwrap();
+                        drop(revalidating);
+                        #set_cache_block
+                    });
+                }
+            };
+            quote! {
+                if let Some((result, age)) = cache.cache_get_with_age(&key) {
+                    if age >= #fresh_lifespan && #revalidating_ident.lock().unwrap().insert(key.clone()) {
+                        #refresh
+                    }
+                    #return_cache_block
+                }
+            }
+        }
+        // a stale value is kept aside in case the function fails
+        (None, Some(_), Some(fresh_lifespan)) => quote! {
+            if let Some((result, age)) = cache.cache_get_with_age(&key) {
+                if age < #fresh_lifespan {
+                    #return_cache_block
+                }
+                stale = Some(result.clone());
+            }
+        },
+        _ => quote! {
+            if let Some(result) = cache.cache_get(&key) {
+                #return_cache_block
+            }
+        },
+    };
+    // a cached error is returned when no value is cached
+    let cache_get_block = match &errors_ty {
+        Some(_) => quote! {
+            #cache_get_block
+            if let Some(error) = ::cached::Cached::cache_get(&mut *#errors_ident.lock().unwrap(), &key) {
+                return Err(error.clone());
+            }
+        },
+        None => cache_get_block,
+    };
+    let (stale_decl, stale_fallback) = match args.serve_stale_on_error {
+        Some(_) => {
+            let return_stale_block = if args.with_cached_flag {
+                quote! { let mut r = result; r.was_cached = true; r.was_stale = true; return Ok(r) }
+            } else {
+                quote! { return Ok(result) }
+            };
+            (
+                quote! { let mut stale = None; },
+                quote! {
+                    let result = match (result, stale) {
+                        (Err(_), Some(result)) => { #return_stale_block }
+                        (result, _) => result,
+                    };
+                },
+            )
+        }
+        None => (quote! {}, quote! {}),
+    };
+    let call_or_stale = quote! {
+        #function_call
+        #stale_fallback
+    };
+
+    // concurrent misses on a key wait on the lock of that key
+    let key_locks_ident = Ident::new(&format!("{}_KEY_LOCKS", cache_ident), cache_ident.span());
+    let key_locks_type = match (args.sync_writes, asyncness.is_some()) {
+        (SyncWrites::ByKey, false) => quote! {
+            static #key_locks_ident: ::cached::once_cell::sync::Lazy<::cached::KeyLocks<#cache_key_ty>> = ::cached::once_cell::sync::Lazy::new(::cached::KeyLocks::new);
+        },
+        (SyncWrites::ByKey, true) => quote! {
+            static #key_locks_ident: ::cached::once_cell::sync::Lazy<::cached::AsyncKeyLocks<#cache_key_ty>> = ::cached::once_cell::sync::Lazy::new(::cached::AsyncKeyLocks::new);
+        },
+        _ => quote! {},
+    };
+
+    // a sharded cache locks internally, only the shard holding
+    // the key is locked when writes are synchronized
+    let mut cache_trait = quote! { cached::Cached };
+    if let Some(shards) = args.shards {
+        cache_static_ty = quote! {
+            ::cached::once_cell::sync::Lazy<cached::ShardedCache<#cache_ty>>
+        };
+        cache_static_init = quote! {
+            ::cached::once_cell::sync::Lazy::new(|| {
+                #reaper
+                cached::ShardedCache::with_shards(#shards, || #cache_create)
+            })
         };
+        if args.sync_writes == SyncWrites::Cache {
+            lock = quote! {
+                let mut cache = #cache_ident.shard(&key);
+            };
+        } else {
+            lock = quote! {
+                let cache = &*#cache_ident;
+            };
+            cache_trait = quote! { cached::ConcurrentCached };
+        }
     }
 
     let prime_do_set_return_block = quote! {
@@ -239,27 +746,48 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
         #set_cache_and_return
     };
 
-    let do_set_return_block = if args.sync_writes {
-        quote! {
+    let do_set_return_block = match args.sync_writes {
+        SyncWrites::Cache => quote! {
+            #stale_decl
             #lock
-            if let Some(result) = cache.cache_get(&key) {
-                #return_cache_block
-            }
-            #function_call
+            #cache_get_block
+            #call_or_stale
             #set_cache_and_return
+        },
+        SyncWrites::ByKey => {
+            // check again once the key is locked, the value may have
+            // been set by the caller that held the lock
+            let key_lock = if asyncness.is_some() {
+                quote! { let _key_lock = #key_locks_ident.lock(&key).await; }
+            } else {
+                quote! { let _key_lock = #key_locks_ident.lock(&key); }
+            };
+            quote! {
+                #stale_decl
+                {
+                    #lock
+                    #cache_get_block
+                }
+                #key_lock
+                {
+                    #lock
+                    #cache_get_block
+                }
+                #call_or_stale
+                #lock
+                #set_cache_and_return
+            }
         }
-    } else {
-        quote! {
+        SyncWrites::Disabled => quote! {
+            #stale_decl
             {
                 #lock
-                if let Some(result) = cache.cache_get(&key) {
-                    #return_cache_block
-                }
+                #cache_get_block
             }
-            #function_call
+            #call_or_stale
             #lock
             #set_cache_and_return
-        }
+        },
     };
 
     let signature_no_muts = get_mut_signature(signature);
@@ -269,28 +797,142 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
     let mut prime_sig = signature_no_muts.clone();
     prime_sig.ident = prime_fn_ident;
 
+    // create signatures for the invalidation and inspection functions, which
+    // take the same arguments to build the key, clearing only needs the cache
+    let remove_fn_ident = Ident::new(&format!("{}_cache_remove", &fn_ident), fn_ident.span());
+    let remove_sig = gen_helper_signature(
+        &signature_no_muts,
+        remove_fn_ident,
+        parse_quote! { -> Option<#cache_value_ty> },
+    );
+    let contains_fn_ident = Ident::new(&format!("{}_cache_contains", &fn_ident), fn_ident.span());
+    let contains_sig = gen_helper_signature(
+        &signature_no_muts,
+        contains_fn_ident,
+        parse_quote! { -> bool },
+    );
+    let clear_fn_ident = Ident::new(&format!("{}_cache_clear", &fn_ident), fn_ident.span());
+    let clear_sig = match &args.cache_field {
+        Some(_) => quote! { #asyncness fn #clear_fn_ident(&self) },
+        None => quote! { #asyncness fn #clear_fn_ident() },
+    };
+    let (errors_remove, errors_clear) = match &errors_ty {
+        Some(_) => (
+            quote! { ::cached::Cached::cache_remove(&mut *#errors_ident.lock().unwrap(), &key); },
+            quote! { ::cached::Cached::cache_clear(&mut *#errors_ident.lock().unwrap()); },
+        ),
+        None => (quote! {}, quote! {}),
+    };
+    let clear_block = if args.shards.is_some() {
+        quote! {
+            use cached::ConcurrentCached;
+            #cache_ident.cache_clear();
+            #errors_clear
+        }
+    } else {
+        quote! {
+            use #cache_trait;
+            #lock
+            cache.cache_clear();
+            #errors_clear
+        }
+    };
+
     // make cached static, cached function and prime cached function doc comments
-    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
-    let no_cache_fn_indent_doc = format!("Origin of the cached function [`{}`].", fn_ident);
-    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
-    let cache_fn_doc_extra = format!(
-        "This is a cached function that uses the [`{}`] cached static.",
-        cache_ident
+    let fn_path = doc_path(&fn_ident, in_impl);
+    let cache_path = doc_path(&cache_ident, in_impl);
+    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_path);
+    let errors_ident_doc = format!("Cached errors of the [`{}`] function.", fn_path);
+    let no_cache_fn_indent_doc = format!("Origin of the cached function [`{}`].", fn_path);
+    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_path);
+    let remove_fn_doc = format!(
+        "Removes the value cached by [`{}`] for these arguments and returns it.",
+        fn_path
     );
+    let clear_fn_doc = format!("Removes all values cached by [`{}`].", fn_path);
+    let contains_fn_doc = format!(
+        "Returns whether [`{}`] has a value cached for these arguments.",
+        fn_path
+    );
+    let cache_fn_doc_extra = match &args.cache_field {
+        Some(field) => format!(
+            "This is a cached method that uses the cache in the `{}` field.",
+            quote! { #field }
+        ),
+        None => format!(
+            "This is a cached function that uses the [`{}`] cached static.",
+            cache_path
+        ),
+    };
     fill_in_attributes(&mut attributes, cache_fn_doc_extra);
 
+    // the cache and helper statics are declared inside functions in `impl` blocks
+    let mut cache_binding = gen_cache_binding(&cache_ident, in_impl, &args.cache_field);
+    let errors_item = match &errors_ty {
+        Some((errors_ty, errors_create)) => {
+            let errors_static_ty =
+                quote! { ::cached::once_cell::sync::Lazy<std::sync::Mutex<#errors_ty>> };
+            let errors_static_init = quote! {
+                ::cached::once_cell::sync::Lazy::new(|| std::sync::Mutex::new(#errors_create))
+            };
+            let errors_binding = gen_cache_binding(&errors_ident, in_impl, &None);
+            cache_binding = quote! { #cache_binding #errors_binding };
+            if in_impl {
+                quote! {
+                    #[doc = #errors_ident_doc]
+                    #[allow(non_snake_case)]
+                    #visibility fn #errors_ident() -> &'static #errors_static_ty {
+                        static #errors_ident: #errors_static_ty = #errors_static_init;
+                        &#errors_ident
+                    }
+                }
+            } else {
+                quote! {
+                    #[doc = #errors_ident_doc]
+                    #visibility static #errors_ident: #errors_static_ty = #errors_static_init;
+                }
+            }
+        }
+        None => quote! {},
+    };
+    let (cache_item, fn_statics) = match (&args.cache_field, in_impl) {
+        (Some(_), _) => (quote! {}, quote! { #revalidating_type #key_locks_type }),
+        (None, true) => (
+            quote! {
+                #[doc = #cache_ident_doc]
+                #[allow(non_snake_case)]
+                #visibility fn #cache_ident() -> &'static #cache_static_ty {
+                    static #cache_ident: #cache_static_ty = #cache_static_init;
+                    &#cache_ident
+                }
+            },
+            quote! { #revalidating_type #key_locks_type },
+        ),
+        (None, false) => (
+            quote! {
+                #[doc = #cache_ident_doc]
+                #visibility static #cache_ident: #cache_static_ty = #cache_static_init;
+                #revalidating_type
+                #key_locks_type
+            },
+            quote! {},
+        ),
+    };
+
     // put it all together
     let expanded = quote! {
         // Cached static
-        #[doc = #cache_ident_doc]
-        #cache_type
+        #cache_item
+        #errors_item
         // No cache function (origin of the cached function)
         #[doc = #no_cache_fn_indent_doc]
         #visibility #function_no_cache
         // Cached function
         #(#attributes)*
         #visibility #signature_no_muts {
-            use cached::Cached;
+            use #cache_trait;
+            #fn_statics
+            #cache_binding
             let key = #key_convert_block;
             #do_set_return_block
         }
@@ -299,11 +941,38 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
         #[allow(dead_code)]
         #(#attributes)*
         #visibility #prime_sig {
-            use cached::Cached;
+            use #cache_trait;
+            #cache_binding
             let key = #key_convert_block;
             #prime_do_set_return_block
         }
+        // Cache invalidation and inspection functions
+        #[doc = #remove_fn_doc]
+        #[allow(dead_code, unused_variables)]
+        #visibility #remove_sig {
+            use #cache_trait;
+            #cache_binding
+            let key = #key_convert_block;
+            #errors_remove
+            #lock
+            cache.cache_remove(&key)
+        }
+        #[doc = #clear_fn_doc]
+        #[allow(dead_code)]
+        #visibility #clear_sig {
+            #cache_binding
+            #clear_block
+        }
+        #[doc = #contains_fn_doc]
+        #[allow(dead_code, unused_variables)]
+        #visibility #contains_sig {
+            use #cache_trait;
+            #cache_binding
+            let key = #key_convert_block;
+            #lock
+            cache.cache_contains(&key)
+        }
     };
 
-    expanded.into()
+    Ok(expanded.into())
 }
@@ -1,15 +1,124 @@
+use darling::ast::NestedMeta;
+use darling::FromMeta;
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
+use proc_macro2::TokenTree as TokenTree2;
 use quote::__private::Span;
-use quote::quote;
+use quote::{quote, ToTokens};
+use std::fmt::Display;
 use std::ops::Deref;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream, Parser};
 use syn::punctuated::Punctuated;
 use syn::token::Comma;
 use syn::{
-    parse_quote, parse_str, Attribute, Block, FnArg, Pat, PatType, PathArguments, ReturnType,
-    Signature, Type,
+    parse_quote, Attribute, Block, Expr, ExprLit, FnArg, GenericArgument, GenericParam, Generics,
+    Ident, Lit, Member, Meta, MetaNameValue, Pat, PatType, Path, PathArguments, ReturnType,
+    Signature, Token, Type,
 };
 
+// an attribute value written as Rust syntax, e.g. `key = String`, or as a string literal
+// containing it, e.g. `key = "String"`, which is what earlier versions required
+pub(super) struct Syntax<T>(T);
+
+impl<T: Parse> FromMeta for Syntax<T> {
+    fn from_expr(expr: &Expr) -> darling::Result<Self> {
+        let parsed = match expr {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(lit), ..
+            }) => lit.parse(),
+            Expr::Group(group) => return Self::from_expr(&group.expr),
+            _ => syn::parse2(expr.to_token_stream()),
+        };
+        parsed.map(Syntax).map_err(darling::Error::from)
+    }
+}
+
+impl<T> Deref for Syntax<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ToTokens> ToTokens for Syntax<T> {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        self.0.to_tokens(tokens)
+    }
+}
+
+impl Syntax<Block> {
+    // the statements of the block in braces of the macro's own, blocks written as Rust
+    // syntax would otherwise trip the `unused_braces` lint where they're used as expressions
+    pub(super) fn to_expr(&self) -> TokenStream2 {
+        let stmts = &self.0.stmts;
+        quote! { { #(#stmts)* } }
+    }
+}
+
+// a single `name` or `name = value` attribute argument
+struct AttrArg(NestedMeta);
+
+impl Parse for AttrArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        // argument names can be keywords, such as `type`
+        let path = Path::from(input.call(Ident::parse_any)?);
+        if !input.peek(Token![=]) {
+            return Ok(AttrArg(NestedMeta::Meta(Meta::Path(path))));
+        }
+        let eq_token = input.parse()?;
+        Ok(AttrArg(NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+            path,
+            eq_token,
+            value: parse_attr_value(input)?,
+        }))))
+    }
+}
+
+// values are kept as verbatim tokens when they parse as a type, since types like
+// `SizedCache<(u32, u32), u32>` aren't expressions or would be misread as comparisons,
+// `Syntax<T>` parses them again into whatever it holds. Other values are expressions.
+fn parse_attr_value(input: ParseStream) -> syn::Result<Expr> {
+    let fork = input.fork();
+    if fork.parse::<Type>().is_ok() && (fork.is_empty() || fork.peek(Token![,])) {
+        let ty = input.parse::<Type>()?;
+        return Ok(Expr::Verbatim(quote! { #ty }));
+    }
+    input.parse()
+}
+
+// the arguments of a macro attribute, so that errors can point at the argument they're about
+pub(super) struct ArgSpans(Vec<Meta>);
+
+impl ArgSpans {
+    pub(super) fn new(args: &[NestedMeta]) -> Self {
+        ArgSpans(
+            args.iter()
+                .filter_map(|arg| match arg {
+                    NestedMeta::Meta(meta) => Some(meta.clone()),
+                    NestedMeta::Lit(_) => None,
+                })
+                .collect(),
+        )
+    }
+
+    // an error pointing at the argument `name`, or at the whole attribute if it isn't given
+    pub(super) fn error(&self, name: &str, message: impl Display) -> syn::Error {
+        match self.0.iter().find(|meta| meta.path().is_ident(name)) {
+            Some(meta) => syn::Error::new_spanned(meta, message),
+            None => syn::Error::new(Span::call_site(), message),
+        }
+    }
+}
+
+// parse the arguments of a macro attribute for darling
+pub(super) fn parse_attr_args(args: TokenStream) -> syn::Result<Vec<NestedMeta>> {
+    Punctuated::<AttrArg, Comma>::parse_terminated
+        .parse(args)
+        .map(|args| args.into_iter().map(|arg| arg.0).collect())
+}
+
 // if you define arguments as mutable, e.g.
 // #[cached]
 // fn mutable_args(mut a: i32, mut b: i32) -> (i32, i32) {
@@ -61,66 +170,72 @@ pub(super) fn match_pattern_type(pat_type: &&PatType) -> Box<Pat> {
 // for Options and Results it's the (first) inner type. So for
 // Option<u32>, store u32, for Result<i32, String>, store i32, etc.
 pub(super) fn find_value_type(
+    spans: &ArgSpans,
     result: bool,
     option: bool,
     output: &ReturnType,
     output_ty: TokenStream2,
-) -> TokenStream2 {
-    match (result, option) {
-        (false, false) => output_ty,
-        (true, true) => panic!("the result and option attributes are mutually exclusive"),
-        _ => match output.clone() {
-            ReturnType::Default => {
-                panic!("function must return something for result or option attributes")
-            }
-            ReturnType::Type(_, ty) => {
-                if let Type::Path(typepath) = *ty {
-                    let segments = typepath.path.segments;
-                    if let PathArguments::AngleBracketed(brackets) =
-                        &segments.last().unwrap().arguments
-                    {
-                        let inner_ty = brackets.args.first().unwrap();
-                        quote! {#inner_ty}
-                    } else {
-                        panic!("function return type has no inner type")
-                    }
-                } else {
-                    panic!("function return type too complex")
-                }
+) -> syn::Result<TokenStream2> {
+    let arg = match (result, option) {
+        (false, false) => return Ok(output_ty),
+        (true, true) => {
+            return Err(spans.error(
+                "option",
+                "the result and option attributes are mutually exclusive",
+            ))
+        }
+        (true, false) => "result",
+        (false, true) => "option",
+    };
+    let ty = match output {
+        ReturnType::Default => {
+            return Err(spans.error(
+                arg,
+                format!("`{}` requires the function to return something", arg),
+            ))
+        }
+        ReturnType::Type(_, ty) => ty,
+    };
+    match ty.as_ref() {
+        Type::Path(typepath) => match typepath.path.segments.last().map(|s| &s.arguments) {
+            Some(PathArguments::AngleBracketed(brackets)) if !brackets.args.is_empty() => {
+                let inner_ty = &brackets.args[0];
+                Ok(quote! {#inner_ty})
             }
+            _ => Err(syn::Error::new_spanned(
+                ty,
+                format!("`{}` requires a return type with an inner type", arg),
+            )),
         },
+        _ => Err(syn::Error::new_spanned(
+            ty,
+            format!("`{}` requires a `Result` or `Option` return type", arg),
+        )),
     }
 }
 
 // make the cache key type and block that converts the inputs into the key type
 pub(super) fn make_cache_key_type(
-    key: &Option<String>,
-    convert: &Option<String>,
-    cache_type: &Option<String>,
+    spans: &ArgSpans,
+    key: &Option<Syntax<Type>>,
+    convert: &Option<Syntax<Block>>,
+    cache_type: &Option<Syntax<Type>>,
     input_tys: Vec<Type>,
     input_names: &Vec<Pat>,
-) -> (TokenStream2, TokenStream2) {
+) -> syn::Result<(TokenStream2, TokenStream2)> {
     match (key, convert, cache_type) {
-        (Some(key_str), Some(convert_str), _) => {
-            let cache_key_ty = parse_str::<Type>(key_str).expect("unable to parse cache key type");
-
-            let key_convert_block =
-                parse_str::<Block>(convert_str).expect("unable to parse key convert block");
-
-            (quote! {#cache_key_ty}, quote! {#key_convert_block})
-        }
-        (None, Some(convert_str), Some(_)) => {
-            let key_convert_block =
-                parse_str::<Block>(convert_str).expect("unable to parse key convert block");
-
-            (quote! {}, quote! {#key_convert_block})
+        (Some(cache_key_ty), Some(key_convert_block), _) => {
+            Ok((quote! {#cache_key_ty}, key_convert_block.to_expr()))
         }
-        (None, None, _) => (
+        (None, Some(key_convert_block), Some(_)) => Ok((quote! {}, key_convert_block.to_expr())),
+        (None, None, _) => Ok((
             quote! {(#(#input_tys),*)},
             quote! {(#(#input_names.clone()),*)},
-        ),
-        (Some(_), None, _) => panic!("key requires convert to be set"),
-        (None, Some(_), None) => panic!("convert requires key or type to be set"),
+        )),
+        (Some(_), None, _) => Err(spans.error("key", "`key` requires `convert` to be set")),
+        (None, Some(_), None) => {
+            Err(spans.error("convert", "`convert` requires `key` or `type` to be set"))
+        }
     }
 }
 
@@ -134,18 +249,19 @@ pub(super) fn make_cache_key_type(
 // then we need to strip off the `mut` keyword from the
 // variable identifiers, so we can refer to arguments `a` and `b`
 // instead of `mut a` and `mut b`
+// `self` is left out, it is never part of the default cache key
 pub(super) fn get_input_names(inputs: &Punctuated<FnArg, Comma>) -> Vec<Pat> {
     inputs
         .iter()
-        .map(|input| match input {
-            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
-            FnArg::Typed(pat_type) => *match_pattern_type(&pat_type),
+        .filter_map(|input| match input {
+            FnArg::Receiver(_) => None,
+            FnArg::Typed(pat_type) => Some(*match_pattern_type(&pat_type)),
         })
         .collect()
 }
 
 pub(super) fn fill_in_attributes(attributes: &mut Vec<Attribute>, cache_fn_doc_extra: String) {
-    if attributes.iter().any(|attr| attr.path.is_ident("doc")) {
+    if attributes.iter().any(|attr| attr.path().is_ident("doc")) {
         attributes.push(parse_quote! { #[doc = ""] });
         attributes.push(parse_quote! { #[doc = "# Caching"] });
         attributes.push(parse_quote! { #[doc = #cache_fn_doc_extra] });
@@ -158,9 +274,9 @@ pub(super) fn fill_in_attributes(attributes: &mut Vec<Attribute>, cache_fn_doc_e
 pub(super) fn get_input_types(inputs: &Punctuated<FnArg, Comma>) -> Vec<Type> {
     inputs
         .iter()
-        .map(|input| match input {
-            FnArg::Receiver(_) => panic!("methods (functions taking 'self') are not supported"),
-            FnArg::Typed(pat_type) => *pat_type.ty.clone(),
+        .filter_map(|input| match input {
+            FnArg::Receiver(_) => None,
+            FnArg::Typed(pat_type) => Some(*pat_type.ty.clone()),
         })
         .collect()
 }
@@ -176,7 +292,7 @@ pub(super) fn get_output_parts(output_ts: &TokenStream) -> Vec<String> {
         .collect()
 }
 
-pub(super) fn with_cache_flag_error(output_span: Span, output_type_display: String) -> TokenStream {
+pub(super) fn with_cache_flag_error(output_span: Span, output_type_display: String) -> syn::Error {
     syn::Error::new(
         output_span,
         format!(
@@ -190,18 +306,35 @@ pub(super) fn with_cache_flag_error(output_span: Span, output_type_display: Stri
             t = output_type_display
         ),
     )
-    .to_compile_error()
-    .into()
 }
 
-pub(super) fn gen_return_cache_block(
+// make a `Duration` expression from the `time` (seconds) or `time_ms` (milliseconds) attributes
+pub(super) fn make_lifespan(
+    spans: &ArgSpans,
     time: Option<u64>,
+    time_ms: Option<u64>,
+) -> syn::Result<Option<TokenStream2>> {
+    match (time, time_ms) {
+        (Some(_), Some(_)) => Err(spans.error(
+            "time_ms",
+            "the time and time_ms attributes are mutually exclusive",
+        )),
+        (Some(time), None) => Ok(Some(quote! { ::std::time::Duration::from_secs(#time) })),
+        (None, Some(time_ms)) => Ok(Some(
+            quote! { ::std::time::Duration::from_millis(#time_ms) },
+        )),
+        (None, None) => Ok(None),
+    }
+}
+
+pub(super) fn gen_return_cache_block(
+    lifespan: Option<&TokenStream2>,
     return_cache_block: TokenStream2,
 ) -> TokenStream2 {
-    if let Some(time) = &time {
+    if let Some(lifespan) = lifespan {
         quote! {
             let (created_sec, result) = result;
-            if now.duration_since(*created_sec).as_secs() < #time {
+            if now.duration_since(*created_sec) < #lifespan {
                 #return_cache_block
             }
         }
@@ -210,6 +343,48 @@ pub(super) fn gen_return_cache_block(
     }
 }
 
+// only run `set_cache_block` when the `cache_if` predicate accepts the value, the type
+// of the predicate is given so that closure arguments don't need to be annotated
+pub(super) fn gen_cache_if_block(
+    cache_if: &Option<Syntax<Expr>>,
+    cache_value_ty: &TokenStream2,
+    value: TokenStream2,
+    set_cache_block: TokenStream2,
+) -> TokenStream2 {
+    match cache_if {
+        Some(cache_if) => {
+            quote! {
+                let cache_if: &dyn Fn(&#cache_value_ty) -> bool = &(#cache_if);
+                if cache_if(#value) {
+                    #set_cache_block
+                }
+            }
+        }
+        None => set_cache_block,
+    }
+}
+
+// set the value in the cache, with the lifespan `ttl_from` returns for it if set. Stores
+// used with `ttl_from` provide a `cache_set_with_ttl` method taking a `Duration`.
+pub(super) fn gen_cache_set(
+    ttl_from: &Option<Syntax<Expr>>,
+    cache_value_ty: &TokenStream2,
+    value: TokenStream2,
+    value_ref: TokenStream2,
+) -> TokenStream2 {
+    match ttl_from {
+        Some(ttl_from) => {
+            quote! {
+                cache.cache_set_with_ttl(key, #value, {
+                    let ttl_from: &dyn Fn(&#cache_value_ty) -> ::std::time::Duration = &(#ttl_from);
+                    ttl_from(#value_ref)
+                })
+            }
+        }
+        None => quote! { cache.cache_set(key, #value) },
+    }
+}
+
 // if `with_cached_flag = true`, then enforce that the return type
 // is something wrapped in `Return`. Either `Return<T>` or the
 // fully qualified `cached::Return<T>`
@@ -218,3 +393,236 @@ pub(super) fn check_with_cache_flag(with_cached_flag: bool, output_string: Strin
         && !output_string.contains("Return")
         && !output_string.contains("cached::Return")
 }
+
+// whether the function is a method taking `self`
+pub(super) fn has_receiver(inputs: &Punctuated<FnArg, Comma>) -> bool {
+    inputs
+        .iter()
+        .any(|input| matches!(input, FnArg::Receiver(_)))
+}
+
+// methods must say what happens to `self`, it is not part of the default cache key.
+// `convert` is `None` for `#[once]`, which has no key to build from `self`
+pub(super) fn check_receiver_key(
+    spans: &ArgSpans,
+    inputs: &Punctuated<FnArg, Comma>,
+    ignore_self: bool,
+    convert: Option<&Option<Syntax<Block>>>,
+    cache_field: &Option<Syntax<Member>>,
+) -> syn::Result<()> {
+    let receiver = inputs.iter().find_map(|input| match input {
+        FnArg::Receiver(receiver) => Some(receiver),
+        FnArg::Typed(_) => None,
+    });
+    let keyed = matches!(convert, Some(Some(_)));
+    match receiver {
+        Some(receiver) if !ignore_self && !keyed && cache_field.is_none() => {
+            let alternatives = if convert.is_some() {
+                "a `convert` block keying on fields of `self`, or a per-instance `cache_field`"
+            } else {
+                "or a per-instance `cache_field`"
+            };
+            Err(syn::Error::new_spanned(
+                receiver,
+                format!(
+                    "methods taking `self` require `ignore_self = true` to share one cache between all instances, {}",
+                    alternatives
+                ),
+            ))
+        }
+        None if ignore_self => Err(spans.error(
+            "ignore_self",
+            "`ignore_self` requires a method taking `self`",
+        )),
+        None if cache_field.is_some() => Err(spans.error(
+            "cache_field",
+            "`cache_field` requires a method taking `self`",
+        )),
+        _ => Ok(()),
+    }
+}
+
+// the path used to call the original function, which is an associated
+// function or a method when the cached function lives in an `impl` block
+pub(super) fn gen_no_cache_callee(
+    no_cache_fn_ident: &Ident,
+    in_impl: bool,
+    receiver: bool,
+) -> TokenStream2 {
+    if receiver {
+        quote! { self.#no_cache_fn_ident }
+    } else if in_impl {
+        quote! { Self::#no_cache_fn_ident }
+    } else {
+        quote! { #no_cache_fn_ident }
+    }
+}
+
+// statics can't be declared in `impl` blocks, so the cache is instead declared inside an
+// associated function of the same name, or read from a field of `self`. Generated functions
+// bind it to a local named like the static, so they can refer to it the same way.
+pub(super) fn gen_cache_binding(
+    cache_ident: &Ident,
+    in_impl: bool,
+    cache_field: &Option<Syntax<Member>>,
+) -> TokenStream2 {
+    match cache_field {
+        Some(field) => {
+            quote! {
+                #[allow(non_snake_case)]
+                let #cache_ident = &self.#field;
+            }
+        }
+        None if in_impl => quote! {
+            #[allow(non_snake_case)]
+            let #cache_ident = Self::#cache_ident();
+        },
+        None => quote! {},
+    }
+}
+
+// the path used in doc links to generated items
+pub(super) fn doc_path(ident: &Ident, in_impl: bool) -> String {
+    if in_impl {
+        format!("Self::{}", ident)
+    } else {
+        ident.to_string()
+    }
+}
+
+// whether any of the tokens, or tokens nested in groups, is one of `idents`
+fn mentions_ident(tokens: TokenStream2, idents: &[String]) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree2::Ident(ident) => idents.contains(&ident.to_string()),
+        TokenTree2::Group(group) => mentions_ident(group.stream(), idents),
+        _ => false,
+    })
+}
+
+// the cache static can't name the generic parameters of a function, arguments of generic
+// types (or `impl Trait`) must be converted into a concrete key type, and the cached value
+// type can't depend on the parameters
+pub(super) fn check_generics(
+    generics: &Generics,
+    inputs: &Punctuated<FnArg, Comma>,
+    keyed: bool,
+    convert: &Option<Syntax<Block>>,
+    cache_value_ty: &TokenStream2,
+) -> syn::Result<()> {
+    let params = generic_param_names(generics);
+    let impl_trait_arg = get_input_types(inputs)
+        .into_iter()
+        .find(|ty| mentions_ident(quote! { #ty }, &["impl".to_string()]));
+
+    if keyed && convert.is_none() {
+        let message = "generic functions require `key` and `convert` to be set, \
+            to convert the arguments into a cache key of a concrete type";
+        if !params.is_empty() {
+            return Err(syn::Error::new_spanned(&generics.params, message));
+        }
+        if let Some(ty) = impl_trait_arg {
+            return Err(syn::Error::new_spanned(ty, message));
+        }
+    }
+    if mentions_ident(cache_value_ty.clone(), &params) {
+        return Err(syn::Error::new_spanned(
+            cache_value_ty,
+            "the cached value type cannot depend on the generic parameters of the function",
+        ));
+    }
+    Ok(())
+}
+
+// whether a type depends on the generic parameters of the function
+pub(super) fn mentions_generics(generics: &Generics, tokens: TokenStream2) -> bool {
+    mentions_ident(tokens, &generic_param_names(generics))
+}
+
+fn generic_param_names(generics: &Generics) -> Vec<String> {
+    generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(param) => Some(param.ident.to_string()),
+            GenericParam::Const(param) => Some(param.ident.to_string()),
+            GenericParam::Lifetime(_) => None,
+        })
+        .collect()
+}
+
+// the `E` of a `Result<T, E>` return type, cached errors are kept in a
+// static of their own that can't name the generic parameters either
+pub(super) fn find_error_type(
+    spans: &ArgSpans,
+    arg: &str,
+    output: &ReturnType,
+    generics: &Generics,
+) -> syn::Result<TokenStream2> {
+    if let ReturnType::Type(_, ty) = output {
+        if let Type::Path(typepath) = ty.as_ref() {
+            if let Some(PathArguments::AngleBracketed(brackets)) =
+                typepath.path.segments.last().map(|s| &s.arguments)
+            {
+                if let Some(GenericArgument::Type(error_ty)) = brackets.args.iter().nth(1) {
+                    let error_ty = quote! { #error_ty };
+                    if mentions_ident(error_ty.clone(), &generic_param_names(generics)) {
+                        return Err(syn::Error::new_spanned(
+                            error_ty,
+                            "the cached error type cannot depend on the generic parameters of the function",
+                        ));
+                    }
+                    return Ok(error_ty);
+                }
+            }
+        }
+    }
+    Err(spans.error(
+        arg,
+        format!(
+            "`{}` requires a `Result<T, E>` return type naming the error type",
+            arg
+        ),
+    ))
+}
+
+// the signature of a generated function taking the same arguments as the cached function,
+// methods only read the cache and key from `self`, so they take `&self`
+pub(super) fn gen_helper_signature(
+    signature: &Signature,
+    ident: Ident,
+    output: ReturnType,
+) -> Signature {
+    let mut helper_sig = signature.clone();
+    helper_sig.ident = ident;
+    helper_sig.output = output;
+    for input in helper_sig.inputs.iter_mut() {
+        if let FnArg::Receiver(_) = input {
+            *input = parse_quote! { &self };
+        }
+    }
+    helper_sig
+}
+
+// replace the `T` of a `Result<T, E>` return type, keeping the error type
+pub(super) fn replace_ok_type(output: &ReturnType, ok_ty: Type) -> syn::Result<ReturnType> {
+    let mut output = output.clone();
+    if let ReturnType::Type(_, ty) = &mut output {
+        if let Type::Path(typepath) = ty.as_mut() {
+            if let Some(PathArguments::AngleBracketed(brackets)) = typepath
+                .path
+                .segments
+                .last_mut()
+                .map(|segment| &mut segment.arguments)
+            {
+                if let Some(GenericArgument::Type(inner_ty)) = brackets.args.first_mut() {
+                    *inner_ty = ok_ty;
+                    return Ok(output);
+                }
+            }
+        }
+    }
+    Err(syn::Error::new_spanned(
+        output,
+        "#[io_cached] functions must return `Result`s",
+    ))
+}
@@ -4,44 +4,69 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::spanned::Spanned;
 use syn::{
-    parse_macro_input, parse_str, AttributeArgs, Block, ExprClosure, GenericArgument, Ident,
-    ItemFn, PathArguments, ReturnType, Type,
+    parse_quote, Block, Expr, ExprClosure, GenericArgument, Ident, ItemFn, Member, PathArguments,
+    ReturnType, Type,
 };
 
+// the lifespan in seconds of redis values set outside of `ttl_from`, e.g. through
+// the store's own `cache_set`, when `ttl_from` is given without `time` or `time_ms`
+const TTL_FROM_DEFAULT_LIFESPAN: u64 = 3600;
+
 #[derive(FromMeta)]
 struct IOMacroArgs {
-    map_error: String,
+    map_error: Syntax<ExprClosure>,
     #[darling(default)]
     redis: bool,
     #[darling(default)]
-    cache_prefix_block: Option<String>,
+    cache_prefix_block: Option<Syntax<Block>>,
     #[darling(default)]
     name: Option<String>,
     #[darling(default)]
     time: Option<u64>,
     #[darling(default)]
+    time_ms: Option<u64>,
+    #[darling(default)]
     time_refresh: Option<bool>,
     #[darling(default)]
-    key: Option<String>,
+    key: Option<Syntax<Type>>,
+    #[darling(default)]
+    convert: Option<Syntax<Block>>,
+    #[darling(default)]
+    cache_if: Option<Syntax<Expr>>,
+    #[darling(default)]
+    ttl_from: Option<Syntax<Expr>>,
+    #[darling(default)]
+    serve_stale_on_error: Option<u64>,
     #[darling(default)]
-    convert: Option<String>,
+    cache_err_for: Option<u64>,
+    #[darling(default)]
+    evict_undeserializable: bool,
     #[darling(default)]
     with_cached_flag: bool,
+    #[darling(default)]
+    in_impl: bool,
+    #[darling(default)]
+    ignore_self: bool,
+    #[darling(default)]
+    cache_field: Option<Syntax<Member>>,
     #[darling(default, rename = "type")]
-    cache_type: Option<String>,
+    cache_type: Option<Syntax<Type>>,
     #[darling(default, rename = "create")]
-    cache_create: Option<String>,
+    cache_create: Option<Syntax<Block>>,
 }
 
 pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
-    let attr_args = parse_macro_input!(args as AttributeArgs);
-    let args = match IOMacroArgs::from_list(&attr_args) {
-        Ok(v) => v,
-        Err(e) => {
-            return TokenStream::from(e.write_errors());
-        }
-    };
-    let input = parse_macro_input!(input as ItemFn);
+    match expand(args, input) {
+        Ok(expanded) => expanded,
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream> {
+    let attr_args = parse_attr_args(args)?;
+    let spans = ArgSpans::new(&attr_args);
+    let args = IOMacroArgs::from_list(&attr_args)?;
+    let input = syn::parse::<ItemFn>(input)?;
 
     // pull out the parts of the input
     let mut attributes = input.attrs;
@@ -54,11 +79,24 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
     let inputs = signature.inputs.clone();
     let output = signature.output.clone();
     let asyncness = signature.asyncness;
+    let generics = signature.generics.clone();
+    let where_clause = &generics.where_clause;
 
     let input_tys = get_input_types(&inputs);
 
     let input_names = get_input_names(&inputs);
 
+    // functions in `impl` blocks can't declare statics next to them
+    let receiver = has_receiver(&inputs);
+    let in_impl = args.in_impl || receiver;
+    check_receiver_key(
+        &spans,
+        &inputs,
+        args.ignore_self,
+        Some(&args.convert),
+        &args.cache_field,
+    )?;
+
     // pull out the output type
     let output_ty = match &output {
         ReturnType::Default => quote! {()},
@@ -78,7 +116,7 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
         && !output_string.contains("Return")
         && !output_string.contains("cached::Return")
     {
-        return syn::Error::new(
+        return Err(syn::Error::new(
             output_span,
             format!(
                 "\nWhen specifying `with_cached_flag = true`, \
@@ -88,61 +126,47 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
                     Found type: {t}.",
                 t = output_type_display
             ),
-        )
-        .to_compile_error()
-        .into();
+        ));
     }
 
     // Find the type of the value to store.
     // Return type always needs to be a result, so we want the (first) inner type.
     // For Result<i32, String>, store i32, etc.
-    let cache_value_ty = match output.clone() {
+    let first_type_arg = |ty: &Type| match ty {
+        Type::Path(typepath) => match typepath.path.segments.last().map(|s| &s.arguments) {
+            Some(PathArguments::AngleBracketed(brackets)) => match brackets.args.first() {
+                Some(GenericArgument::Type(ty)) => Some(ty.clone()),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    };
+    let result_ty = match &output {
+        ReturnType::Type(_, ty) => ty,
         ReturnType::Default => {
-            panic!(
-                "#[io_cached] functions must return `Result`s, found {:?}",
-                output_type_display
-            );
-        }
-        ReturnType::Type(_, ty) => {
-            if let Type::Path(typepath) = *ty {
-                let segments = typepath.path.segments;
-                if let PathArguments::AngleBracketed(brackets) = &segments.last().unwrap().arguments
-                {
-                    let inner_ty = brackets.args.first().unwrap();
-                    if output_string.contains("Return") || output_string.contains("cached::Return")
-                    {
-                        if let GenericArgument::Type(Type::Path(typepath)) = inner_ty {
-                            let segments = &typepath.path.segments;
-                            if let PathArguments::AngleBracketed(brackets) =
-                                &segments.last().unwrap().arguments
-                            {
-                                let inner_ty = brackets.args.first().unwrap();
-                                quote! {#inner_ty}
-                            } else {
-                                panic!(
-                                    "#[io_cached] unable to determine cache value type, found {:?}",
-                                    output_type_display
-                                );
-                            }
-                        } else {
-                            panic!(
-                                "#[io_cached] unable to determine cache value type, found {:?}",
-                                output_type_display
-                            );
-                        }
-                    } else {
-                        quote! {#inner_ty}
-                    }
-                } else {
-                    panic!("#[io_cached] functions must return `Result`s")
-                }
-            } else {
-                panic!(
-                    "function return type too complex, #[io_cached] functions must return `Result`s"
-                )
-            }
+            return Err(syn::Error::new_spanned(
+                &signature,
+                "#[io_cached] functions must return `Result`s",
+            ))
         }
     };
+    let cache_value_ty = first_type_arg(result_ty).ok_or_else(|| {
+        syn::Error::new_spanned(result_ty, "#[io_cached] functions must return `Result`s")
+    })?;
+    let cache_value_ty = if output_string.contains("Return") {
+        first_type_arg(&cache_value_ty).ok_or_else(|| {
+            syn::Error::new_spanned(
+                &cache_value_ty,
+                "#[io_cached] unable to determine cache value type",
+            )
+        })?
+    } else {
+        cache_value_ty
+    };
+    let cache_value_ty = quote! {#cache_value_ty};
+
+    check_generics(&generics, &inputs, true, &args.convert, &cache_value_ty)?;
 
     // make the cache identifier
     let cache_ident = match args.name {
@@ -151,29 +175,127 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
     };
 
     let (cache_key_ty, key_convert_block) = make_cache_key_type(
+        &spans,
         &args.key,
         &args.convert,
         &args.cache_type,
         input_tys,
         &input_names,
-    );
+    )?;
+
+    let lifespan = make_lifespan(&spans, args.time, args.time_ms)?;
+
+    // make the cache type and create statement, a per-instance
+    // cache is created along with the instance instead
+    if args.cache_field.is_some()
+        && (args.redis
+            || lifespan.is_some()
+            || args.time_refresh.is_some()
+            || args.cache_prefix_block.is_some()
+            || args.cache_type.is_some()
+            || args.cache_create.is_some())
+    {
+        return Err(spans.error(
+            "cache_field",
+            "cache_field cannot be combined with attributes defining the cache store",
+        ));
+    }
+    if args.ttl_from.is_some() && args.time_refresh == Some(true) {
+        return Err(spans.error("ttl_from", "ttl_from cannot be combined with time_refresh"));
+    }
+    // stale values are kept in redis for an extra grace period, their
+    // remaining time to live tells whether they're still fresh
+    let stale_error = |message| Err(spans.error("serve_stale_on_error", message));
+    match args.serve_stale_on_error {
+        Some(0) => return stale_error("serve_stale_on_error must be greater than zero"),
+        Some(_) if !args.redis || args.cache_create.is_some() => {
+            return stale_error("serve_stale_on_error requires a `redis` store without `create`")
+        }
+        Some(_) if lifespan.is_none() => {
+            return stale_error("serve_stale_on_error requires time or time_ms to be set")
+        }
+        Some(_) if args.time_refresh == Some(true) => {
+            return stale_error("serve_stale_on_error and time_refresh are mutually exclusive")
+        }
+        Some(_) if args.ttl_from.is_some() => {
+            return stale_error("serve_stale_on_error and ttl_from are mutually exclusive")
+        }
+        _ => {}
+    }
+    if args.evict_undeserializable && (!args.redis || args.cache_create.is_some()) {
+        return Err(spans.error(
+            "evict_undeserializable",
+            "evict_undeserializable requires a `redis` store without `create`",
+        ));
+    }
+    let set_evict = if args.evict_undeserializable {
+        Some(quote! { .set_evict_undeserializable(true) })
+    } else {
+        None
+    };
+    // the default prefixes end with a delimiter so the prefix-wide `SCAN MATCH {prefix}*`
+    // of a function doesn't include the keys of functions whose name starts with its name
+    let (redis_prefix, errors_prefix) = match &args.cache_prefix_block {
+        Some(cp) => {
+            let cp = cp.to_expr();
+            (quote! { #cp }, quote! { format!("errors:{}", #cp) })
+        }
+        None => {
+            let cp = format!("cached::proc_macro::io_cached::{}:", cache_ident);
+            let ep = format!("cached::proc_macro::io_cached::errors::{}:", cache_ident);
+            (quote! { { #cp } }, quote! { #ep })
+        }
+    };
 
-    // make the cache type and create statement
+    // errors are cached for a lifespan of their own under a sibling redis prefix, outside of
+    // the keys matched by `{prefix}*`
+    let err_error = |message| Err(spans.error("cache_err_for", message));
+    let errors_ty = match args.cache_err_for {
+        Some(0) => return err_error("cache_err_for must be greater than zero"),
+        Some(_) if !args.redis || args.cache_create.is_some() => {
+            return err_error("cache_err_for requires a `redis` store without `create`")
+        }
+        Some(_) if args.serve_stale_on_error.is_some() => {
+            return err_error("cache_err_for and serve_stale_on_error are mutually exclusive")
+        }
+        Some(_) if cache_key_ty.is_empty() => {
+            return err_error("cache_err_for requires `key` to be set when `type` is used")
+        }
+        Some(secs) => {
+            let error_ty = find_error_type(&spans, "cache_err_for", &output, &generics)?;
+            Some(if asyncness.is_some() {
+                (
+                    quote! { cached::AsyncRedisCache<#cache_key_ty, #error_ty> },
+                    quote! { cached::AsyncRedisCache::new(#errors_prefix, #secs)#set_evict.build().await.expect("error constructing AsyncRedisCache in #[io_cached] macro") },
+                )
+            } else {
+                (
+                    quote! { cached::RedisCache<#cache_key_ty, #error_ty> },
+                    quote! { cached::RedisCache::new(#errors_prefix, #secs)#set_evict.build().expect("error constructing RedisCache in #[io_cached] macro") },
+                )
+            })
+        }
+        None => None,
+    };
+    let create_error = || {
+        Err(spans.error(
+            "create",
+            "cannot specify `time`, `time_ms`, `time_refresh`, or `cache_prefix_block` when passing `create` block",
+        ))
+    };
     let (cache_ty, cache_create) = match (
+        &args.cache_field,
         &args.redis,
-        &args.time,
+        &lifespan,
         &args.time_refresh,
         &args.cache_prefix_block,
         &args.cache_type,
         &args.cache_create,
     ) {
-        (true, time, time_refresh, cache_prefix, cache_type, cache_create) => {
+        (Some(_), ..) => (quote! {}, quote! {}),
+        (None, true, time, time_refresh, cache_prefix, cache_type, cache_create) => {
             let cache_ty = match cache_type {
-                Some(cache_type) => {
-                    let cache_type =
-                        parse_str::<Type>(cache_type).expect("unable to parse cache type");
-                    quote! { #cache_type }
-                }
+                Some(cache_type) => quote! { #cache_type },
                 None => {
                     if asyncness.is_some() {
                         quote! { cached::AsyncRedisCache<#cache_key_ty, #cache_value_ty> }
@@ -185,46 +307,50 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
             let cache_create = match cache_create {
                 Some(cache_create) => {
                     if time.is_some() || time_refresh.is_some() || cache_prefix.is_some() {
-                        panic!("cannot specify `time`, `time_refresh`, or `cache_prefix` when passing `create block");
+                        return create_error();
                     } else {
-                        let cache_create = parse_str::<Block>(cache_create.as_ref())
-                            .expect("unable to parse cache create block");
-                        quote! { #cache_create }
+                        cache_create.to_expr()
                     }
                 }
                 None => {
-                    if time.is_none() {
-                        if asyncness.is_some() {
-                            panic!("AsyncRedisCache requires a `time` when `create` block is not specified")
+                    if time.is_none() && args.ttl_from.is_none() {
+                        let store = if asyncness.is_some() {
+                            "AsyncRedisCache"
                         } else {
-                            panic!(
-                                "RedisCache requires a `time` when `create` block is not specified"
-                            )
+                            "RedisCache"
                         };
+                        return Err(spans.error(
+                            "redis",
+                            format!("{} requires a `time`, `time_ms` or `ttl_from` when `create` block is not specified", store),
+                        ));
                     } else {
-                        let cache_prefix = if let Some(cp) = cache_prefix {
-                            cp.to_string()
-                        } else {
-                            format!(" {{ \"cached::proc_macro::io_cached::{}\" }}", cache_ident)
+                        // the lifespan in seconds given to `new` is overridden by the `Duration`,
+                        // with `ttl_from` it's only used for values set outside of the macro
+                        let set_lifespan = match (time, args.serve_stale_on_error) {
+                            (Some(time), Some(grace)) => quote! {
+                                .set_lifespan_duration(#time + ::std::time::Duration::from_secs(#grace))
+                            },
+                            (Some(time), None) => quote! { .set_lifespan_duration(#time) },
+                            (None, _) => quote! {
+                                .set_lifespan(#TTL_FROM_DEFAULT_LIFESPAN)
+                            },
                         };
-                        let cache_prefix = parse_str::<Block>(cache_prefix.as_ref())
-                            .expect("unable to parse cache_prefix_block");
                         match time_refresh {
                             Some(time_refresh) => {
                                 if asyncness.is_some() {
-                                    quote! { cached::AsyncRedisCache::new(#cache_prefix, #time).set_refresh(#time_refresh).build().await.expect("error constructing AsyncRedisCache in #[io_cached] macro") }
+                                    quote! { cached::AsyncRedisCache::new(#redis_prefix, 0)#set_lifespan#set_evict.set_refresh(#time_refresh).build().await.expect("error constructing AsyncRedisCache in #[io_cached] macro") }
                                 } else {
                                     quote! {
-                                        cached::RedisCache::new(#cache_prefix, #time).set_refresh(#time_refresh).build().expect("error constructing RedisCache in #[io_cached] macro")
+                                        cached::RedisCache::new(#redis_prefix, 0)#set_lifespan#set_evict.set_refresh(#time_refresh).build().expect("error constructing RedisCache in #[io_cached] macro")
                                     }
                                 }
                             }
                             None => {
                                 if asyncness.is_some() {
-                                    quote! { cached::AsyncRedisCache::new(#cache_prefix, #time).build().await.expect("error constructing AsyncRedisCache in #[io_cached] macro") }
+                                    quote! { cached::AsyncRedisCache::new(#redis_prefix, 0)#set_lifespan#set_evict.build().await.expect("error constructing AsyncRedisCache in #[io_cached] macro") }
                                 } else {
                                     quote! {
-                                        cached::RedisCache::new(#cache_prefix, #time).build().expect("error constructing RedisCache in #[io_cached] macro")
+                                        cached::RedisCache::new(#redis_prefix, 0)#set_lifespan#set_evict.build().expect("error constructing RedisCache in #[io_cached] macro")
                                     }
                                 }
                             }
@@ -234,96 +360,134 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
             };
             (cache_ty, cache_create)
         }
-        (_, time, time_refresh, cache_prefix, cache_type, cache_create) => {
+        (None, _, time, time_refresh, cache_prefix, cache_type, cache_create) => {
             let cache_ty = match cache_type {
-                Some(cache_type) => {
-                    let cache_type =
-                        parse_str::<Type>(cache_type).expect("unable to parse cache type");
-                    quote! { #cache_type }
+                Some(cache_type) => quote! { #cache_type },
+                None => {
+                    return Err(spans.error("create", "#[io_cached] cache `type` must be specified"))
                 }
-                None => panic!("#[io_cached] cache `type` must be specified"),
             };
             let cache_create = match cache_create {
                 Some(cache_create) => {
                     if time.is_some() || time_refresh.is_some() || cache_prefix.is_some() {
-                        panic!("cannot specify `time`, `time_refresh`, or `cache_prefix` when passing `create block");
+                        return create_error();
                     } else {
-                        let cache_create = parse_str::<Block>(cache_create.as_ref())
-                            .expect("unable to parse cache create block");
-                        quote! { #cache_create }
+                        cache_create.to_expr()
                     }
                 }
                 None => {
-                    panic!("#[io_cached] cache `create` block must be specified");
+                    return Err(spans.error(
+                        "type",
+                        "#[io_cached] cache `create` block must be specified",
+                    ))
                 }
             };
             (cache_ty, cache_create)
         }
-        #[allow(unreachable_patterns)]
-        _ => panic!("#[io_cached] cache types cache type could not be determined"),
     };
 
     let map_error = &args.map_error;
-    let map_error = parse_str::<ExprClosure>(map_error).expect("unable to parse map_error block");
 
     // make the set cache and return cache blocks
     let (set_cache_block, return_cache_block) = {
-        let (set_cache_block, return_cache_block) = if args.with_cached_flag {
+        let (cached_value, cached_value_ref, return_cache_block) = if args.with_cached_flag {
             (
-                if asyncness.is_some() {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.value.clone()).await.map_err(#map_error)?;
-                        }
-                    }
-                } else {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.value.clone()).map_err(#map_error)?;
-                        }
-                    }
-                },
+                quote! { result.value.clone() },
+                quote! { &result.value },
                 quote! { let mut r = ::cached::Return::new(result.clone()); r.was_cached = true; return Ok(r) },
             )
         } else {
             (
-                if asyncness.is_some() {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.clone()).await.map_err(#map_error)?;
-                        }
-                    }
-                } else {
-                    quote! {
-                        if let Ok(result) = &result {
-                            cache.cache_set(key, result.clone()).map_err(#map_error)?;
-                        }
-                    }
-                },
+                quote! { result.clone() },
+                quote! { result },
                 quote! { return Ok(result.clone()) },
             )
         };
+        let cache_set = gen_cache_set(
+            &args.ttl_from,
+            &cache_value_ty,
+            cached_value,
+            cached_value_ref.clone(),
+        );
+        let set_cache_block = if asyncness.is_some() {
+            quote! { #cache_set.await.map_err(#map_error)?; }
+        } else {
+            quote! { #cache_set.map_err(#map_error)?; }
+        };
+        let set_cache_block = gen_cache_if_block(
+            &args.cache_if,
+            &cache_value_ty,
+            cached_value_ref,
+            set_cache_block,
+        );
+        let set_cache_block = match &errors_ty {
+            Some(_) => {
+                let errors_set = if asyncness.is_some() {
+                    quote! { errors.cache_set(key, error.clone()).await }
+                } else {
+                    quote! { errors.cache_set(key, error.clone()) }
+                };
+                quote! {
+                    match &result {
+                        Ok(result) => { #set_cache_block }
+                        Err(error) => { #errors_set.map_err(#map_error)?; }
+                    }
+                }
+            }
+            None => quote! {
+                if let Ok(result) = &result {
+                    #set_cache_block
+                }
+            },
+        };
         (set_cache_block, return_cache_block)
     };
 
-    let do_set_return_block = if asyncness.is_some() {
-        quote! {
-            // run the function and cache the result
-            async fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*).await;
-            let cache = &#cache_ident.get_or_init(init).await;
-            #set_cache_block
-            result
-        }
-    } else {
-        quote! {
-            // run the function and cache the result
-            fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*);
-            let cache = &#cache_ident;
-            #set_cache_block
-            result
-        }
+    // functions in `impl` blocks call the original function through
+    // an associated function, nested functions can't take `self`
+    let no_cache_fn_ident = Ident::new(&format!("{}_no_cache", &fn_ident), fn_ident.span());
+    let no_cache_callee = gen_no_cache_callee(&no_cache_fn_ident, in_impl, receiver);
+    let (function_no_cache, function_call) = match (asyncness.is_some(), in_impl) {
+        (true, true) => (
+            quote! { async fn #no_cache_fn_ident #generics (#inputs) #output #where_clause #body },
+            quote! { let result = #no_cache_callee(#(#input_names),*).await; },
+        ),
+        (true, false) => (
+            quote! {},
+            quote! {
+                async fn inner #generics (#inputs) #output #where_clause #body
+                let result = inner(#(#input_names),*).await;
+            },
+        ),
+        (false, true) => (
+            quote! { fn #no_cache_fn_ident #generics (#inputs) #output #where_clause #body },
+            quote! { let result = #no_cache_callee(#(#input_names),*); },
+        ),
+        (false, false) => (
+            quote! {},
+            quote! {
+                fn inner #generics (#inputs) #output #where_clause #body
+                let result = inner(#(#input_names),*);
+            },
+        ),
+    };
+
+    // a per-instance cache is used as is, the async static is initialized on first use
+    let (init, get_cache) = match (&args.cache_field, asyncness.is_some()) {
+        (Some(_), _) => (quote! {}, quote! { let cache = #cache_ident; }),
+        (None, true) => (
+            quote! { let init = || async { #cache_create }; },
+            quote! { let cache = &#cache_ident.get_or_init(init).await; },
+        ),
+        (None, false) => (quote! {}, quote! { let cache = &#cache_ident; }),
+    };
+
+    let do_set_return_block = quote! {
+        // run the function and cache the result
+        #function_call
+        #get_cache
+        #set_cache_block
+        result
     };
 
     let signature_no_muts = get_mut_signature(signature);
@@ -333,75 +497,309 @@ pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
     let mut prime_sig = signature_no_muts.clone();
     prime_sig.ident = prime_fn_ident;
 
+    // create signatures for the invalidation and inspection functions, which take
+    // the same arguments to build the key and return the error of the function,
+    // clearing only needs the cache
+    let remove_fn_ident = Ident::new(&format!("{}_cache_remove", &fn_ident), fn_ident.span());
+    let remove_sig = gen_helper_signature(
+        &signature_no_muts,
+        remove_fn_ident,
+        replace_ok_type(&output, parse_quote! { Option<#cache_value_ty> })?,
+    );
+    let contains_fn_ident = Ident::new(&format!("{}_cache_contains", &fn_ident), fn_ident.span());
+    let contains_sig = gen_helper_signature(
+        &signature_no_muts,
+        contains_fn_ident,
+        replace_ok_type(&output, parse_quote! { bool })?,
+    );
+    let clear_fn_ident = Ident::new(&format!("{}_cache_clear", &fn_ident), fn_ident.span());
+    let clear_output = replace_ok_type(&output, parse_quote! { Option<usize> })?;
+    // without arguments the generic parameters couldn't be inferred, they're
+    // only kept if the error type depends on them
+    let (clear_generics, clear_where_clause) =
+        if mentions_generics(&generics, quote! { #clear_output }) {
+            (quote! { #generics }, quote! { #where_clause })
+        } else {
+            (quote! {}, quote! {})
+        };
+    let clear_sig = match &args.cache_field {
+        Some(_) => quote! {
+            #asyncness fn #clear_fn_ident #clear_generics(&self) #clear_output #clear_where_clause
+        },
+        None => quote! {
+            #asyncness fn #clear_fn_ident #clear_generics() #clear_output #clear_where_clause
+        },
+    };
+
     // make cached static, cached function and prime cached function doc comments
-    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
-    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
-    let cache_fn_doc_extra = format!(
-        "This is a cached function that uses the [`{}`] cached static.",
-        cache_ident
+    let fn_path = doc_path(&fn_ident, in_impl);
+    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_path);
+    let errors_ident_doc = format!("Cached errors of the [`{}`] function.", fn_path);
+    let no_cache_fn_indent_doc = format!("Origin of the cached function [`{}`].", fn_path);
+    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_path);
+    let remove_fn_doc = format!(
+        "Removes the value cached by [`{}`] for these arguments and returns it.",
+        fn_path
+    );
+    let contains_fn_doc = format!(
+        "Returns whether [`{}`] has a value cached for these arguments.",
+        fn_path
+    );
+    let clear_fn_doc = format!(
+        "Removes all values cached by [`{}`] and returns how many were removed, if the store can tell.",
+        fn_path
     );
+    let cache_fn_doc_extra = match &args.cache_field {
+        Some(field) => format!(
+            "This is a cached method that uses the cache in the `{}` field.",
+            quote! { #field }
+        ),
+        None => format!(
+            "This is a cached function that uses the [`{}`] cached static.",
+            doc_path(&cache_ident, in_impl)
+        ),
+    };
     fill_in_attributes(&mut attributes, cache_fn_doc_extra);
 
-    // put it all together
-    let expanded = if asyncness.is_some() {
-        quote! {
-            // Cached static
-            #[doc = #cache_ident_doc]
-            #visibility static #cache_ident: ::cached::async_sync::OnceCell<#cache_ty> = ::cached::async_sync::OnceCell::const_new();
-            // Cached function
-            #(#attributes)*
-            #visibility #signature_no_muts {
-                let init = || async { #cache_create };
-                use cached::IOCachedAsync;
-                let key = #key_convert_block;
-                {
-                    // check if the result is cached
-                    let cache = &#cache_ident.get_or_init(init).await;
-                    if let Some(result) = cache.cache_get(&key).await.map_err(#map_error)? {
-                        #return_cache_block
+    let (cache_static_ty, cache_static_init, cache_trait) = if asyncness.is_some() {
+        (
+            quote! { ::cached::async_sync::OnceCell<#cache_ty> },
+            quote! { ::cached::async_sync::OnceCell::const_new() },
+            quote! { cached::IOCachedAsync },
+        )
+    } else {
+        (
+            quote! { ::cached::once_cell::sync::Lazy<#cache_ty> },
+            quote! { ::cached::once_cell::sync::Lazy::new(|| #cache_create) },
+            quote! { cached::IOCached },
+        )
+    };
+    let (cache_get, cache_remove, cache_contains, cache_clear) = if asyncness.is_some() {
+        (
+            quote! { cache.cache_get(&key).await },
+            quote! { cache.cache_remove(&key).await },
+            quote! { cache.cache_contains(&key).await },
+            quote! { cache.cache_clear().await },
+        )
+    } else {
+        (
+            quote! { cache.cache_get(&key) },
+            quote! { cache.cache_remove(&key) },
+            quote! { cache.cache_contains(&key) },
+            quote! { cache.cache_clear() },
+        )
+    };
+
+    // with `serve_stale_on_error` the cached function falls back to a stale value
+    let (cache_get_block, call_or_stale) = match args.serve_stale_on_error {
+        Some(grace) => {
+            let cache_get_with_ttl = if asyncness.is_some() {
+                quote! { cache.cache_get_with_ttl(&key).await }
+            } else {
+                quote! { cache.cache_get_with_ttl(&key) }
+            };
+            let return_stale_block = if args.with_cached_flag {
+                quote! { let mut r = ::cached::Return::new(result); r.was_cached = true; r.was_stale = true; return Ok(r) }
+            } else {
+                quote! { return Ok(result) }
+            };
+            (
+                quote! {
+                    let mut stale = None;
+                    {
+                        // check if the result is cached and still fresh
+                        #get_cache
+                        if let Some((result, ttl)) = #cache_get_with_ttl.map_err(#map_error)? {
+                            if ttl.map_or(true, |ttl| ttl > ::std::time::Duration::from_secs(#grace)) {
+                                #return_cache_block
+                            }
+                            stale = Some(result);
+                        }
                     }
-                }
-                #do_set_return_block
-            }
-            // Prime cached function
-            #[doc = #prime_fn_indent_doc]
-            #[allow(dead_code)]
-            #visibility #prime_sig {
-                use cached::IOCachedAsync;
-                let init = || async { #cache_create };
-                let key = #key_convert_block;
-                #do_set_return_block
-            }
+                },
+                quote! {
+                    #function_call
+                    let result = match (result, stale) {
+                        (Err(_), Some(result)) => { #return_stale_block }
+                        (result, _) => result,
+                    };
+                    #get_cache
+                    #set_cache_block
+                    result
+                },
+            )
         }
-    } else {
-        quote! {
-            // Cached static
-            #[doc = #cache_ident_doc]
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<#cache_ty> = ::cached::once_cell::sync::Lazy::new(|| #cache_create);
-            // Cached function
-            #(#attributes)*
-            #visibility #signature_no_muts {
-                use cached::IOCached;
-                let key = #key_convert_block;
+        None => (
+            quote! {
                 {
                     // check if the result is cached
-                    let cache = &#cache_ident;
-                    if let Some(result) = cache.cache_get(&key).map_err(#map_error)? {
+                    #get_cache
+                    if let Some(result) = #cache_get.map_err(#map_error)? {
                         #return_cache_block
                     }
                 }
-                #do_set_return_block
+            },
+            do_set_return_block.clone(),
+        ),
+    };
+
+    // errors cached by `cache_err_for` are looked up when no value is cached
+    let errors_ident = Ident::new(&format!("{}_ERRORS", cache_ident), cache_ident.span());
+    let errors_path = if in_impl {
+        quote! { Self::#errors_ident() }
+    } else {
+        quote! { #errors_ident }
+    };
+    let (errors_item, errors_binding, cache_get_block, errors_remove, errors_clear) =
+        match &errors_ty {
+            Some((errors_ty, errors_create)) => {
+                let (
+                    errors_static_ty,
+                    errors_static_init,
+                    errors_binding,
+                    errors_get,
+                    errors_remove,
+                    errors_clear,
+                ) = if asyncness.is_some() {
+                    (
+                        quote! { ::cached::async_sync::OnceCell<#errors_ty> },
+                        quote! { ::cached::async_sync::OnceCell::const_new() },
+                        quote! { let errors: &#errors_ty = #errors_path.get_or_init(|| async { #errors_create }).await; },
+                        quote! { errors.cache_get(&key).await },
+                        quote! { errors.cache_remove(&key).await },
+                        quote! { errors.cache_clear().await },
+                    )
+                } else {
+                    (
+                        quote! { ::cached::once_cell::sync::Lazy<#errors_ty> },
+                        quote! { ::cached::once_cell::sync::Lazy::new(|| #errors_create) },
+                        quote! { let errors: &#errors_ty = &#errors_path; },
+                        quote! { errors.cache_get(&key) },
+                        quote! { errors.cache_remove(&key) },
+                        quote! { errors.cache_clear() },
+                    )
+                };
+                let errors_item = if in_impl {
+                    quote! {
+                        #[doc = #errors_ident_doc]
+                        #[allow(non_snake_case)]
+                        #visibility fn #errors_ident() -> &'static #errors_static_ty {
+                            static #errors_ident: #errors_static_ty = #errors_static_init;
+                            &#errors_ident
+                        }
+                    }
+                } else {
+                    quote! {
+                        #[doc = #errors_ident_doc]
+                        #visibility static #errors_ident: #errors_static_ty = #errors_static_init;
+                    }
+                };
+                (
+                    errors_item,
+                    errors_binding,
+                    quote! {
+                        #cache_get_block
+                        if let Some(error) = #errors_get.map_err(#map_error)? {
+                            return Err(error);
+                        }
+                    },
+                    quote! { #errors_remove.map_err(#map_error)?; },
+                    quote! { #errors_clear.map_err(#map_error)?; },
+                )
             }
-            // Prime cached function
-            #[doc = #prime_fn_indent_doc]
-            #[allow(dead_code)]
-            #visibility #prime_sig {
-                use cached::IOCached;
-                let key = #key_convert_block;
-                #do_set_return_block
+            None => (quote! {}, quote! {}, cache_get_block, quote! {}, quote! {}),
+        };
+
+    // the cache is declared inside a function in `impl` blocks
+    let cache_binding = gen_cache_binding(&cache_ident, in_impl, &args.cache_field);
+    let cache_item = match (&args.cache_field, in_impl) {
+        (Some(_), _) => quote! {},
+        (None, true) => quote! {
+            #[doc = #cache_ident_doc]
+            #[allow(non_snake_case)]
+            #visibility fn #cache_ident() -> &'static #cache_static_ty {
+                static #cache_ident: #cache_static_ty = #cache_static_init;
+                &#cache_ident
             }
+        },
+        (None, false) => quote! {
+            #[doc = #cache_ident_doc]
+            #visibility static #cache_ident: #cache_static_ty = #cache_static_init;
+        },
+    };
+    let no_cache_item = if in_impl {
+        quote! {
+            #[doc = #no_cache_fn_indent_doc]
+            #visibility #function_no_cache
+        }
+    } else {
+        quote! {}
+    };
+
+    // put it all together
+    let expanded = quote! {
+        // Cached static
+        #cache_item
+        #errors_item
+        // No cache function (origin of the cached function)
+        #no_cache_item
+        // Cached function
+        #(#attributes)*
+        #visibility #signature_no_muts {
+            #cache_binding
+            #init
+            use #cache_trait;
+            #errors_binding
+            let key = #key_convert_block;
+            #cache_get_block
+            #call_or_stale
+        }
+        // Prime cached function
+        #[doc = #prime_fn_indent_doc]
+        #[allow(dead_code)]
+        #visibility #prime_sig {
+            #cache_binding
+            #init
+            use #cache_trait;
+            #errors_binding
+            let key = #key_convert_block;
+            #do_set_return_block
+        }
+        // Cache invalidation and inspection functions
+        #[doc = #remove_fn_doc]
+        #[allow(dead_code, unused_variables)]
+        #visibility #remove_sig {
+            #cache_binding
+            #init
+            use #cache_trait;
+            #errors_binding
+            let key = #key_convert_block;
+            #errors_remove
+            #get_cache
+            #cache_remove.map_err(#map_error)
+        }
+        #[doc = #clear_fn_doc]
+        #[allow(dead_code)]
+        #visibility #clear_sig {
+            #cache_binding
+            #init
+            use #cache_trait;
+            #errors_binding
+            #errors_clear
+            #get_cache
+            #cache_clear.map_err(#map_error)
+        }
+        #[doc = #contains_fn_doc]
+        #[allow(dead_code, unused_variables)]
+        #visibility #contains_sig {
+            #cache_binding
+            #init
+            use #cache_trait;
+            let key = #key_convert_block;
+            #get_cache
+            #cache_contains.map_err(#map_error)
         }
     };
 
-    expanded.into()
+    Ok(expanded.into())
 }
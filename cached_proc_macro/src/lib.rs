@@ -11,29 +11,81 @@ use proc_macro::TokenStream;
 /// # Attributes
 /// - `name`: (optional, string) specify the name for the generated cache, defaults to the function name uppercase.
 /// - `size`: (optional, usize) specify an LRU max size, implies the cache type is a `SizedCache` or `TimedSizedCache`.
+/// - `policy`: (optional, string) specify the eviction policy used with `size`, either `"lru"` (the default,
+///   a `SizedCache`) or `"lfu"` (an `LfuCache`). Cannot be combined with `time`.
+/// - `max_weight`: (optional, usize) specify the maximum total weight of cached values, implies the cache type
+///   is a `WeightedCache`. Requires `weigher`. Values heavier than `max_weight` are returned without being cached.
+/// - `weigher`: (optional, expr) specify a function or closure `Fn(&K, &V) -> usize` computing the weight
+///   of a cache entry, used with `max_weight`.
+/// - `shards`: (optional, usize) split the cache across this many independently locked shards (a `ShardedCache`)
+///   instead of a single global lock. A `size` is divided between the shards.
 /// - `time`: (optional, u64) specify a cache TTL in seconds, implies the cache type is a `TimedCache` or `TimedSizedCache`.
+/// - `time_ms`: (optional, u64) specify a cache TTL in milliseconds instead of `time`, the two are mutually exclusive.
 /// - `time_refresh`: (optional, bool) specify whether to refresh the TTL on cache hits.
-/// - `sync_writes`: (optional, bool) specify whether to synchronize the execution of writing of uncached values.
-/// - `type`: (optional, string type) The cache store type to use. Defaults to `UnboundCache`. When `unbound` is
+/// - `ttl_from`: (optional, expr) specify a closure or function `Fn(&V) -> Duration` computing the lifespan
+///   of each value from the value itself, e.g. `ttl_from = |token: &Token| token.expires_in()`. Implies the cache
+///   type is a `TtlCache`, and cannot be combined with `time`, `time_ms`, `time_refresh`, `size`, `max_weight` or `shards`.
+///   A custom `type` must have a `cache_set_with_ttl(key, value, Duration)` method.
+/// - `flush_interval`: (optional, u64) remove expired values every this many seconds from a background thread,
+///   instead of only when they are looked up. Requires `time`, `time_ms` or a custom `type`.
+/// - `stale_while_revalidate`: (optional, u64) keep values for this many seconds after they expire. A stale value
+///   is returned right away while a single refresh per key runs on a thread, or a tokio task for async functions.
+///   Requires `time` or `time_ms`. Arguments and the cache key must be `Clone + Send + 'static`.
+/// - `serve_stale_on_error`: (optional, u64) keep values for this many seconds after they expire, and return
+///   the stale value when recomputing it returns an `Err`. Without a stale value the error is returned as usual.
+///   Requires `result = true` and `time` or `time_ms`, and cannot be combined with `stale_while_revalidate`,
+///   `time_refresh` or `shards`. With `with_cached_flag`, `cached::Return.was_stale` is set on stale values.
+/// - `sync_writes`: (optional, bool or `"by_key"`) specify whether to synchronize the execution of writing of uncached values.
+///   `true` keeps the whole cache locked while the function runs. `"by_key"` only makes concurrent calls with the same key
+///   wait on a single execution, calls with other keys run in parallel. The cache key must be `Clone`.
+/// - `type`: (optional, type) The cache store type to use. Defaults to `UnboundCache`. When `unbound` is
 ///   specified, defaults to `UnboundCache`. When `size` is specified, defaults to `SizedCache`.
 ///   When `time` is specified, defaults to `TimedCached`.
 ///   When `size` and `time` are specified, defaults to `TimedSizedCache`. When `type` is
 ///   specified, `create` must also be specified.
-/// - `create`: (optional, string expr) specify an expression used to create a new cache store, e.g. `create = r##"{ CacheType::new() }"##`.
-/// - `key`: (optional, string type) specify what type to use for the cache key, e.g. `key = "u32"`.
-///    When `key` is specified, `convert` must also be specified.
-/// - `convert`: (optional, string expr) specify an expression used to convert function arguments to a cache
-///   key, e.g. `convert = r##"{ format!("{}:{}", arg1, arg2) }"##`. When `convert` is specified,
+/// - `create`: (optional, expr) specify an expression used to create a new cache store, e.g. `create = { CacheType::new() }`.
+/// - `key`: (optional, type) specify what type to use for the cache key, e.g. `key = u32`.
+///   When `key` is specified, `convert` must also be specified.
+/// - `convert`: (optional, expr) specify an expression used to convert function arguments to a cache
+///   key, e.g. `convert = { format!("{}:{}", arg1, arg2) }`. When `convert` is specified,
 ///   `key` or `type` must also be set.
 /// - `result`: (optional, bool) If your function returns a `Result`, only cache `Ok` values returned by the function.
+/// - `cache_err_for`: (optional, u64) also cache `Err` values for this many seconds in a separate `TimedCache`
+///   named after the cache with an `_ERRORS` suffix. Requires `result = true` and a `Clone` error type, and cannot
+///   be combined with `serve_stale_on_error` or `cache_field`.
 /// - `option`: (optional, bool) If your function returns an `Option`, only cache `Some` values returned by the function.
+/// - `cache_if`: (optional, expr) specify a closure or function `Fn(&V) -> bool` deciding whether a value
+///   is cached, e.g. `cache_if = |names| !names.is_empty()`. It's given the value that would be cached, the
+///   `Ok` or `Some` value with `result` or `option`. Values it rejects are returned without being cached.
 /// - `with_cached_flag`: (optional, bool) If your function returns a `cached::Return` or `Result<cached::Return, E>`,
 ///   the `cached::Return.was_cached` flag will be updated when a cached value is returned.
+/// - `in_impl`: (optional, bool) specify that an associated function without `self` is defined in an `impl` block.
+///   Implied for methods taking `self`. The cache is then reached through an associated function of the same
+///   name, e.g. `Type::CACHE_NAME()`, since statics can't be declared in `impl` blocks.
+/// - `ignore_self`: (optional, bool) leave `self` out of the cache key of a method, sharing one cache between all instances.
+/// - `cache_field`: (optional, field) use a per-instance cache stored in this field of `self`, e.g. a
+///   `std::sync::Mutex<UnboundCache<u32, u32>>` (or `cached::async_sync::Mutex` for async methods).
+///
+/// Methods taking `self` must set `ignore_self`, a `convert` block keying on fields of `self`, or a `cache_field`.
+/// Functions in `impl` blocks can't use `Self` or generic parameters of the block in their cache key or value.
+/// Generic functions must set `key` and `convert` to build a cache key of a concrete type, e.g.
+/// `#[cached(key = String, convert = { name.as_ref().to_string() })]` on `fn f<S: AsRef<str>>(name: S)`.
+///
+/// # Generated functions
+/// Besides the cache, a cached function `f` generates:
+/// - `f_no_cache(args...)`: the original function, without caching.
+/// - `f_prime_cache(args...)`: run the function and cache the result, even if a value is already cached.
+/// - `f_cache_remove(args...) -> Option<V>`: remove and return the value cached for these arguments,
+///   along with a cached error.
+/// - `f_cache_contains(args...) -> bool`: whether a value is cached for these arguments, without counting a hit or miss.
+/// - `f_cache_clear()`: remove all cached values and errors.
+///
+/// The arguments are converted into the cache key the same way as for `f`. Methods take `&self`,
+/// `f_cache_clear` only takes `&self` when the cache is a `cache_field`.
 ///
 /// ## Note
-/// The `type`, `create`, `key`, and `convert` attributes must be in a `String`
-/// This is because darling, which is used for parsing the attributes, does not support directly parsing
-/// attributes into `Type`s or `Block`s.
+/// Types, expressions and blocks are written as Rust syntax, e.g. `key = String`. The string forms
+/// required by earlier versions, e.g. `key = "String"`, are still accepted.
 #[proc_macro_attribute]
 pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
     cached::cached(args, input)
@@ -46,11 +98,30 @@ pub fn cached(args: TokenStream, input: TokenStream) -> TokenStream {
 /// # Attributes
 /// - `name`: (optional, string) specify the name for the generated cache, defaults to the function name uppercase.
 /// - `time`: (optional, u64) specify a cache TTL in seconds, implies the cache type is a `TimedCached` or `TimedSizedCache`.
+/// - `time_ms`: (optional, u64) specify a cache TTL in milliseconds instead of `time`, the two are mutually exclusive.
+/// - `clock`: (optional, expr) specify a `cached::Clock` to read the current time from instead of the
+///   system clock, e.g. a `cached::MockClock` in tests. Requires `time` or `time_ms`.
 /// - `sync_writes`: (optional, bool) specify whether to synchronize the execution of writing of uncached values.
 /// - `result`: (optional, bool) If your function returns a `Result`, only cache `Ok` values returned by the function.
 /// - `option`: (optional, bool) If your function returns an `Option`, only cache `Some` values returned by the function.
+/// - `cache_if`: (optional, expr) specify a closure or function `Fn(&V) -> bool` deciding whether a value
+///   is cached, e.g. `cache_if = |names| !names.is_empty()`. It's given the value that would be cached, the
+///   `Ok` or `Some` value with `result` or `option`. Values it rejects are returned without being cached.
 /// - `with_cached_flag`: (optional, bool) If your function returns a `cached::Return` or `Result<cached::Return, E>`,
 ///   the `cached::Return.was_cached` flag will be updated when a cached value is returned.
+/// - `in_impl`: (optional, bool) specify that an associated function without `self` is defined in an `impl` block.
+///   Implied for methods taking `self`. The cache is then reached through an associated function of the
+///   same name, e.g. `Type::CACHE_NAME()`.
+/// - `ignore_self`: (optional, bool) share the single cached value of a method between all instances.
+/// - `cache_field`: (optional, field) use a per-instance cache stored in this field of `self`,
+///   a `std::sync::RwLock<Option<V>>` (or `cached::async_sync::RwLock` for async methods). The value is
+///   stored as `Option<(cached::instant::Instant, V)>` when a lifespan is set.
+///
+/// Methods taking `self` must set `ignore_self` or a `cache_field`.
+///
+/// ## Note
+/// Types, expressions and blocks are written as Rust syntax, e.g. `clock = MOCK_CLOCK`. The string
+/// forms required by earlier versions are still accepted.
 #[proc_macro_attribute]
 pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
     once::once(args, input)
@@ -60,31 +131,72 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
 /// `cached::IOCachedAsync` for async functions)
 ///
 /// # Attributes
-/// - `map_error`: (string, expr closure) specify a closure used to map any IO-store errors into
+/// - `map_error`: (expr closure) specify a closure used to map any IO-store errors into
 ///   the error type returned by your function.
 /// - `name`: (optional, string) specify the name for the generated cache, defaults to the function name uppercase.
 /// - `redis`: (optional, bool) default to a `RedisCache` or `AsyncRedisCache`
 /// - `time`: (optional, u64) specify a cache TTL in seconds, implies the cache type is a `TimedCached` or `TimedSizedCache`.
+/// - `time_ms`: (optional, u64) specify a cache TTL in milliseconds instead of `time`, the two are mutually exclusive.
 /// - `time_refresh`: (optional, bool) specify whether to refresh the TTL on cache hits.
-/// - `type`: (optional, string type) explicitly specify the cache store type to use.
-/// - `cache_prefix_block`: (optional, string expr) specify an expression used to create the string used as a
-///   prefix for all cache keys of this function, e.g. `cache_prefix_block = r##"{ "my_prefix" }"##`.
-///   When not specified, the cache prefix will be constructed from the name of the function. This
-///   could result in unexpected conflicts between io_cached-functions of the same name, so it's
-///   recommended that you specify a prefix you're sure will be unique.
-/// - `create`: (optional, string expr) specify an expression used to create a new cache store, e.g. `create = r##"{ CacheType::new() }"##`.
-/// - `key`: (optional, string type) specify what type to use for the cache key, e.g. `type = "TimedCached<u32, u32>"`.
-///    When `key` is specified, `convert` must also be specified.
-/// - `convert`: (optional, string expr) specify an expression used to convert function arguments to a cache
-///   key, e.g. `convert = r##"{ format!("{}:{}", arg1, arg2) }"##`. When `convert` is specified,
+/// - `ttl_from`: (optional, expr) specify a closure or function `Fn(&V) -> Duration` computing the lifespan
+///   of each `Ok` value from the value itself, e.g. `ttl_from = |token: &Token| token.expires_in()`. Values are
+///   set with `cache_set_with_ttl`, a per-call `PSETEX` for redis stores, which then don't need a `time`.
+///   Values set without `ttl_from`, e.g. through the store's own `cache_set`, expire after `time`, or after
+///   an hour when it isn't set.
+///   Cannot be combined with `time_refresh`.
+/// - `serve_stale_on_error`: (optional, u64) keep values in redis for this many seconds after they expire, and
+///   return the stale value when recomputing it returns an `Err`. Without a stale value the error is returned
+///   as usual. Requires `redis = true` with `time` or `time_ms`, and cannot be combined with `create`,
+///   `time_refresh` or `ttl_from`. With `with_cached_flag`, `cached::Return.was_stale` is set on stale values.
+/// - `cache_err_for`: (optional, u64) also cache `Err` values for this many seconds in a second redis store
+///   named after the cache with an `_ERRORS` suffix. Its prefix is the cache prefix preceded by `errors:`, or
+///   `cached::proc_macro::io_cached::errors::{CACHE}:` by default, so errors aren't part of the cache's keys. The error type
+///   must be `Clone` and serializable, and `redis = true` is required without `create`.
+/// - `evict_undeserializable`: (optional, bool) treat redis values that can't be deserialized, e.g. after the
+///   value type changed, as missing: they're logged, deleted and recomputed instead of failing through `map_error`.
+///   Requires `redis = true` without `create`, stores built with `create` can call `set_evict_undeserializable`.
+/// - `type`: (optional, type) explicitly specify the cache store type to use.
+/// - `cache_prefix_block`: (optional, expr) specify an expression used to create the string used as a
+///   prefix for all cache keys of this function, e.g. `cache_prefix_block = { "my_prefix" }`.
+///   When not specified, the cache prefix will be constructed from the name of the function as
+///   `cached::proc_macro::io_cached::{CACHE}:`. This could result in unexpected conflicts between
+///   io_cached-functions of the same name, so it's recommended that you specify a prefix you're sure
+///   will be unique. End it with a delimiter such as `:` so the keys of `f_cache_clear` and other
+///   prefix-wide operations don't include those of prefixes starting with it.
+/// - `create`: (optional, expr) specify an expression used to create a new cache store, e.g. `create = { CacheType::new() }`.
+/// - `key`: (optional, type) specify what type to use for the cache key, e.g. `key = u32`.
+///   When `key` is specified, `convert` must also be specified.
+/// - `convert`: (optional, expr) specify an expression used to convert function arguments to a cache
+///   key, e.g. `convert = { format!("{}:{}", arg1, arg2) }`. When `convert` is specified,
 ///   `key` or `type` must also be set.
+/// - `cache_if`: (optional, expr) specify a closure or function `Fn(&V) -> bool` deciding whether an `Ok`
+///   value is cached, e.g. `cache_if = |page| !page.retry_later`. Values it rejects are returned without being cached.
 /// - `with_cached_flag`: (optional, bool) If your function returns a `cached::Return` or `Result<cached::Return, E>`,
 ///   the `cached::Return.was_cached` flag will be updated when a cached value is returned.
+/// - `in_impl`: (optional, bool) specify that an associated function without `self` is defined in an `impl` block.
+///   Implied for methods taking `self`. The cache is then reached through an associated function of the same
+///   name, e.g. `Type::CACHE_NAME()`, since statics can't be declared in `impl` blocks.
+/// - `ignore_self`: (optional, bool) leave `self` out of the cache key of a method, sharing one cache between all instances.
+/// - `cache_field`: (optional, field) use a per-instance cache stored in this field of `self`, e.g. a `RedisCache<K, V>`.
+///
+/// Methods taking `self` must set `ignore_self`, a `convert` block keying on fields of `self`, or a `cache_field`.
+///
+/// # Generated functions
+/// Besides the cache, an io-cached function `f` returning `Result<T, E>` generates:
+/// - `f_prime_cache(args...)`: run the function and cache the result, even if a value is already cached.
+/// - `f_cache_remove(args...) -> Result<Option<T>, E>`: remove and return the value cached for these arguments,
+///   along with a cached error.
+/// - `f_cache_contains(args...) -> Result<bool, E>`: whether a value is cached for these arguments, one `f` would
+///   return instead of recomputing it. Redis values of another version, or evicted by `evict_undeserializable`, aren't.
+/// - `f_cache_clear() -> Result<Option<usize>, E>`: remove all cached values and errors, returning how many
+///   values were removed, or `None` if the store doesn't support `cache_clear`. Takes `&self` with `cache_field`.
+///
+/// The arguments are converted into the cache key the same way as for `f`, and store errors are mapped
+/// with `map_error`.
 ///
 /// ## Note
-/// The `type`, `create`, `key`, and `convert` attributes must be in a `String`
-/// This is because darling, which is used for parsing the attributes, does not support directly parsing
-/// attributes into `Type`s or `Block`s.
+/// Types, expressions and blocks are written as Rust syntax, e.g. `key = String`. The string forms
+/// required by earlier versions, e.g. `key = "String"`, are still accepted.
 #[proc_macro_attribute]
 pub fn io_cached(args: TokenStream, input: TokenStream) -> TokenStream {
     io_cached::io_cached(args, input)
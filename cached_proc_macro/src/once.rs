@@ -3,7 +3,7 @@ use darling::FromMeta;
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::spanned::Spanned;
-use syn::{parse_macro_input, AttributeArgs, Ident, ItemFn, ReturnType};
+use syn::{Expr, Ident, ItemFn, Member, ReturnType};
 
 #[derive(FromMeta)]
 struct OnceMacroArgs {
@@ -12,24 +12,39 @@ struct OnceMacroArgs {
     #[darling(default)]
     time: Option<u64>,
     #[darling(default)]
+    time_ms: Option<u64>,
+    #[darling(default)]
+    clock: Option<Syntax<Expr>>,
+    #[darling(default)]
     sync_writes: bool,
     #[darling(default)]
     result: bool,
     #[darling(default)]
     option: bool,
     #[darling(default)]
+    cache_if: Option<Syntax<Expr>>,
+    #[darling(default)]
     with_cached_flag: bool,
+    #[darling(default)]
+    in_impl: bool,
+    #[darling(default)]
+    ignore_self: bool,
+    #[darling(default)]
+    cache_field: Option<Syntax<Member>>,
 }
 
 pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
-    let attr_args = parse_macro_input!(args as AttributeArgs);
-    let args = match OnceMacroArgs::from_list(&attr_args) {
-        Ok(v) => v,
-        Err(e) => {
-            return TokenStream::from(e.write_errors());
-        }
-    };
-    let input = parse_macro_input!(input as ItemFn);
+    match expand(args, input) {
+        Ok(expanded) => expanded,
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(args: TokenStream, input: TokenStream) -> syn::Result<TokenStream> {
+    let attr_args = parse_attr_args(args)?;
+    let spans = ArgSpans::new(&attr_args);
+    let args = OnceMacroArgs::from_list(&attr_args)?;
+    let input = syn::parse::<ItemFn>(input)?;
 
     // pull out the parts of the input
     let mut attributes = input.attrs;
@@ -42,10 +57,17 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
     let inputs = signature.inputs.clone();
     let output = signature.output.clone();
     let asyncness = signature.asyncness;
+    let generics = signature.generics.clone();
+    let where_clause = &generics.where_clause;
 
     // pull out the names and types of the function inputs
     let input_names = get_input_names(&inputs);
 
+    // functions in `impl` blocks can't declare statics next to them
+    let receiver = has_receiver(&inputs);
+    let in_impl = args.in_impl || receiver;
+    check_receiver_key(&spans, &inputs, args.ignore_self, None, &args.cache_field)?;
+
     // pull out the output type
     let output_ty = match &output {
         ReturnType::Default => quote! {()},
@@ -59,10 +81,12 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
     let output_type_display = output_ts.to_string().replace(' ', "");
 
     if check_with_cache_flag(args.with_cached_flag, output_string) {
-        return with_cache_flag_error(output_span, output_type_display);
+        return Err(with_cache_flag_error(output_span, output_type_display));
     }
 
-    let cache_value_ty = find_value_type(args.result, args.option, &output, output_ty);
+    let cache_value_ty = find_value_type(&spans, args.result, args.option, &output, output_ty)?;
+
+    check_generics(&generics, &inputs, false, &None, &cache_value_ty)?;
 
     // make the cache identifier
     let cache_ident = match args.name {
@@ -70,8 +94,21 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
         None => Ident::new(&fn_ident.to_string().to_uppercase(), fn_ident.span()),
     };
 
+    let lifespan = make_lifespan(&spans, args.time, args.time_ms)?;
+
+    // read the current time from the `clock` expression if one is given
+    let now = match &args.clock {
+        Some(_) if lifespan.is_none() => {
+            return Err(spans.error("clock", "clock requires time or time_ms to be set"))
+        }
+        Some(clock) => {
+            quote! { { use ::cached::Clock; (#clock).now() } }
+        }
+        None => quote! { ::cached::instant::Instant::now() },
+    };
+
     // make the cache type and create statement
-    let (cache_ty, cache_create) = match &args.time {
+    let (cache_ty, cache_create) = match &lifespan {
         None => (quote! { Option<#cache_value_ty> }, quote! { None }),
         Some(_) => (
             quote! { Option<(::cached::instant::Instant, #cache_value_ty)> },
@@ -82,7 +119,7 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
     // make the set cache and return cache blocks
     let (set_cache_block, return_cache_block) = match (&args.result, &args.option) {
         (false, false) => {
-            let set_cache_block = if args.time.is_some() {
+            let set_cache_block = if lifespan.is_some() {
                 quote! {
                     *cached = Some((now, result.clone()));
                 }
@@ -91,27 +128,36 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
                     *cached = Some(result.clone());
                 }
             };
+            let set_cache_block = gen_cache_if_block(
+                &args.cache_if,
+                &cache_value_ty,
+                quote! { &result },
+                set_cache_block,
+            );
 
             let return_cache_block = if args.with_cached_flag {
                 quote! { let mut r = result.clone(); r.was_cached = true; return r }
             } else {
                 quote! { return result.clone() }
             };
-            let return_cache_block = gen_return_cache_block(args.time, return_cache_block);
+            let return_cache_block = gen_return_cache_block(lifespan.as_ref(), return_cache_block);
             (set_cache_block, return_cache_block)
         }
         (true, false) => {
-            let set_cache_block = if args.time.is_some() {
-                quote! {
-                    if let Ok(result) = &result {
-                        *cached = Some((now, result.clone()));
-                    }
-                }
+            let set_cache_block = if lifespan.is_some() {
+                quote! { *cached = Some((now, result.clone())); }
             } else {
-                quote! {
-                    if let Ok(result) = &result {
-                        *cached = Some(result.clone());
-                    }
+                quote! { *cached = Some(result.clone()); }
+            };
+            let set_cache_block = gen_cache_if_block(
+                &args.cache_if,
+                &cache_value_ty,
+                quote! { result },
+                set_cache_block,
+            );
+            let set_cache_block = quote! {
+                if let Ok(result) = &result {
+                    #set_cache_block
                 }
             };
 
@@ -120,21 +166,24 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
             } else {
                 quote! { return Ok(result.clone()) }
             };
-            let return_cache_block = gen_return_cache_block(args.time, return_cache_block);
+            let return_cache_block = gen_return_cache_block(lifespan.as_ref(), return_cache_block);
             (set_cache_block, return_cache_block)
         }
         (false, true) => {
-            let set_cache_block = if args.time.is_some() {
-                quote! {
-                    if let Some(result) = &result {
-                        *cached = Some((now, result.clone()));
-                    }
-                }
+            let set_cache_block = if lifespan.is_some() {
+                quote! { *cached = Some((now, result.clone())); }
             } else {
-                quote! {
-                    if let Some(result) = &result {
-                        *cached = Some(result.clone());
-                    }
+                quote! { *cached = Some(result.clone()); }
+            };
+            let set_cache_block = gen_cache_if_block(
+                &args.cache_if,
+                &cache_value_ty,
+                quote! { result },
+                set_cache_block,
+            );
+            let set_cache_block = quote! {
+                if let Some(result) = &result {
+                    #set_cache_block
                 }
             };
 
@@ -143,20 +192,31 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
             } else {
                 quote! { return Some(result.clone()) }
             };
-            let return_cache_block = gen_return_cache_block(args.time, return_cache_block);
+            let return_cache_block = gen_return_cache_block(lifespan.as_ref(), return_cache_block);
             (set_cache_block, return_cache_block)
         }
-        _ => panic!("the result and option attributes are mutually exclusive"),
+        (true, true) => unreachable!("rejected by find_value_type"),
     };
 
     let set_cache_and_return = quote! {
         #set_cache_block
         result
     };
+    // functions in `impl` blocks call the original function through
+    // an associated function, nested functions can't take `self`
+    let no_cache_fn_ident = Ident::new(&format!("{}_no_cache", &fn_ident), fn_ident.span());
+    let no_cache_callee = gen_no_cache_callee(&no_cache_fn_ident, in_impl, receiver);
+    let function_no_cache = if asyncness.is_some() {
+        quote! { async fn #no_cache_fn_ident #generics (#inputs) #output #where_clause #body }
+    } else {
+        quote! { fn #no_cache_fn_ident #generics (#inputs) #output #where_clause #body }
+    };
+
     let r_lock;
     let w_lock;
     let function_call;
-    let cache_type;
+    let cache_static_ty;
+    let cache_static_init;
     if asyncness.is_some() {
         w_lock = quote! {
             // try to get a write lock
@@ -168,14 +228,24 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
             let mut cached = #cache_ident.read().await;
         };
 
-        function_call = quote! {
-            // run the function and cache the result
-            async fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*).await;
+        function_call = if in_impl {
+            quote! {
+                // run the function and cache the result
+                let result = #no_cache_callee(#(#input_names),*).await;
+            }
+        } else {
+            quote! {
+                // run the function and cache the result
+                async fn inner #generics (#inputs) #output #where_clause #body
+                let result = inner(#(#input_names),*).await;
+            }
         };
 
-        cache_type = quote! {
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<::cached::async_sync::RwLock<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| ::cached::async_sync::RwLock::new(#cache_create));
+        cache_static_ty = quote! {
+            ::cached::once_cell::sync::Lazy<::cached::async_sync::RwLock<#cache_ty>>
+        };
+        cache_static_init = quote! {
+            ::cached::once_cell::sync::Lazy::new(|| ::cached::async_sync::RwLock::new(#cache_create))
         };
     } else {
         w_lock = quote! {
@@ -188,14 +258,24 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
             let mut cached = #cache_ident.read().unwrap();
         };
 
-        function_call = quote! {
-            // run the function and cache the result
-            fn inner(#inputs) #output #body;
-            let result = inner(#(#input_names),*);
+        function_call = if in_impl {
+            quote! {
+                // run the function and cache the result
+                let result = #no_cache_callee(#(#input_names),*);
+            }
+        } else {
+            quote! {
+                // run the function and cache the result
+                fn inner #generics (#inputs) #output #where_clause #body
+                let result = inner(#(#input_names),*);
+            }
         };
 
-        cache_type = quote! {
-            #visibility static #cache_ident: ::cached::once_cell::sync::Lazy<std::sync::RwLock<#cache_ty>> = ::cached::once_cell::sync::Lazy::new(|| std::sync::RwLock::new(#cache_create));
+        cache_static_ty = quote! {
+            ::cached::once_cell::sync::Lazy<std::sync::RwLock<#cache_ty>>
+        };
+        cache_static_init = quote! {
+            ::cached::once_cell::sync::Lazy::new(|| std::sync::RwLock::new(#cache_create))
         };
     }
 
@@ -240,33 +320,70 @@ pub fn once(args: TokenStream, input: TokenStream) -> TokenStream {
     prime_sig.ident = prime_fn_ident;
 
     // make cached static, cached function and prime cached function doc comments
-    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_ident);
-    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_ident);
-    let cache_fn_doc_extra = format!(
-        "This is a cached function that uses the [`{}`] cached static.",
-        cache_ident
-    );
+    let fn_path = doc_path(&fn_ident, in_impl);
+    let cache_ident_doc = format!("Cached static for the [`{}`] function.", fn_path);
+    let no_cache_fn_indent_doc = format!("Origin of the cached function [`{}`].", fn_path);
+    let prime_fn_indent_doc = format!("Primes the cached function [`{}`].", fn_path);
+    let cache_fn_doc_extra = match &args.cache_field {
+        Some(field) => format!(
+            "This is a cached method that uses the cache in the `{}` field.",
+            quote! { #field }
+        ),
+        None => format!(
+            "This is a cached function that uses the [`{}`] cached static.",
+            doc_path(&cache_ident, in_impl)
+        ),
+    };
     fill_in_attributes(&mut attributes, cache_fn_doc_extra);
 
+    // the cache is declared inside a function in `impl` blocks
+    let cache_binding = gen_cache_binding(&cache_ident, in_impl, &args.cache_field);
+    let cache_item = match (&args.cache_field, in_impl) {
+        (Some(_), _) => quote! {},
+        (None, true) => quote! {
+            #[doc = #cache_ident_doc]
+            #[allow(non_snake_case)]
+            #visibility fn #cache_ident() -> &'static #cache_static_ty {
+                static #cache_ident: #cache_static_ty = #cache_static_init;
+                &#cache_ident
+            }
+        },
+        (None, false) => quote! {
+            #[doc = #cache_ident_doc]
+            #visibility static #cache_ident: #cache_static_ty = #cache_static_init;
+        },
+    };
+    let no_cache_item = if in_impl {
+        quote! {
+            #[doc = #no_cache_fn_indent_doc]
+            #visibility #function_no_cache
+        }
+    } else {
+        quote! {}
+    };
+
     // put it all together
     let expanded = quote! {
         // Cached static
-        #[doc = #cache_ident_doc]
-        #cache_type
+        #cache_item
+        // No cache function (origin of the cached function)
+        #no_cache_item
         // Cached function
         #(#attributes)*
         #visibility #signature_no_muts {
-            let now = ::cached::instant::Instant::now();
+            #cache_binding
+            let now = #now;
             #do_set_return_block
         }
         // Prime cached function
         #[doc = #prime_fn_indent_doc]
         #[allow(dead_code)]
         #visibility #prime_sig {
-            let now = ::cached::instant::Instant::now();
+            #cache_binding
+            let now = #now;
             #prime_do_set_return_block
         }
     };
 
-    expanded.into()
+    Ok(expanded.into())
 }
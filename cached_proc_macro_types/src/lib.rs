@@ -1,14 +1,21 @@
 /// Used to wrap a function result so callers can see whether the result was cached.
+///
+/// Fields may be added in future versions, build a `Return` with [`Return::new`].
 #[derive(Clone)]
+#[non_exhaustive]
 pub struct Return<T> {
     pub was_cached: bool,
+    /// Whether the value is an expired one, returned because recomputing it failed
+    pub was_stale: bool,
     pub value: T,
 }
 
 impl<T> Return<T> {
+    /// Wrap a freshly computed value, neither cached nor stale
     pub fn new(value: T) -> Self {
         Self {
             was_cached: false,
+            was_stale: false,
             value,
         }
     }
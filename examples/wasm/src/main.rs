@@ -5,7 +5,7 @@ use yew::prelude::*;
 use cached::proc_macro::cached;
 use cached::TimedCache;
 
-const URL: &'static str = "https://echo.zuplo.io/";
+const URL: &str = "https://echo.zuplo.io/";
 
 #[derive(Clone)]
 struct State {
@@ -35,7 +35,7 @@ fn app() -> Html {
             <button onclick = {onclick}>{"Fetch Content"}</button><br/>
             <spam>{"Last clicked: "}{state.date}</spam><br/>
             <div>
-                {if let Some(response) = (*state).content.clone() {
+                {if let Some(response) = state.content.clone() {
                     response
                 } else {
                     "Click the button".to_owned()
@@ -0,0 +1,119 @@
+/*!
+Sources of time for timed cache stores
+
+Timed stores read the current time through a [`Clock`]. They use the
+[`SystemClock`] by default, a [`MockClock`] can be given instead to
+control expiry in tests without sleeping.
+
+```rust
+use cached::{Cached, MockClock, TimedCache};
+use std::time::Duration;
+
+let clock = MockClock::new();
+let mut cache = TimedCache::with_lifespan_duration_and_clock(Duration::from_secs(60), clock.clone());
+cache.cache_set(1, "one");
+clock.advance(Duration::from_secs(59));
+assert_eq!(cache.cache_get(&1), Some(&"one"));
+clock.advance(Duration::from_secs(1));
+assert_eq!(cache.cache_get(&1), None);
+```
+*/
+
+use instant::Instant;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A source of the current time
+pub trait Clock {
+    /// Return the current instant
+    fn now(&self) -> Instant;
+}
+
+/// The system's monotonic clock, `Instant::now()`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves forward when advanced manually
+///
+/// Clones share the same time, so a clone can be handed to a cache
+/// and the original advanced from a test.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    start: Instant,
+    elapsed_nanos: Arc<AtomicU64>,
+}
+
+impl MockClock {
+    /// Creates a new `MockClock` stopped at the current instant
+    #[must_use]
+    pub fn new() -> MockClock {
+        MockClock {
+            start: Instant::now(),
+            elapsed_nanos: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Move the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.elapsed_nanos.fetch_add(nanos, Ordering::SeqCst);
+    }
+
+    /// Return the time the clock was advanced by since it was created
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_nanos.load(Ordering::SeqCst))
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_clock() {
+        let clock = MockClock::new();
+        let shared = clock.clone();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+
+        shared.advance(Duration::from_millis(1500));
+        assert_eq!(
+            clock.now().duration_since(start),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(clock.elapsed(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn system_clock() {
+        let start = SystemClock.now();
+        assert!(SystemClock.now() >= start);
+    }
+}
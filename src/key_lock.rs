@@ -0,0 +1,318 @@
+/*!
+Per-key locks
+
+Used by `#[cached(sync_writes = "by_key")]` so that concurrent callers
+for the same key wait on a single computation, while callers for other
+keys proceed in parallel. Locks are created on demand and dropped once
+no caller holds or waits on them.
+
+```rust
+use cached::KeyLocks;
+
+let locks = KeyLocks::new();
+let a = locks.lock(&"a");
+// a different key does not wait
+let b = locks.lock(&"b");
+drop(a);
+drop(b);
+assert!(locks.is_empty());
+```
+*/
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+fn lock_ignore_poison<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[derive(Debug, Default)]
+struct KeyState {
+    locked: Mutex<bool>,
+    unlocked: Condvar,
+}
+
+/// A set of locks, one per key, blocking the current thread
+#[derive(Debug)]
+pub struct KeyLocks<K> {
+    locks: Mutex<HashMap<K, Arc<KeyState>>>,
+}
+
+/// Holds the lock of a key in [`KeyLocks`], released when dropped
+#[derive(Debug)]
+pub struct KeyLockGuard<'a, K: Hash + Eq> {
+    locks: &'a KeyLocks<K>,
+    key: K,
+    state: Option<Arc<KeyState>>,
+}
+
+impl<K: Hash + Eq + Clone> KeyLocks<K> {
+    /// Creates a new empty `KeyLocks`
+    #[must_use]
+    pub fn new() -> KeyLocks<K> {
+        KeyLocks {
+            locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Lock `key`, blocking the current thread until no other guard
+    /// for `key` is held
+    pub fn lock(&self, key: &K) -> KeyLockGuard<'_, K> {
+        let state = lock_ignore_poison(&self.locks)
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        {
+            let mut locked = lock_ignore_poison(&state.locked);
+            while *locked {
+                locked = state
+                    .unlocked
+                    .wait(locked)
+                    .unwrap_or_else(std::sync::PoisonError::into_inner);
+            }
+            *locked = true;
+        }
+        KeyLockGuard {
+            locks: self,
+            key: key.clone(),
+            state: Some(state),
+        }
+    }
+}
+
+impl<K: Hash + Eq> KeyLocks<K> {
+    /// Return the number of keys currently locked or waited on
+    pub fn len(&self) -> usize {
+        lock_ignore_poison(&self.locks).len()
+    }
+
+    /// Return whether no key is currently locked or waited on
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K: Hash + Eq + Clone> Default for KeyLocks<K> {
+    fn default() -> Self {
+        KeyLocks::new()
+    }
+}
+
+impl<'a, K: Hash + Eq> Drop for KeyLockGuard<'a, K> {
+    fn drop(&mut self) {
+        let mut locks = lock_ignore_poison(&self.locks.locks);
+        // the reference is dropped before the map is unlocked, so that
+        // the next guard of the key can tell when it is the last one
+        if let Some(state) = self.state.take() {
+            *lock_ignore_poison(&state.locked) = false;
+            state.unlocked.notify_one();
+            // only the map and this guard still reference the lock
+            if Arc::strong_count(&state) == 2 {
+                locks.remove(&self.key);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub use self::async_key_lock::{AsyncKeyLockGuard, AsyncKeyLocks};
+
+#[cfg(feature = "async")]
+mod async_key_lock {
+    use super::lock_ignore_poison;
+    use std::collections::HashMap;
+    use std::hash::Hash;
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+    /// A set of locks, one per key, awaited asynchronously
+    #[derive(Debug)]
+    pub struct AsyncKeyLocks<K> {
+        locks: Mutex<HashMap<K, Arc<AsyncMutex<()>>>>,
+    }
+
+    /// Holds the lock of a key in [`AsyncKeyLocks`], released when dropped
+    #[derive(Debug)]
+    pub struct AsyncKeyLockGuard<'a, K: Hash + Eq> {
+        locks: &'a AsyncKeyLocks<K>,
+        key: K,
+        lock: Option<Arc<AsyncMutex<()>>>,
+        guard: Option<OwnedMutexGuard<()>>,
+    }
+
+    impl<K: Hash + Eq + Clone> AsyncKeyLocks<K> {
+        /// Creates a new empty `AsyncKeyLocks`
+        #[must_use]
+        pub fn new() -> AsyncKeyLocks<K> {
+            AsyncKeyLocks {
+                locks: Mutex::new(HashMap::new()),
+            }
+        }
+
+        /// Lock `key`, waiting until no other guard for `key` is held
+        pub async fn lock(&self, key: &K) -> AsyncKeyLockGuard<'_, K> {
+            let lock = lock_ignore_poison(&self.locks)
+                .entry(key.clone())
+                .or_default()
+                .clone();
+            // the waiter is a guard too, so that a wait cancelled after the
+            // lock was handed over still removes the key when it's the last
+            let mut guard = AsyncKeyLockGuard {
+                locks: self,
+                key: key.clone(),
+                lock: Some(lock.clone()),
+                guard: None,
+            };
+            guard.guard = Some(lock.lock_owned().await);
+            guard
+        }
+    }
+
+    impl<K: Hash + Eq> AsyncKeyLocks<K> {
+        /// Return the number of keys currently locked or waited on
+        pub fn len(&self) -> usize {
+            lock_ignore_poison(&self.locks).len()
+        }
+
+        /// Return whether no key is currently locked or waited on
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+    }
+
+    impl<K: Hash + Eq + Clone> Default for AsyncKeyLocks<K> {
+        fn default() -> Self {
+            AsyncKeyLocks::new()
+        }
+    }
+
+    impl<'a, K: Hash + Eq> Drop for AsyncKeyLockGuard<'a, K> {
+        fn drop(&mut self) {
+            let mut locks = lock_ignore_poison(&self.locks.locks);
+            self.guard.take();
+            if let Some(lock) = self.lock.take() {
+                // only the map and this guard still reference the lock
+                if Arc::strong_count(&lock) == 2 {
+                    locks.remove(&self.key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+/// Key lock tests
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn same_key_waits() {
+        let locks = Arc::new(KeyLocks::new());
+        let running = Arc::new(AtomicUsize::new(0));
+        let handles = (0..4)
+            .map(|_| {
+                let locks = locks.clone();
+                let running = running.clone();
+                thread::spawn(move || {
+                    let _guard = locks.lock(&1);
+                    assert_eq!(running.fetch_add(1, Ordering::SeqCst), 0);
+                    thread::sleep(Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect::<Vec<_>>();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert!(locks.is_empty());
+    }
+
+    #[test]
+    fn other_keys_proceed() {
+        let locks = KeyLocks::new();
+        let a = locks.lock(&1);
+        let b = locks.lock(&2);
+        assert_eq!(locks.len(), 2);
+        drop(a);
+        assert_eq!(locks.len(), 1);
+        drop(b);
+        assert!(locks.is_empty());
+    }
+
+    #[test]
+    fn released_after_panic() {
+        let locks = Arc::new(KeyLocks::new());
+        let panicking = locks.clone();
+        let result = thread::spawn(move || {
+            let _guard = panicking.lock(&1);
+            panic!("computation failed");
+        })
+        .join();
+        assert!(result.is_err());
+        let _guard = locks.lock(&1);
+        assert_eq!(locks.len(), 1);
+    }
+
+    #[test]
+    fn removed_after_handover() {
+        let locks = Arc::new(KeyLocks::new());
+        for _ in 0..200 {
+            let guard = locks.lock(&1);
+            let waiting = {
+                let locks = locks.clone();
+                thread::spawn(move || drop(locks.lock(&1)))
+            };
+            while Arc::strong_count(guard.state.as_ref().unwrap()) < 3 {
+                thread::yield_now();
+            }
+            drop(guard);
+            waiting.join().unwrap();
+            assert!(locks.is_empty());
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_same_key_waits() {
+        let locks = Arc::new(AsyncKeyLocks::new());
+        let guard = locks.lock(&1).await;
+        let waiting = {
+            let locks = locks.clone();
+            tokio::spawn(async move {
+                let _guard = locks.lock(&1).await;
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+        let _other = locks.lock(&2).await;
+        drop(guard);
+        waiting.await.unwrap();
+        assert_eq!(locks.len(), 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_removed_after_cancelled_wait() {
+        let locks = AsyncKeyLocks::new();
+        let guard = locks.lock(&1).await;
+        let mut waiting = Box::pin(locks.lock(&1));
+        assert!(futures::FutureExt::now_or_never(&mut waiting).is_none());
+        // the lock is handed over to a waiter that is dropped before it runs again
+        drop(guard);
+        drop(waiting);
+        assert!(locks.is_empty());
+
+        let guard = locks.lock(&1).await;
+        let timed_out = tokio::time::timeout(Duration::from_millis(10), locks.lock(&1)).await;
+        assert!(timed_out.is_err());
+        drop(guard);
+        assert!(locks.is_empty());
+    }
+}
@@ -14,6 +14,8 @@ By default, the function-cache is **not** locked for the duration of the functio
 concurrent calls of long-running functions with the same arguments will each execute fully and each overwrite
 the memoized value as they complete. This mirrors the behavior of Python's `functools.lru_cache`. To synchronize the execution and caching
 of un-cached arguments, specify `#[cached(sync_writes = true)]` / `#[once(sync_writes = true)]` (not supported by `#[io_cached]`.
+To only synchronize concurrent calls with the same arguments, and let calls with other arguments run in parallel,
+specify `#[cached(sync_writes = "by_key")]`.
 
 - See [`cached::stores` docs](https://docs.rs/cached/latest/cached/stores/index.html) cache stores available.
 - See [`proc_macro`](https://docs.rs/cached/latest/cached/proc_macro/index.html) for more procedural macro examples.
@@ -29,10 +31,17 @@ of un-cached arguments, specify `#[cached(sync_writes = true)]` / `#[once(sync_w
 - `redis_async_std`: Include async Redis support using `async-std` and `async-std` tls support, implies `redis_store` and `async`
 - `redis_tokio`: Include async Redis support using `tokio` and `tokio` tls support, implies `redis_store` and `async`
 - `redis_connection_manager`: Enable the optional `connection-manager` feature of `redis`. Any async redis caches created
-                              will use a connection manager instead of a `MultiplexedConnection`
+  will use a connection manager instead of a `MultiplexedConnection`
 - `redis_ahash`: Enable the optional `ahash` feature of `redis`
+- `redis_bincode`: Include `BincodeSerializer` to store Redis values with `bincode`, implies `redis_store`
+- `redis_msgpack`: Include `MessagePackSerializer` to store Redis values as MessagePack, implies `redis_store`
+- `redis_cbor`: Include `CborSerializer` to store Redis values as CBOR, implies `redis_store`
+- `redis_postcard`: Include `PostcardSerializer` to store Redis values with `postcard`, implies `redis_store`
+- `redis_zstd`: Include `Compression::Zstd` to compress Redis values with `zstd`, implies `redis_store`
+- `redis_lz4`: Include `Compression::Lz4` to compress Redis values with `lz4_flex`, implies `redis_store`
+- `redis_gzip`: Include `Compression::Gzip` to compress Redis values with gzip, implies `redis_store`
 - `wasm`: Enable WASM support. Note that this feature is incompatible with `tokio`'s multi-thread
-   runtime (`async_tokio_rt_multi_thread`) and all Redis features (`redis_store`, `redis_async_std`, `redis_tokio`, `redis_ahash`)
+  runtime (`async_tokio_rt_multi_thread`) and all Redis features (`redis_store`, `redis_async_std`, `redis_tokio`, `redis_ahash`)
 
 The procedural macros (`#[cached]`, `#[once]`, `#[io_cached]`) offer more features, including async support.
 See the [`proc_macro`](crate::proc_macro) and [`macros`](crate::macros) modules for more samples, and the
@@ -69,9 +78,9 @@ use cached::SizedCache;
 
 /// Use an explicit cache-type with a custom creation block and custom cache-key generating block
 #[cached(
-    type = "SizedCache<String, usize>",
-    create = "{ SizedCache::with_size(100) }",
-    convert = r#"{ format!("{}{}", a, b) }"#
+    type = SizedCache<String, usize>,
+    create = { SizedCache::with_size(100) },
+    convert = { format!("{}{}", a, b) }
 )]
 fn keyed(a: &str, b: &str) -> usize {
     let size = a.len() + b.len();
@@ -122,15 +131,15 @@ enum ExampleError {
 /// redis cache errors into the same type of error returned
 /// by your function. All `io_cached` functions must return `Result`s.
 #[io_cached(
-    map_error = r##"|e| ExampleError::RedisError(format!("{:?}", e))"##,
-    type = "AsyncRedisCache<u64, String>",
-    create = r##" {
+    map_error = |e| ExampleError::RedisError(format!("{:?}", e)),
+    type = AsyncRedisCache<u64, String>,
+    create = {
         AsyncRedisCache::new("cached_redis_prefix", 1)
             .set_refresh(true)
             .build()
             .await
             .expect("error building example redis cache")
-    } "##
+    }
 )]
 async fn async_cached_sleep_secs(secs: u64) -> Result<String, ExampleError> {
     std::thread::sleep(std::time::Duration::from_secs(secs));
@@ -161,9 +170,13 @@ Due to the requirements of storing arguments and return values in a global cache
 - Arguments and return values will be `cloned` in the process of insertion and retrieval. Except for Redis
   where arguments are formatted into `Strings` and values are de/serialized.
 - Macro-defined functions should not be used to produce side-effectual results!
-- Macro-defined functions cannot live directly under `impl` blocks since macros expand to a
-  `once_cell` initialization and one or more function definitions.
-- Macro-defined functions cannot accept `Self` types as a parameter.
+- Macro-defined functions can live under `impl` blocks. Since statics can't be declared there, the cache is
+  reached through an associated function named like the cache, or stored in a field of `self` with `cache_field`.
+  Methods taking `self` must either ignore it (`ignore_self = true`), key on its fields with `convert`,
+  or use a per-instance `cache_field`.
+- Macro-defined functions in `impl` blocks cannot use `Self` or generic parameters of the block in their key or value types.
+- Generic `#[cached]` and `#[io_cached]` functions (with type parameters or `impl Trait` arguments) must set `key` and `convert`
+  to build a cache key of a concrete type. The return type of generic functions cannot depend on the generic parameters.
 
 
 */
@@ -173,9 +186,18 @@ Due to the requirements of storing arguments and return values in a global cache
 #[doc(hidden)]
 pub extern crate once_cell;
 
+pub use clock::{Clock, MockClock, SystemClock};
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub use key_lock::AsyncKeyLocks;
+pub use key_lock::KeyLocks;
 #[cfg(feature = "proc_macro")]
 #[cfg_attr(docsrs, doc(cfg(feature = "proc_macro")))]
 pub use proc_macro::Return;
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub use reaper::AsyncReaper;
+pub use reaper::Reaper;
 #[cfg(any(feature = "redis_async_std", feature = "redis_tokio"))]
 #[cfg_attr(
     docsrs,
@@ -183,7 +205,8 @@ pub use proc_macro::Return;
 )]
 pub use stores::AsyncRedisCache;
 pub use stores::{
-    CanExpire, ExpiringValueCache, SizedCache, TimedCache, TimedSizedCache, UnboundCache,
+    ArcCache, CanExpire, ExpiringValueCache, LfuCache, ShardedCache, SizedCache, TimedCache,
+    TimedSizedCache, TinyLfuCache, TtlCache, UnboundCache, WeightedCache,
 };
 #[cfg(feature = "redis_store")]
 #[cfg_attr(docsrs, doc(cfg(feature = "redis_store")))]
@@ -192,10 +215,15 @@ pub use stores::{RedisCache, RedisCacheError};
 #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
 use {async_trait::async_trait, futures::Future};
 
+use std::time::Duration;
+
+pub mod clock;
+pub mod key_lock;
 mod lru_list;
 pub mod macros;
 #[cfg(feature = "proc_macro")]
 pub mod proc_macro;
+pub mod reaper;
 pub mod stores;
 #[doc(hidden)]
 pub use instant;
@@ -206,6 +234,7 @@ pub mod async_sync {
     pub use tokio::sync::Mutex;
     pub use tokio::sync::OnceCell;
     pub use tokio::sync::RwLock;
+    pub use tokio::task::spawn;
 }
 
 /// Cache operations
@@ -287,6 +316,28 @@ pub trait Cached<K, V> {
         K: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq + ?Sized;
 
+    /// Return whether a value is cached for a key
+    ///
+    /// Stores override this to check without counting a hit or miss
+    /// and without changing the eviction order. The default looks
+    /// the key up with `cache_get`.
+    ///
+    /// ```rust
+    /// # use cached::{Cached, UnboundCache};
+    /// let mut cache = UnboundCache::new();
+    /// cache.cache_set("key".to_string(), 1);
+    ///
+    /// assert!(cache.cache_contains("key"));
+    /// assert!(!cache.cache_contains("other"));
+    /// ```
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.cache_get(k).is_some()
+    }
+
     /// Remove all cached values. Keeps the allocated memory for reuse.
     fn cache_clear(&mut self);
 
@@ -296,6 +347,9 @@ pub trait Cached<K, V> {
     /// Reset misses/hits counters
     fn cache_reset_metrics(&mut self) {}
 
+    /// Remove any expired values from the cache
+    fn cache_flush(&mut self) {}
+
     /// Return the current cache size (number of elements)
     fn cache_size(&self) -> usize;
 
@@ -314,6 +368,11 @@ pub trait Cached<K, V> {
         None
     }
 
+    /// Return the current total weight of cached values, for caches bounded by weight
+    fn cache_weight(&self) -> Option<usize> {
+        None
+    }
+
     /// Return the lifespan of cached values (time to eviction)
     fn cache_lifespan(&self) -> Option<u64> {
         None
@@ -323,6 +382,21 @@ pub trait Cached<K, V> {
     fn cache_set_lifespan(&mut self, _seconds: u64) -> Option<u64> {
         None
     }
+
+    /// Return the lifespan of cached values (time to eviction) as a `Duration`
+    ///
+    /// Defaults to `cache_lifespan` in whole seconds
+    fn cache_lifespan_duration(&self) -> Option<Duration> {
+        self.cache_lifespan().map(Duration::from_secs)
+    }
+
+    /// Set the lifespan of cached values as a `Duration`, returns the old value
+    ///
+    /// Defaults to `cache_set_lifespan`, truncating to whole seconds
+    fn cache_set_lifespan_duration(&mut self, lifespan: Duration) -> Option<Duration> {
+        self.cache_set_lifespan(lifespan.as_secs())
+            .map(Duration::from_secs)
+    }
 }
 
 #[cfg(feature = "async")]
@@ -342,6 +416,72 @@ pub trait CachedAsync<K, V> {
         Fut: Future<Output = Result<V, E>> + Send;
 }
 
+/// Cache operations on a store that synchronizes access internally
+///
+/// Unlike [`Cached`], all operations take `&self` so a store can be shared
+/// between threads without wrapping it in a lock. Values are returned by clone.
+///
+/// ```rust
+/// use cached::{ConcurrentCached, ShardedCache, SizedCache};
+///
+/// let cache = ShardedCache::with_shards(4, || SizedCache::with_size(16));
+/// cache.cache_set(1, "one".to_string());
+/// assert_eq!(cache.cache_get(&1), Some("one".to_string()));
+/// ```
+pub trait ConcurrentCached<K, V> {
+    /// Attempt to retrieve a copy of a cached value
+    fn cache_get<Q>(&self, k: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+        V: Clone;
+
+    /// Insert a key, value pair and return the previous value
+    fn cache_set(&self, k: K, v: V) -> Option<V>;
+
+    /// Get or insert a key, value pair and return a copy of the value
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&self, k: K, f: F) -> V
+    where
+        V: Clone;
+
+    /// Remove a cached value
+    fn cache_remove<Q>(&self, k: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized;
+
+    /// Return whether a value is cached for a key
+    fn cache_contains<Q>(&self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized;
+
+    /// Remove all cached values. Keeps the allocated memory for reuse.
+    fn cache_clear(&self);
+
+    /// Remove all cached values. Free memory and return to initial state
+    fn cache_reset(&self);
+
+    /// Reset misses/hits counters
+    fn cache_reset_metrics(&self) {}
+
+    /// Remove any expired values from the cache
+    fn cache_flush(&self) {}
+
+    /// Return the current cache size (number of elements)
+    fn cache_size(&self) -> usize;
+
+    /// Return the number of times a cached value was successfully retrieved
+    fn cache_hits(&self) -> Option<u64> {
+        None
+    }
+
+    /// Return the number of times a cached value was unable to be retrieved
+    fn cache_misses(&self) -> Option<u64> {
+        None
+    }
+}
+
 /// Cache operations on an io-connected store
 pub trait IOCached<K, V> {
     type Error;
@@ -367,6 +507,39 @@ pub trait IOCached<K, V> {
     /// Should return `Self::Error` if the operation fails
     fn cache_remove(&self, k: &K) -> Result<Option<V>, Self::Error>;
 
+    /// Return whether a value is cached for a key
+    ///
+    /// Defaults to looking the key up with `cache_get`
+    ///
+    /// # Errors
+    ///
+    /// Should return `Self::Error` if the operation fails
+    fn cache_contains(&self, k: &K) -> Result<bool, Self::Error> {
+        Ok(self.cache_get(k)?.is_some())
+    }
+
+    /// Remove all cached values, returning how many were removed
+    ///
+    /// Defaults to `None` for stores that can't be cleared
+    ///
+    /// # Errors
+    ///
+    /// Should return `Self::Error` if the operation fails
+    fn cache_clear(&self) -> Result<Option<usize>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Return the number of cached values
+    ///
+    /// Defaults to `None` for stores that can't count their values
+    ///
+    /// # Errors
+    ///
+    /// Should return `Self::Error` if the operation fails
+    fn cache_size(&self) -> Result<Option<usize>, Self::Error> {
+        Ok(None)
+    }
+
     /// Set the flag to control whether cache hits refresh the ttl of cached values, returns the old flag value
     fn cache_set_refresh(&mut self, refresh: bool) -> bool;
 
@@ -379,6 +552,21 @@ pub trait IOCached<K, V> {
     fn cache_set_lifespan(&mut self, _seconds: u64) -> Option<u64> {
         None
     }
+
+    /// Return the lifespan of cached values (time to eviction) as a `Duration`
+    ///
+    /// Defaults to `cache_lifespan` in whole seconds
+    fn cache_lifespan_duration(&self) -> Option<Duration> {
+        self.cache_lifespan().map(Duration::from_secs)
+    }
+
+    /// Set the lifespan of cached values as a `Duration`, returns the old value
+    ///
+    /// Defaults to `cache_set_lifespan`, truncating to whole seconds
+    fn cache_set_lifespan_duration(&mut self, lifespan: Duration) -> Option<Duration> {
+        self.cache_set_lifespan(lifespan.as_secs())
+            .map(Duration::from_secs)
+    }
 }
 
 #[cfg(feature = "async")]
@@ -393,6 +581,30 @@ pub trait IOCachedAsync<K, V> {
     /// Remove a cached value
     async fn cache_remove(&self, k: &K) -> Result<Option<V>, Self::Error>;
 
+    /// Return whether a value is cached for a key
+    ///
+    /// Defaults to looking the key up with `cache_get`
+    async fn cache_contains(&self, k: &K) -> Result<bool, Self::Error>
+    where
+        K: Sync,
+    {
+        Ok(self.cache_get(k).await?.is_some())
+    }
+
+    /// Remove all cached values, returning how many were removed
+    ///
+    /// Defaults to `None` for stores that can't be cleared
+    async fn cache_clear(&self) -> Result<Option<usize>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Return the number of cached values
+    ///
+    /// Defaults to `None` for stores that can't count their values
+    async fn cache_size(&self) -> Result<Option<usize>, Self::Error> {
+        Ok(None)
+    }
+
     /// Set the flag to control whether cache hits refresh the ttl of cached values, returns the old flag value
     fn cache_set_refresh(&mut self, refresh: bool) -> bool;
 
@@ -405,4 +617,19 @@ pub trait IOCachedAsync<K, V> {
     fn cache_set_lifespan(&mut self, _seconds: u64) -> Option<u64> {
         None
     }
+
+    /// Return the lifespan of cached values (time to eviction) as a `Duration`
+    ///
+    /// Defaults to `cache_lifespan` in whole seconds
+    fn cache_lifespan_duration(&self) -> Option<Duration> {
+        self.cache_lifespan().map(Duration::from_secs)
+    }
+
+    /// Set the lifespan of cached values as a `Duration`, returns the old value
+    ///
+    /// Defaults to `cache_set_lifespan`, truncating to whole seconds
+    fn cache_set_lifespan_duration(&mut self, lifespan: Duration) -> Option<Duration> {
+        self.cache_set_lifespan(lifespan.as_secs())
+            .map(Duration::from_secs)
+    }
 }
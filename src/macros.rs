@@ -445,7 +445,8 @@ macro_rules! cached_control {
         static $cachename: $crate::once_cell::sync::Lazy<::std::sync::Mutex<$cachetype>>
             = $crate::once_cell::sync::Lazy::new(|| ::std::sync::Mutex::new($cacheinstance));
 
-        #[allow(unused_parens)]
+        // `PostExec` is usually a `match` that returns early on errors
+        #[allow(unused_parens, clippy::question_mark)]
         pub fn $name($($arg: $argtype),*) -> $ret {
             let key = $key;
             {
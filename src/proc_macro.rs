@@ -76,6 +76,61 @@ fn keyed(a: String, b: String) -> usize {
 ```rust,no_run
 use cached::proc_macro::cached;
 
+/// Use a timed cache with a TTL of 60s,
+/// removing expired values every 30s in the background
+#[cached(time=60, flush_interval=30)]
+fn lookup(id: u64) -> String {
+    format!("user {}", id)
+}
+# pub fn main() { }
+```
+
+----
+
+```rust
+use cached::proc_macro::cached;
+use cached::{Cached, UnboundCache};
+use std::sync::Mutex;
+
+struct Client {
+    region: String,
+    cache: Mutex<UnboundCache<u64, String>>,
+}
+
+impl Client {
+    /// Cache of the associated function is reached through `Client::PARSE()`
+    #[cached(in_impl = true)]
+    fn parse(id: String) -> u64 {
+        id.parse().unwrap_or_default()
+    }
+
+    /// Share one cache between all clients, keyed on a field of `self`
+    #[cached(key = (String, u64), convert = { (self.region.clone(), id) })]
+    fn endpoint(&self, id: u64) -> String {
+        format!("https://{}.example.com/{}", self.region, id)
+    }
+
+    /// Use a cache per client
+    #[cached(cache_field = cache)]
+    fn name(&self, id: u64) -> String {
+        format!("user {}", id)
+    }
+}
+
+# pub fn main() {
+let client = Client { region: "eu".to_string(), cache: Mutex::new(UnboundCache::new()) };
+assert_eq!(Client::parse("42".to_string()), 42);
+assert_eq!(client.endpoint(42), "https://eu.example.com/42");
+assert_eq!(client.name(42), "user 42");
+assert_eq!(client.cache.lock().unwrap().cache_size(), 1);
+# }
+```
+
+----
+
+```rust,no_run
+use cached::proc_macro::cached;
+
 # fn do_something_fallible() -> std::result::Result<(), ()> {
 #     Ok(())
 # }
@@ -210,9 +265,9 @@ use cached::SizedCache;
 
 /// Use an explicit cache-type with a custom creation block and custom cache-key generating block
 #[cached(
-    type = "SizedCache<String, usize>",
-    create = "{ SizedCache::with_size(100) }",
-    convert = r#"{ format!("{}{}", a, b) }"#
+    type = SizedCache<String, usize>,
+    create = { SizedCache::with_size(100) },
+    convert = { format!("{}{}", a, b) }
 )]
 fn keyed(a: &str, b: &str) -> usize {
     let size = a.len() + b.len();
@@ -253,7 +308,7 @@ use cached::proc_macro::cached;
 
 /// Use a timed cache with a TTL of 60s.
 /// Run a background thread to continuously refresh a specific key.
-#[cached(time = 60, key = "String", convert = r#"{ String::from(a) }"#)]
+#[cached(time = 60, key = String, convert = { String::from(a) })]
 fn keyed(a: &str) -> usize {
     a.len()
 }
@@ -302,7 +357,7 @@ use std::time::Duration;
 use cached::proc_macro::cached;
 
 /// Run a background thread to continuously refresh every key of a cache
-#[cached(key = "String", convert = r#"{ String::from(a) }"#)]
+#[cached(key = String, convert = { String::from(a) })]
 fn keyed(a: &str) -> usize {
     a.len()
 }
@@ -324,6 +379,38 @@ pub fn main() {
 }
 ```
 
+----
+
+```rust
+use cached::proc_macro::cached;
+
+/// Invalidate the cached value of a user after it's updated
+#[cached(size = 100, key = u32, convert = { user.id })]
+fn user_name(user: &User) -> String {
+    // do some long database request
+    user.name.clone()
+}
+
+struct User {
+    id: u32,
+    name: String,
+}
+
+pub fn main() {
+    let mut user = User { id: 1, name: "ferris".to_string() };
+    assert_eq!(user_name(&user), "ferris");
+
+    user.name = "crab".to_string();
+    // these methods are generated by the `cached` macro, building the key with the same `convert` block
+    assert!(user_name_cache_contains(&user));
+    assert_eq!(user_name_cache_remove(&user), Some("ferris".to_string()));
+    assert_eq!(user_name(&user), "crab");
+
+    user_name_cache_clear();
+    assert!(!user_name_cache_contains(&user));
+}
+```
+
 
 */
 
@@ -0,0 +1,297 @@
+/*!
+Background removal of expired cache values
+
+Timed stores only drop expired values when they are looked up or flushed,
+so keys that are never read again stay in memory. A [`Reaper`] periodically
+flushes a cache from a background thread until it is dropped. With the
+`async` feature, an [`AsyncReaper`] does the same from a tokio task.
+
+```rust
+use cached::{Cached, Reaper, TimedCache};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+let cache = Arc::new(Mutex::new(TimedCache::with_lifespan_duration(
+    Duration::from_millis(10),
+)));
+let reaper = Reaper::flush_cache(&cache, Duration::from_millis(20));
+cache.lock().unwrap().cache_set(1, 100);
+std::thread::sleep(Duration::from_millis(100));
+assert_eq!(cache.lock().unwrap().cache_size(), 0);
+drop(reaper);
+```
+*/
+
+use crate::Cached;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Periodically runs a flush on a background thread
+///
+/// The thread stops once the `Reaper` is dropped, or once the flush
+/// function returns `false`.
+#[derive(Debug)]
+#[must_use = "the background thread stops when the `Reaper` is dropped"]
+pub struct Reaper {
+    stop: Sender<()>,
+}
+
+impl Reaper {
+    /// Spawns a thread calling `flush` every `interval`
+    pub fn spawn<F>(interval: Duration, mut flush: F) -> Reaper
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                if !flush() {
+                    break;
+                }
+            }
+        });
+        Reaper { stop }
+    }
+
+    /// Spawns a thread calling [`Cached::cache_flush`] on `cache` every `interval`
+    ///
+    /// The thread only holds a weak reference and stops once the cache is
+    /// dropped or its lock is poisoned.
+    pub fn flush_cache<K, V, C>(cache: &Arc<Mutex<C>>, interval: Duration) -> Reaper
+    where
+        C: Cached<K, V> + Send + 'static,
+    {
+        let cache = Arc::downgrade(cache);
+        Reaper::spawn(interval, move || {
+            let cache = match cache.upgrade() {
+                Some(cache) => cache,
+                None => return false,
+            };
+            let lock = cache.lock();
+            match lock {
+                Ok(mut cache) => {
+                    cache.cache_flush();
+                    true
+                }
+                Err(_) => false,
+            }
+        })
+    }
+
+    /// Let the background thread run for as long as the process does
+    pub fn detach(self) {
+        std::mem::forget(self.stop);
+    }
+}
+
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub use self::async_reaper::AsyncReaper;
+
+#[cfg(feature = "async")]
+mod async_reaper {
+    use crate::Cached;
+    use futures::Future;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Mutex;
+    use tokio::task::JoinHandle;
+    use tokio::time::MissedTickBehavior;
+
+    /// Periodically runs a flush on a tokio task
+    ///
+    /// The task is aborted once the `AsyncReaper` is dropped, and stops
+    /// once the flush function returns `false`. Must be created from
+    /// within a tokio runtime.
+    #[derive(Debug)]
+    #[must_use = "the background task is aborted when the `AsyncReaper` is dropped"]
+    pub struct AsyncReaper {
+        handle: Option<JoinHandle<()>>,
+    }
+
+    impl AsyncReaper {
+        /// Spawns a task awaiting `flush` every `interval`
+        ///
+        /// # Panics
+        ///
+        /// Will panic if called outside of a tokio runtime or if `interval` is zero
+        pub fn spawn<F, Fut>(interval: Duration, mut flush: F) -> AsyncReaper
+        where
+            F: FnMut() -> Fut + Send + 'static,
+            Fut: Future<Output = bool> + Send,
+        {
+            let handle = tokio::spawn(async move {
+                let mut ticks = tokio::time::interval(interval);
+                ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                // the first tick completes immediately
+                ticks.tick().await;
+                loop {
+                    ticks.tick().await;
+                    if !flush().await {
+                        break;
+                    }
+                }
+            });
+            AsyncReaper {
+                handle: Some(handle),
+            }
+        }
+
+        /// Spawns a task calling [`Cached::cache_flush`] on `cache` every `interval`
+        ///
+        /// The task only holds a weak reference and stops once the cache is dropped.
+        ///
+        /// # Panics
+        ///
+        /// Will panic if called outside of a tokio runtime or if `interval` is zero
+        pub fn flush_cache<K, V, C>(cache: &Arc<Mutex<C>>, interval: Duration) -> AsyncReaper
+        where
+            C: Cached<K, V> + Send + 'static,
+        {
+            let cache = Arc::downgrade(cache);
+            AsyncReaper::spawn(interval, move || {
+                let cache = cache.upgrade();
+                async move {
+                    match cache {
+                        Some(cache) => {
+                            cache.lock().await.cache_flush();
+                            true
+                        }
+                        None => false,
+                    }
+                }
+            })
+        }
+
+        /// Let the background task run for as long as the runtime does
+        pub fn detach(mut self) {
+            self.handle.take();
+        }
+    }
+
+    impl Drop for AsyncReaper {
+        fn drop(&mut self) {
+            if let Some(handle) = self.handle.take() {
+                handle.abort();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+/// Reaper tests
+mod tests {
+    use super::*;
+    use crate::{MockClock, TimedCache};
+    use std::time::Instant;
+
+    // generous bounds, the background thread may be slow to run on a loaded machine
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn eventually(done: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while !done() {
+            if start.elapsed() > TIMEOUT {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        true
+    }
+
+    #[test]
+    fn flushes_expired_values() {
+        let clock = MockClock::new();
+        let cache = Arc::new(Mutex::new(TimedCache::with_lifespan_duration_and_clock(
+            Duration::from_secs(2),
+            clock.clone(),
+        )));
+        let (flushed, flushes) = mpsc::channel();
+        let _reaper = {
+            let cache = Arc::downgrade(&cache);
+            Reaper::spawn(Duration::from_millis(1), move || {
+                let cache = cache.upgrade().unwrap();
+                cache.lock().unwrap().cache_flush();
+                flushed.send(()).is_ok()
+            })
+        };
+        cache.lock().unwrap().cache_set(1, 100);
+        flushes.recv_timeout(TIMEOUT).unwrap();
+        flushes.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(cache.lock().unwrap().cache_size(), 1);
+
+        clock.advance(Duration::from_secs(2));
+        assert!(eventually(|| cache.lock().unwrap().cache_size() == 0));
+    }
+
+    #[test]
+    fn flush_cache_stops_when_dropped() {
+        let cache = Arc::new(Mutex::new(TimedCache::<u32, u32>::with_lifespan(1)));
+        let reaper = Reaper::flush_cache(&cache, Duration::from_millis(1));
+        let weak = Arc::downgrade(&cache);
+        drop(cache);
+        // the thread only held a weak reference
+        assert!(weak.upgrade().is_none());
+        drop(reaper);
+    }
+
+    #[test]
+    fn stops_on_drop() {
+        let (called, calls) = mpsc::channel();
+        let reaper = Reaper::spawn(Duration::from_millis(1), move || called.send(()).is_ok());
+        for _ in 0..3 {
+            calls.recv_timeout(TIMEOUT).unwrap();
+        }
+        drop(reaper);
+        // the thread stops and drops the flush function, disconnecting the
+        // channel, after at most the flush that was already running
+        let mut after_drop = 0;
+        let stopped = loop {
+            match calls.recv_timeout(TIMEOUT) {
+                Ok(()) if after_drop < 2 => after_drop += 1,
+                result => break result,
+            }
+        };
+        assert_eq!(stopped, Err(RecvTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn stops_when_flush_returns_false() {
+        let (called, calls) = mpsc::channel();
+        let mut count = 0;
+        let _reaper = Reaper::spawn(Duration::from_millis(1), move || {
+            called.send(()).unwrap();
+            count += 1;
+            count < 3
+        });
+        for _ in 0..3 {
+            calls.recv_timeout(TIMEOUT).unwrap();
+        }
+        assert_eq!(
+            calls.recv_timeout(TIMEOUT),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_flushes_expired_values() {
+        let clock = MockClock::new();
+        let cache = Arc::new(tokio::sync::Mutex::new(
+            TimedCache::with_lifespan_duration_and_clock(Duration::from_secs(2), clock.clone()),
+        ));
+        let reaper = AsyncReaper::flush_cache(&cache, Duration::from_millis(1));
+        cache.lock().await.cache_set(1, 100);
+        clock.advance(Duration::from_secs(2));
+        let flushed = tokio::time::timeout(TIMEOUT, async {
+            while cache.lock().await.cache_size() > 0 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await;
+        assert!(flushed.is_ok());
+        drop(reaper);
+    }
+}
@@ -0,0 +1,643 @@
+use super::Cached;
+use crate::lru_list::LRUList;
+use std::cmp::Eq;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[cfg(feature = "async")]
+use {super::CachedAsync, async_trait::async_trait, futures::Future};
+
+/// The list of an `ArcCache` a key currently lives in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum ArcList {
+    /// Keys seen once recently, with their values
+    Recent,
+    /// Keys seen at least twice recently, with their values
+    Frequent,
+    /// Keys recently evicted from `Recent`, without values
+    RecentGhost,
+    /// Keys recently evicted from `Frequent`, without values
+    FrequentGhost,
+}
+
+/// Adaptive Replacement Cache
+///
+/// Stores up to a specified size, split between a list of keys that
+/// were used once recently and a list of keys that were used more than
+/// once. Keys evicted from either list are remembered (without their
+/// values) for a while. Setting a value for one of those "ghost" keys
+/// shifts the split towards the list it was evicted from, so the cache
+/// tunes itself between LRU and LFU behavior as the workload changes.
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct ArcCache<K, V> {
+    // `store` contains K -> the list and index of the key in that list
+    pub(super) store: HashMap<K, (ArcList, usize)>,
+    pub(super) recent: LRUList<(K, V)>,
+    pub(super) frequent: LRUList<(K, V)>,
+    pub(super) recent_ghosts: LRUList<K>,
+    pub(super) frequent_ghosts: LRUList<K>,
+    pub(super) recent_len: usize,
+    pub(super) frequent_len: usize,
+    pub(super) recent_ghosts_len: usize,
+    pub(super) frequent_ghosts_len: usize,
+    // target size of the `recent` list
+    pub(super) target: usize,
+    pub(super) capacity: usize,
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> ArcCache<K, V> {
+    /// Creates a new `ArcCache` with a given size limit and pre-allocated backing data
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0
+    #[must_use]
+    pub fn with_size(size: usize) -> ArcCache<K, V> {
+        if size == 0 {
+            panic!("`size` of `ArcCache` must be greater than zero.");
+        }
+        ArcCache::with_store(size, HashMap::with_capacity(size * 2))
+    }
+
+    /// Creates a new `ArcCache` with a given size limit and pre-allocated backing data
+    ///
+    /// # Errors
+    ///
+    /// Will return a `std::io::Error`, depending on the error
+    pub fn try_with_size(size: usize) -> std::io::Result<ArcCache<K, V>> {
+        if size == 0 {
+            // EINVAL
+            return Err(std::io::Error::from_raw_os_error(22));
+        }
+
+        // the store also holds the keys of both ghost lists
+        let capacity = match size.checked_mul(2) {
+            Some(capacity) => capacity,
+            // EINVAL
+            None => return Err(std::io::Error::from_raw_os_error(22)),
+        };
+        let mut store = HashMap::new();
+        if store.try_reserve(capacity).is_err() {
+            // ENOMEM
+            return Err(std::io::Error::from_raw_os_error(12));
+        }
+
+        Ok(ArcCache::with_store(size, store))
+    }
+
+    fn with_store(size: usize, store: HashMap<K, (ArcList, usize)>) -> ArcCache<K, V> {
+        ArcCache {
+            store,
+            recent: LRUList::with_capacity(size),
+            frequent: LRUList::with_capacity(size),
+            recent_ghosts: LRUList::with_capacity(size),
+            frequent_ghosts: LRUList::with_capacity(size),
+            recent_len: 0,
+            frequent_len: 0,
+            recent_ghosts_len: 0,
+            frequent_ghosts_len: 0,
+            target: 0,
+            capacity: size,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn iter_order(&self) -> impl Iterator<Item = &(K, V)> {
+        self.frequent.iter().chain(self.recent.iter())
+    }
+
+    /// Return an iterator of keys in the current order: keys used more than
+    /// once from most to least recently used, followed by keys used only once
+    /// from most to least recently used.
+    pub fn key_order(&self) -> impl Iterator<Item = &K> {
+        self.iter_order().map(|(k, _v)| k)
+    }
+
+    /// Return an iterator of values in the same order as `key_order`
+    pub fn value_order(&self) -> impl Iterator<Item = &V> {
+        self.iter_order().map(|(_k, v)| v)
+    }
+
+    /// Return the number of entries the cache currently aims to keep
+    /// for keys that were only used once. The remainder of the capacity
+    /// is reserved for keys used more than once.
+    #[must_use]
+    pub fn recent_target(&self) -> usize {
+        self.target
+    }
+
+    // move the least recently used entry of `from` to the front of its ghost list,
+    // dropping the value
+    fn demote(&mut self, from: ArcList) {
+        let (list, ghosts, ghost) = match from {
+            ArcList::Recent => (
+                &mut self.recent,
+                &mut self.recent_ghosts,
+                ArcList::RecentGhost,
+            ),
+            _ => (
+                &mut self.frequent,
+                &mut self.frequent_ghosts,
+                ArcList::FrequentGhost,
+            ),
+        };
+        let (key, _value) = list.remove(list.back());
+        let location = self
+            .store
+            .get_mut(&key)
+            .expect("ArcCache key is not stored");
+        *location = (ghost, ghosts.push_front(key));
+        if from == ArcList::Recent {
+            self.recent_len -= 1;
+            self.recent_ghosts_len += 1;
+        } else {
+            self.frequent_len -= 1;
+            self.frequent_ghosts_len += 1;
+        }
+    }
+
+    // forget the least recently evicted key of a ghost list
+    fn forget(&mut self, ghost: ArcList) {
+        let key = if ghost == ArcList::RecentGhost {
+            if self.recent_ghosts_len == 0 {
+                return;
+            }
+            self.recent_ghosts_len -= 1;
+            self.recent_ghosts.remove(self.recent_ghosts.back())
+        } else {
+            if self.frequent_ghosts_len == 0 {
+                return;
+            }
+            self.frequent_ghosts_len -= 1;
+            self.frequent_ghosts.remove(self.frequent_ghosts.back())
+        };
+        self.store.remove(&key);
+    }
+
+    // make room for a new entry by demoting an entry of the list that is over its target
+    fn replace(&mut self, in_frequent_ghosts: bool) {
+        if self.recent_len + self.frequent_len < self.capacity {
+            return;
+        }
+        let prefer_recent =
+            self.recent_len > self.target || (in_frequent_ghosts && self.recent_len == self.target);
+        if self.recent_len > 0 && (prefer_recent || self.frequent_len == 0) {
+            self.demote(ArcList::Recent);
+        } else if self.frequent_len > 0 {
+            self.demote(ArcList::Frequent);
+        }
+    }
+
+    // record a hit on a resident key, moving it to the front of the `frequent` list
+    fn touch<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        let location = self.store.get_mut(key)?;
+        match *location {
+            (ArcList::Recent, index) => {
+                let entry = self.recent.remove(index);
+                self.recent_len -= 1;
+                let index = self.frequent.push_front(entry);
+                self.frequent_len += 1;
+                *location = (ArcList::Frequent, index);
+                Some(index)
+            }
+            (ArcList::Frequent, index) => {
+                self.frequent.move_to_front(index);
+                Some(index)
+            }
+            _ => None,
+        }
+    }
+
+    // insert a key that is not resident, returns its location
+    fn insert(&mut self, key: K, value: V) -> (ArcList, usize) {
+        match self.store.get(&key).copied() {
+            Some((ArcList::RecentGhost, index)) => {
+                let delta = (self.frequent_ghosts_len / self.recent_ghosts_len).max(1);
+                self.target = (self.target + delta).min(self.capacity);
+                self.replace(false);
+                self.recent_ghosts.remove(index);
+                self.recent_ghosts_len -= 1;
+                self.link_frequent(key, value)
+            }
+            Some((ArcList::FrequentGhost, index)) => {
+                let delta = (self.recent_ghosts_len / self.frequent_ghosts_len).max(1);
+                self.target = self.target.saturating_sub(delta);
+                self.replace(true);
+                self.frequent_ghosts.remove(index);
+                self.frequent_ghosts_len -= 1;
+                self.link_frequent(key, value)
+            }
+            _ => {
+                if self.recent_len + self.recent_ghosts_len >= self.capacity {
+                    if self.recent_len < self.capacity {
+                        self.forget(ArcList::RecentGhost);
+                        self.replace(false);
+                    } else {
+                        let (key, _value) = self.recent.remove(self.recent.back());
+                        self.recent_len -= 1;
+                        self.store.remove(&key);
+                    }
+                } else {
+                    let total = self.recent_len
+                        + self.frequent_len
+                        + self.recent_ghosts_len
+                        + self.frequent_ghosts_len;
+                    if total >= self.capacity {
+                        if total >= self.capacity * 2 {
+                            self.forget(ArcList::FrequentGhost);
+                        }
+                        self.replace(false);
+                    }
+                }
+                let index = self.recent.push_front((key.clone(), value));
+                self.recent_len += 1;
+                self.store.insert(key, (ArcList::Recent, index));
+                (ArcList::Recent, index)
+            }
+        }
+    }
+
+    fn link_frequent(&mut self, key: K, value: V) -> (ArcList, usize) {
+        let location = self
+            .store
+            .get_mut(&key)
+            .expect("ArcCache key is not stored");
+        let index = self.frequent.push_front((key, value));
+        self.frequent_len += 1;
+        *location = (ArcList::Frequent, index);
+        (ArcList::Frequent, index)
+    }
+
+    fn get_mut_at(&mut self, (list, index): (ArcList, usize)) -> &mut V {
+        match list {
+            ArcList::Recent => &mut self.recent.get_mut(index).1,
+            _ => &mut self.frequent.get_mut(index).1,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cached<K, V> for ArcCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        if let Some(index) = self.touch(key) {
+            self.hits += 1;
+            Some(&self.frequent.get(index).1)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        if let Some(index) = self.touch(key) {
+            self.hits += 1;
+            Some(&mut self.frequent.get_mut(index).1)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        if let Some(index) = self.touch(&key) {
+            Some(std::mem::replace(&mut self.frequent.get_mut(index).1, val))
+        } else {
+            self.insert(key, val);
+            None
+        }
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        if let Some(index) = self.touch(&key) {
+            self.hits += 1;
+            &mut self.frequent.get_mut(index).1
+        } else {
+            self.misses += 1;
+            let location = self.insert(key, f());
+            self.get_mut_at(location)
+        }
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        match self.store.get(k).copied()? {
+            (ArcList::Recent, index) => {
+                self.store.remove(k);
+                self.recent_len -= 1;
+                Some(self.recent.remove(index).1)
+            }
+            (ArcList::Frequent, index) => {
+                self.store.remove(k);
+                self.frequent_len -= 1;
+                Some(self.frequent.remove(index).1)
+            }
+            _ => None,
+        }
+    }
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        matches!(
+            self.store.get(k),
+            Some((ArcList::Recent | ArcList::Frequent, _))
+        )
+    }
+    fn cache_clear(&mut self) {
+        self.store.clear();
+        self.recent.clear();
+        self.frequent.clear();
+        self.recent_ghosts.clear();
+        self.frequent_ghosts.clear();
+        self.recent_len = 0;
+        self.frequent_len = 0;
+        self.recent_ghosts_len = 0;
+        self.frequent_ghosts_len = 0;
+        self.target = 0;
+    }
+    fn cache_reset(&mut self) {
+        // ArcCache uses cache_clear because capacity is fixed.
+        self.cache_clear();
+    }
+    fn cache_reset_metrics(&mut self) {
+        self.misses = 0;
+        self.hits = 0;
+    }
+    fn cache_size(&self) -> usize {
+        self.recent_len + self.frequent_len
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl<K, V> CachedAsync<K, V> for ArcCache<K, V>
+where
+    K: Hash + Eq + Clone + Send,
+{
+    async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+    {
+        if let Some(index) = self.touch(&k) {
+            self.hits += 1;
+            &mut self.frequent.get_mut(index).1
+        } else {
+            self.misses += 1;
+            let v = f().await;
+            let location = self.insert(k, v);
+            self.get_mut_at(location)
+        }
+    }
+
+    async fn try_get_or_set_with<F, Fut, E>(&mut self, k: K, f: F) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+    {
+        if let Some(index) = self.touch(&k) {
+            self.hits += 1;
+            Ok(&mut self.frequent.get_mut(index).1)
+        } else {
+            self.misses += 1;
+            let v = f().await?;
+            let location = self.insert(k, v);
+            Ok(self.get_mut_at(location))
+        }
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arc_cache() {
+        let mut c = ArcCache::with_size(3);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_misses(), Some(1));
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [3, 2, 1]);
+
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [1, 3, 2]);
+        assert_eq!(
+            c.value_order().copied().collect::<Vec<_>>(),
+            [100, 300, 200]
+        );
+
+        assert_eq!(c.cache_set(4, 400), None);
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [1, 4, 3]);
+
+        assert_eq!(c.cache_set(4, 401), Some(400));
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [4, 1, 3]);
+
+        c.cache_reset_metrics();
+        assert_eq!(c.cache_hits(), Some(0));
+        assert_eq!(c.cache_misses(), Some(0));
+        assert_eq!(c.cache_capacity(), Some(3));
+    }
+
+    #[test]
+    fn try_new() {
+        let c: std::io::Result<ArcCache<i32, i32>> = ArcCache::try_with_size(0);
+        assert_eq!(c.unwrap_err().raw_os_error(), Some(22));
+        let c: std::io::Result<ArcCache<i32, i32>> = ArcCache::try_with_size(usize::MAX);
+        assert_eq!(c.unwrap_err().raw_os_error(), Some(22));
+
+        let mut c = ArcCache::try_with_size(3).unwrap();
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_capacity(), Some(3));
+    }
+
+    #[test]
+    fn scan_does_not_flush_frequent_keys() {
+        let mut c = ArcCache::with_size(4);
+        for _ in 0..2 {
+            c.cache_get_or_set_with(1, || 1);
+            c.cache_get_or_set_with(2, || 2);
+        }
+        for n in 100..200 {
+            c.cache_set(n, n);
+        }
+        assert_eq!(c.cache_size(), 4);
+        assert_eq!(c.cache_get(&1), Some(&1));
+        assert_eq!(c.cache_get(&2), Some(&2));
+        assert!(c.cache_get(&100).is_none());
+    }
+
+    #[test]
+    fn adapts_to_recent_ghost_hits() {
+        let mut c = ArcCache::with_size(2);
+        assert_eq!(c.recent_target(), 0);
+
+        c.cache_set(1, 1);
+        c.cache_get(&1);
+        c.cache_set(2, 2);
+        c.cache_set(3, 3);
+        // 2 was evicted from the recency list and is remembered as a ghost
+        assert!(c.cache_get(&2).is_none());
+        assert_eq!(c.store.get(&2).map(|l| l.0), Some(ArcList::RecentGhost));
+
+        // re-setting a ghost grows the recency target
+        c.cache_set(2, 2);
+        assert_eq!(c.recent_target(), 1);
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.cache_get(&2), Some(&2));
+    }
+
+    #[test]
+    fn contains() {
+        let mut c = ArcCache::with_size(2);
+        c.cache_set(1, 1);
+        c.cache_get(&1);
+        c.cache_set(2, 2);
+        c.cache_set(3, 3);
+
+        assert!(c.cache_contains(&1));
+        assert!(c.cache_contains(&3));
+        // ghosts keep the key but not the value
+        assert!(!c.cache_contains(&2));
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(0));
+    }
+
+    #[test]
+    fn bounded_metadata() {
+        let mut c = ArcCache::with_size(5);
+        for n in 0..1000 {
+            c.cache_get_or_set_with(n % 13, || n);
+            c.cache_get_or_set_with(n % 7, || n);
+            assert!(c.cache_size() <= 5);
+            assert!(c.store.len() <= 10);
+            assert_eq!(
+                c.store.len(),
+                c.recent_len + c.frequent_len + c.recent_ghosts_len + c.frequent_ghosts_len
+            );
+        }
+    }
+
+    #[test]
+    fn clear() {
+        let mut c = ArcCache::with_size(3);
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+        c.cache_clear();
+
+        assert_eq!(0, c.cache_size());
+        assert!(c.key_order().next().is_none());
+    }
+
+    #[test]
+    fn remove() {
+        let mut c = ArcCache::with_size(3);
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+        assert_eq!(c.cache_get(&2), Some(&200));
+
+        assert_eq!(Some(100), c.cache_remove(&1));
+        assert_eq!(2, c.cache_size());
+
+        assert_eq!(Some(200), c.cache_remove(&2));
+        assert_eq!(1, c.cache_size());
+
+        assert_eq!(None, c.cache_remove(&2));
+        assert_eq!(1, c.cache_size());
+    }
+
+    #[test]
+    fn get_or_set_with() {
+        let mut c = ArcCache::with_size(5);
+
+        assert_eq!(c.cache_get_or_set_with(0, || 0), &0);
+        assert_eq!(c.cache_get_or_set_with(1, || 1), &1);
+        assert_eq!(c.cache_misses(), Some(2));
+
+        assert_eq!(c.cache_get_or_set_with(0, || 42), &0);
+        assert_eq!(c.cache_get_or_set_with(1, || 42), &1);
+        assert_eq!(c.cache_misses(), Some(2));
+        assert_eq!(c.cache_hits(), Some(2));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_trait() {
+        use crate::CachedAsync;
+        let mut c = ArcCache::with_size(5);
+
+        async fn _get(n: usize) -> usize {
+            n
+        }
+
+        assert_eq!(c.get_or_set_with(0, || async { _get(0).await }).await, &0);
+        assert_eq!(c.get_or_set_with(1, || async { _get(1).await }).await, &1);
+        assert_eq!(c.get_or_set_with(0, || async { _get(3).await }).await, &0);
+        assert_eq!(c.get_or_set_with(1, || async { _get(3).await }).await, &1);
+
+        c.cache_reset();
+        async fn _try_get(n: usize) -> Result<usize, String> {
+            if n < 10 {
+                Ok(n)
+            } else {
+                Err("dead".to_string())
+            }
+        }
+
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(10).await })
+            .await;
+        assert!(res.is_err());
+        assert!(c.key_order().next().is_none());
+
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(1).await })
+            .await;
+        assert_eq!(res.unwrap(), &1);
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(5).await })
+            .await;
+        assert_eq!(res.unwrap(), &1);
+    }
+}
@@ -126,12 +126,22 @@ impl<K: Hash + Eq + Clone, V: CanExpire> Cached<K, V> for ExpiringValueCache<K,
     {
         self.store.cache_remove(k)
     }
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store.peek(k).is_some_and(|v| !v.is_expired())
+    }
     fn cache_clear(&mut self) {
         self.store.cache_clear();
     }
     fn cache_reset(&mut self) {
         self.store.cache_reset();
     }
+    fn cache_flush(&mut self) {
+        self.flush();
+    }
     fn cache_size(&self) -> usize {
         self.store.cache_size()
     }
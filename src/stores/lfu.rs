@@ -0,0 +1,464 @@
+use super::Cached;
+use std::cmp::Eq;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+#[cfg(feature = "async")]
+use {super::CachedAsync, async_trait::async_trait, futures::Future};
+
+#[derive(Clone, Debug)]
+pub(super) struct LfuEntry<V> {
+    pub(super) value: V,
+    pub(super) frequency: u64,
+    pub(super) tick: u64,
+}
+
+/// Least Frequently Used Cache
+///
+/// Stores up to a specified size before beginning
+/// to evict the least frequently used keys. Ties between
+/// keys with the same use count are broken by evicting
+/// the least recently used one.
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct LfuCache<K, V> {
+    pub(super) store: HashMap<K, LfuEntry<V>>,
+    // `order` maps (frequency, tick) -> key, so the first entry
+    // is always the next one to be evicted
+    pub(super) order: BTreeMap<(u64, u64), K>,
+    pub(super) capacity: usize,
+    pub(super) tick: u64,
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LfuCache<K, V> {
+    /// Creates a new `LfuCache` with a given size limit and pre-allocated backing data
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0
+    #[must_use]
+    pub fn with_size(size: usize) -> LfuCache<K, V> {
+        if size == 0 {
+            panic!("`size` of `LfuCache` must be greater than zero.");
+        }
+        LfuCache {
+            store: HashMap::with_capacity(size),
+            order: BTreeMap::new(),
+            capacity: size,
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Creates a new `LfuCache` with a given size limit and pre-allocated backing data
+    ///
+    /// # Errors
+    ///
+    /// Will return a `std::io::Error`, depending on the error
+    pub fn try_with_size(size: usize) -> std::io::Result<LfuCache<K, V>> {
+        if size == 0 {
+            // EINVAL
+            return Err(std::io::Error::from_raw_os_error(22));
+        }
+
+        let mut store = HashMap::new();
+        if store.try_reserve(size).is_err() {
+            // ENOMEM
+            return Err(std::io::Error::from_raw_os_error(12));
+        }
+
+        Ok(LfuCache {
+            store,
+            order: BTreeMap::new(),
+            capacity: size,
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        })
+    }
+
+    /// Return an iterator of keys in the current order from most
+    /// to least frequently used.
+    pub fn key_order(&self) -> impl Iterator<Item = &K> {
+        self.order.values().rev()
+    }
+
+    /// Return an iterator of values in the current order from most
+    /// to least frequently used.
+    pub fn value_order(&self) -> impl Iterator<Item = &V> {
+        let store = &self.store;
+        self.order.values().rev().map(move |k| &store[k].value)
+    }
+
+    /// Return the number of times the value stored under `key` has been used,
+    /// counting the insertion as the first use.
+    pub fn frequency<Q>(&self, key: &Q) -> Option<u64>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store.get(key).map(|entry| entry.frequency)
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    // bump the use count of `key`, returns `false` if the key is not present
+    fn touch<Q>(&mut self, key: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.store.get_mut(key) {
+            Some(entry) => {
+                let k = self
+                    .order
+                    .remove(&(entry.frequency, entry.tick))
+                    .expect("LfuCache order is missing a stored key");
+                entry.frequency = entry.frequency.saturating_add(1);
+                entry.tick = tick;
+                self.order.insert((entry.frequency, entry.tick), k);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // insert a key that is known to not be present, evicting
+    // the least frequently used key if the store is full
+    fn insert_new(&mut self, key: K, value: V) -> &mut V {
+        if self.store.len() >= self.capacity {
+            if let Some((_, evicted)) = self.order.pop_first() {
+                self.store.remove(&evicted);
+            }
+        }
+        let tick = self.next_tick();
+        self.order.insert((1, tick), key.clone());
+        &mut self
+            .store
+            .entry(key)
+            .or_insert(LfuEntry {
+                value,
+                frequency: 1,
+                tick,
+            })
+            .value
+    }
+
+    /// Remove the entries for which `keep` returns `false`
+    ///
+    /// ```rust
+    /// use cached::{Cached, LfuCache};
+    ///
+    /// let mut cache = LfuCache::with_size(10);
+    /// cache.cache_set(1, "one");
+    /// cache.cache_set(2, "two");
+    /// cache.retain(|k, _v| *k > 1);
+    /// assert_eq!(cache.key_order().collect::<Vec<_>>(), [&2]);
+    /// ```
+    pub fn retain<F: Fn(&K, &V) -> bool>(&mut self, keep: F) {
+        let order = &mut self.order;
+        self.store.retain(|k, entry| {
+            let keep = keep(k, &entry.value);
+            if !keep {
+                order.remove(&(entry.frequency, entry.tick));
+            }
+            keep
+        });
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cached<K, V> for LfuCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        if self.touch(key) {
+            self.hits += 1;
+            self.store.get(key).map(|entry| &entry.value)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        if self.touch(key) {
+            self.hits += 1;
+            self.store.get_mut(key).map(|entry| &mut entry.value)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        if self.touch(&key) {
+            self.store
+                .get_mut(&key)
+                .map(|entry| std::mem::replace(&mut entry.value, val))
+        } else {
+            self.insert_new(key, val);
+            None
+        }
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        if self.touch(&key) {
+            self.hits += 1;
+            &mut self.store.get_mut(&key).unwrap().value
+        } else {
+            self.misses += 1;
+            self.insert_new(key, f())
+        }
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store.remove(k).map(|entry| {
+            self.order.remove(&(entry.frequency, entry.tick));
+            entry.value
+        })
+    }
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store.contains_key(k)
+    }
+    fn cache_clear(&mut self) {
+        self.store.clear();
+        self.order.clear();
+    }
+    fn cache_reset(&mut self) {
+        self.store = HashMap::with_capacity(self.capacity);
+        self.order = BTreeMap::new();
+        self.tick = 0;
+    }
+    fn cache_reset_metrics(&mut self) {
+        self.misses = 0;
+        self.hits = 0;
+    }
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl<K, V> CachedAsync<K, V> for LfuCache<K, V>
+where
+    K: Hash + Eq + Clone + Send,
+{
+    async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+    {
+        if self.touch(&k) {
+            self.hits += 1;
+            &mut self.store.get_mut(&k).unwrap().value
+        } else {
+            self.misses += 1;
+            let v = f().await;
+            self.insert_new(k, v)
+        }
+    }
+
+    async fn try_get_or_set_with<F, Fut, E>(&mut self, k: K, f: F) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+    {
+        if self.touch(&k) {
+            self.hits += 1;
+            Ok(&mut self.store.get_mut(&k).unwrap().value)
+        } else {
+            self.misses += 1;
+            let v = f().await?;
+            Ok(self.insert_new(k, v))
+        }
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lfu_cache() {
+        let mut c = LfuCache::with_size(3);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_misses(), Some(1));
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_hits(), Some(2));
+        assert_eq!(c.frequency(&1), Some(3));
+
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_get(&2), Some(&200));
+        assert_eq!(c.cache_set(3, 300), None);
+
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [1, 2, 3]);
+
+        // a one-off key evicts the least frequently used entry, not the oldest
+        assert_eq!(c.cache_set(4, 400), None);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [1, 2, 4]);
+        assert_eq!(c.cache_set(5, 500), None);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [1, 2, 5]);
+        assert_eq!(c.cache_size(), 3);
+
+        assert_eq!(c.cache_set(1, 101), Some(100));
+        assert_eq!(c.frequency(&1), Some(4));
+        assert_eq!(
+            c.value_order().copied().collect::<Vec<_>>(),
+            [101, 200, 500]
+        );
+
+        c.cache_reset_metrics();
+        assert_eq!(c.cache_hits(), Some(0));
+        assert_eq!(c.cache_misses(), Some(0));
+        assert_eq!(c.cache_capacity(), Some(3));
+    }
+
+    #[test]
+    fn scan_does_not_flush_hot_keys() {
+        let mut c = LfuCache::with_size(4);
+        for _ in 0..3 {
+            c.cache_get_or_set_with(1, || 1);
+            c.cache_get_or_set_with(2, || 2);
+        }
+        for n in 100..200 {
+            c.cache_set(n, n);
+        }
+        assert_eq!(c.cache_size(), 4);
+        assert_eq!(c.cache_get(&1), Some(&1));
+        assert_eq!(c.cache_get(&2), Some(&2));
+        assert!(c.cache_get(&100).is_none());
+    }
+
+    #[test]
+    fn try_new() {
+        let c: std::io::Result<LfuCache<i32, i32>> = LfuCache::try_with_size(0);
+        assert_eq!(c.unwrap_err().raw_os_error(), Some(22));
+    }
+
+    #[test]
+    fn clear() {
+        let mut c = LfuCache::with_size(3);
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+        c.cache_clear();
+
+        assert_eq!(0, c.cache_size());
+        assert!(c.order.is_empty());
+    }
+
+    #[test]
+    fn remove() {
+        let mut c = LfuCache::with_size(3);
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+
+        assert_eq!(Some(100), c.cache_remove(&1));
+        assert_eq!(2, c.cache_size());
+        assert_eq!(None, c.cache_remove(&1));
+        assert_eq!(2, c.order.len());
+
+        c.retain(|k, _| *k != 2);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn get_or_set_with() {
+        let mut c = LfuCache::with_size(2);
+
+        assert_eq!(c.cache_get_or_set_with(0, || 0), &0);
+        assert_eq!(c.cache_get_or_set_with(0, || 42), &0);
+        assert_eq!(c.cache_get_or_set_with(1, || 1), &1);
+        assert_eq!(c.cache_get_or_set_with(2, || 2), &2);
+        assert_eq!(c.cache_misses(), Some(3));
+        assert_eq!(c.cache_hits(), Some(1));
+
+        // 1 was the least frequently used key
+        assert_eq!(c.cache_get_or_set_with(1, || 10), &10);
+        assert_eq!(c.cache_get_or_set_with(0, || 42), &0);
+        assert_eq!(c.cache_misses(), Some(4));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_trait() {
+        use crate::CachedAsync;
+        let mut c = LfuCache::with_size(2);
+
+        async fn _get(n: usize) -> usize {
+            n
+        }
+
+        assert_eq!(c.get_or_set_with(0, || async { _get(0).await }).await, &0);
+        assert_eq!(c.get_or_set_with(0, || async { _get(3).await }).await, &0);
+        assert_eq!(c.get_or_set_with(1, || async { _get(1).await }).await, &1);
+        assert_eq!(c.get_or_set_with(2, || async { _get(2).await }).await, &2);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [0, 2]);
+
+        c.cache_reset();
+        async fn _try_get(n: usize) -> Result<usize, String> {
+            if n < 10 {
+                Ok(n)
+            } else {
+                Err("dead".to_string())
+            }
+        }
+
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(10).await })
+            .await;
+        assert!(res.is_err());
+        assert!(c.key_order().next().is_none());
+
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(1).await })
+            .await;
+        assert_eq!(res.unwrap(), &1);
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(5).await })
+            .await;
+        assert_eq!(res.unwrap(), &1);
+    }
+}
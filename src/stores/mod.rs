@@ -8,24 +8,49 @@ use std::hash::Hash;
 #[cfg(feature = "async")]
 use {super::CachedAsync, async_trait::async_trait, futures::Future};
 
+mod arc;
 mod expiring_value_cache;
+mod lfu;
 #[cfg(feature = "redis_store")]
 mod redis;
+mod sharded;
 mod sized;
 mod timed;
 mod timed_sized;
+mod tiny_lfu;
+mod ttl;
 mod unbound;
+mod weighted;
 
+#[cfg(feature = "redis_bincode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis_bincode")))]
+pub use crate::stores::redis::BincodeSerializer;
+#[cfg(feature = "redis_cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis_cbor")))]
+pub use crate::stores::redis::CborSerializer;
+#[cfg(feature = "redis_msgpack")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis_msgpack")))]
+pub use crate::stores::redis::MessagePackSerializer;
+#[cfg(feature = "redis_postcard")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis_postcard")))]
+pub use crate::stores::redis::PostcardSerializer;
 #[cfg(feature = "redis_store")]
 #[cfg_attr(docsrs, doc(cfg(feature = "redis_store")))]
 pub use crate::stores::redis::{
-    RedisCache, RedisCacheBuildError, RedisCacheBuilder, RedisCacheError,
+    Compression, JsonSerializer, RedisCache, RedisCacheBuildError, RedisCacheBuilder,
+    RedisCacheError, RedisCacheKeys, Serializer, SerializerError,
 };
+pub use arc::ArcCache;
 pub use expiring_value_cache::{CanExpire, ExpiringValueCache};
+pub use lfu::LfuCache;
+pub use sharded::ShardedCache;
 pub use sized::SizedCache;
 pub use timed::TimedCache;
 pub use timed_sized::TimedSizedCache;
+pub use tiny_lfu::TinyLfuCache;
+pub use ttl::TtlCache;
 pub use unbound::UnboundCache;
+pub use weighted::{Weigher, WeightedCache};
 
 #[cfg(all(
     feature = "async",
@@ -74,6 +99,13 @@ where
     {
         self.remove(k)
     }
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.contains_key(k)
+    }
     fn cache_clear(&mut self) {
         self.clear();
     }
@@ -1,11 +1,27 @@
 use crate::IOCached;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::convert::TryFrom;
 use std::fmt::Display;
 use std::marker::PhantomData;
-
-pub struct RedisCacheBuilder<K, V> {
-    seconds: u64,
+use std::time::Duration;
+
+mod compression;
+mod serializer;
+
+pub use compression::Compression;
+#[cfg(feature = "redis_bincode")]
+pub use serializer::BincodeSerializer;
+#[cfg(feature = "redis_cbor")]
+pub use serializer::CborSerializer;
+#[cfg(feature = "redis_msgpack")]
+pub use serializer::MessagePackSerializer;
+#[cfg(feature = "redis_postcard")]
+pub use serializer::PostcardSerializer;
+pub use serializer::{JsonSerializer, Serializer, SerializerError};
+
+pub struct RedisCacheBuilder<K, V, S = JsonSerializer> {
+    lifespan: Duration,
     refresh: bool,
     namespace: String,
     prefix: String,
@@ -14,11 +30,22 @@ pub struct RedisCacheBuilder<K, V> {
     pool_min_idle: Option<u32>,
     pool_max_lifetime: Option<std::time::Duration>,
     pool_idle_timeout: Option<std::time::Duration>,
+    serializer: S,
+    compression: Option<(Compression, usize)>,
+    version: u64,
+    migration: Option<Migration<V>>,
+    evict_undeserializable: bool,
     _phantom: PhantomData<(K, V)>,
 }
 
 const ENV_KEY: &str = "CACHED_REDIS_CONNECTION_STRING";
 const DEFAULT_NAMESPACE: &str = "cached-redis-store:";
+const DEFAULT_VERSION: u64 = 1;
+// the number of keys `SCAN` is asked to look at in each batch
+const SCAN_COUNT: usize = 1000;
+
+/// Converts a value stored with an older schema version, see `set_migration`
+type Migration<V> = Box<dyn Fn(u64, V) -> Option<V> + Send + Sync>;
 
 use thiserror::Error;
 
@@ -43,7 +70,7 @@ where
     /// Initialize a `RedisCacheBuilder`
     pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> RedisCacheBuilder<K, V> {
         Self {
-            seconds,
+            lifespan: Duration::from_secs(seconds),
             refresh: false,
             namespace: DEFAULT_NAMESPACE.to_string(),
             prefix: prefix.as_ref().to_string(),
@@ -52,14 +79,95 @@ where
             pool_min_idle: None,
             pool_max_lifetime: None,
             pool_idle_timeout: None,
+            serializer: JsonSerializer,
+            compression: None,
+            version: DEFAULT_VERSION,
+            migration: None,
+            evict_undeserializable: false,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<K, V, S> RedisCacheBuilder<K, V, S>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+    S: Serializer,
+{
+    /// Specify the format values are stored in. Defaults to [`JsonSerializer`].
+    #[must_use]
+    pub fn set_serializer<T: Serializer>(self, serializer: T) -> RedisCacheBuilder<K, V, T> {
+        RedisCacheBuilder {
+            lifespan: self.lifespan,
+            refresh: self.refresh,
+            namespace: self.namespace,
+            prefix: self.prefix,
+            connection_string: self.connection_string,
+            pool_max_size: self.pool_max_size,
+            pool_min_idle: self.pool_min_idle,
+            pool_max_lifetime: self.pool_max_lifetime,
+            pool_idle_timeout: self.pool_idle_timeout,
+            serializer,
+            compression: self.compression,
+            version: self.version,
+            migration: self.migration,
+            evict_undeserializable: self.evict_undeserializable,
             _phantom: PhantomData,
         }
     }
 
+    /// Compress values whose serialized size is at least `threshold` bytes.
+    /// Each value is then stored after a 4 byte marker, values stored
+    /// without one by a cache that didn't compress are still read.
+    #[must_use]
+    pub fn set_compression(mut self, compression: Compression, threshold: usize) -> Self {
+        self.compression = Some((compression, threshold));
+        self
+    }
+
+    /// Set the schema version stored with values. Defaults to `1`.
+    /// Values stored with another version are treated as missing,
+    /// unless they are converted by the closure given to `set_migration`.
+    #[must_use]
+    pub fn set_version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Convert values stored with another schema version instead of treating
+    /// them as missing. The closure is called with the stored version and value,
+    /// which must still deserialize as `V` (e.g. because new fields have a
+    /// `#[serde(default)]`), and returns `None` to treat the value as missing.
+    /// Converted values aren't written back.
+    #[must_use]
+    pub fn set_migration<F>(mut self, migration: F) -> Self
+    where
+        F: Fn(u64, V) -> Option<V> + Send + Sync + 'static,
+    {
+        self.migration = Some(Box::new(migration));
+        self
+    }
+
+    /// Specify whether values that can't be deserialized are treated as missing.
+    /// They are logged as a warning and deleted, so that they're recomputed,
+    /// instead of failing with `RedisCacheError::CacheDeserializationError`.
+    #[must_use]
+    pub fn set_evict_undeserializable(mut self, evict: bool) -> Self {
+        self.evict_undeserializable = evict;
+        self
+    }
+
     /// Specify the cache TTL/lifespan in seconds
     #[must_use]
-    pub fn set_lifespan(mut self, seconds: u64) -> Self {
-        self.seconds = seconds;
+    pub fn set_lifespan(self, seconds: u64) -> Self {
+        self.set_lifespan_duration(Duration::from_secs(seconds))
+    }
+
+    /// Specify the cache TTL/lifespan, with millisecond precision
+    #[must_use]
+    pub fn set_lifespan_duration(mut self, lifespan: Duration) -> Self {
+        self.lifespan = lifespan;
         self
     }
 
@@ -75,7 +183,7 @@ where
     /// Note that no delimiters are implicitly added so you may pass
     /// an empty string if you want there to be no namespace on keys.
     #[must_use]
-    pub fn set_namespace<S: AsRef<str>>(mut self, namespace: S) -> Self {
+    pub fn set_namespace<N: AsRef<str>>(mut self, namespace: N) -> Self {
         self.namespace = namespace.as_ref().to_string();
         self
     }
@@ -85,7 +193,7 @@ where
     /// Note that no delimiters are implicitly added so you may pass
     /// an empty string if you want there to be no prefix on keys.
     #[must_use]
-    pub fn set_prefix<S: AsRef<str>>(mut self, prefix: S) -> Self {
+    pub fn set_prefix<P: AsRef<str>>(mut self, prefix: P) -> Self {
         self.prefix = prefix.as_ref().to_string();
         self
     }
@@ -179,14 +287,21 @@ where
     /// # Errors
     ///
     /// Will return a `RedisCacheBuildError`, depending on the error
-    pub fn build(self) -> Result<RedisCache<K, V>, RedisCacheBuildError> {
+    pub fn build(self) -> Result<RedisCache<K, V, S>, RedisCacheBuildError> {
         Ok(RedisCache {
-            seconds: self.seconds,
+            lifespan: self.lifespan,
             refresh: self.refresh,
             connection_string: self.connection_string()?,
             pool: self.create_pool()?,
             namespace: self.namespace,
             prefix: self.prefix,
+            encoding: Encoding {
+                serializer: self.serializer,
+                compression: self.compression,
+                version: self.version,
+                migration: self.migration,
+                evict_undeserializable: self.evict_undeserializable,
+            },
             _phantom: PhantomData,
         })
     }
@@ -196,13 +311,15 @@ where
 ///
 /// Values have a ttl applied and enforced by redis.
 /// Uses an r2d2 connection pool under the hood.
-pub struct RedisCache<K, V> {
-    pub(super) seconds: u64,
+/// Values are stored in the format of a [`Serializer`], JSON by default.
+pub struct RedisCache<K, V, S = JsonSerializer> {
+    pub(super) lifespan: Duration,
     pub(super) refresh: bool,
     pub(super) namespace: String,
     pub(super) prefix: String,
     connection_string: String,
     pool: r2d2::Pool<redis::Client>,
+    encoding: Encoding<V, S>,
     _phantom: PhantomData<(K, V)>,
 }
 
@@ -216,16 +333,116 @@ where
     pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> RedisCacheBuilder<K, V> {
         RedisCacheBuilder::new(prefix, seconds)
     }
+}
 
+impl<K, V, S> RedisCache<K, V, S>
+where
+    K: Display,
+    V: Serialize + DeserializeOwned,
+    S: Serializer,
+{
     fn generate_key(&self, key: &K) -> String {
         format!("{}{}{}", self.namespace, self.prefix, key)
     }
 
+    // read a value fetched from `key`, deleting it when it's evicted
+    fn read_value(
+        &self,
+        conn: &mut redis::Connection,
+        key: &str,
+        bytes: Vec<u8>,
+    ) -> Result<Option<V>, RedisCacheError> {
+        let (value, evict) = self.encoding.read(key, bytes)?;
+        if evict {
+            redis::cmd("DEL").arg(key).query::<()>(conn)?;
+        }
+        Ok(value)
+    }
+
     /// Return the redis connection string used
     #[must_use]
     pub fn connection_string(&self) -> String {
         self.connection_string.clone()
     }
+
+    /// Iterate over the cached keys, without their `{namespace}{prefix}`
+    ///
+    /// Keys are fetched in batches with `SCAN MATCH {namespace}{prefix}*`, so keys
+    /// of other caches whose prefix starts with this prefix are included, and keys
+    /// set or removed during the iteration may or may not be returned.
+    ///
+    /// # Errors
+    ///
+    /// Will return a `RedisCacheError` if a connection can't be taken from the pool,
+    /// the iterator then returns errors of fetching a batch
+    pub fn cache_keys(&self) -> Result<RedisCacheKeys, RedisCacheError> {
+        Ok(RedisCacheKeys {
+            conn: self.pool.get()?,
+            scan: KeyScan::new(&self.namespace, &self.prefix),
+            batch: Vec::new().into_iter(),
+        })
+    }
+
+    /// Get a cached value along with the time it has left to live, `None` if
+    /// it doesn't expire. The lifespan isn't refreshed, even with `set_refresh`
+    ///
+    /// # Errors
+    ///
+    /// Will return a `RedisCacheError` if the value can't be read
+    pub fn cache_get_with_ttl(
+        &self,
+        key: &K,
+    ) -> Result<Option<(V, Option<Duration>)>, RedisCacheError> {
+        let mut conn = self.pool.get()?;
+        let mut pipe = redis::pipe();
+        let key = self.generate_key(key);
+
+        pipe.get(key.clone());
+        pipe.pttl(key.clone());
+        let res: (Option<Vec<u8>>, i64) = pipe.query(&mut *conn)?;
+        match res {
+            (None, _) => Ok(None),
+            (Some(bytes), ttl) => {
+                let ttl = u64::try_from(ttl).ok().map(Duration::from_millis);
+                Ok(self
+                    .read_value(&mut conn, &key, bytes)?
+                    .map(|value| (value, ttl)))
+            }
+        }
+    }
+
+    /// Set a cached value that expires after `ttl` instead of the
+    /// lifespan of the cache, rounded up to at least a millisecond and
+    /// clamped to what redis accepts
+    ///
+    /// # Errors
+    ///
+    /// Will return a `RedisCacheError` if the value can't be set or the
+    /// previous value can't be read
+    pub fn cache_set_with_ttl(
+        &self,
+        key: K,
+        val: V,
+        ttl: Duration,
+    ) -> Result<Option<V>, RedisCacheError> {
+        let mut conn = self.pool.get()?;
+        let mut pipe = redis::pipe();
+        let key = self.generate_key(&key);
+
+        pipe.get(key.clone());
+        pipe.pset_ex::<String, Vec<u8>>(
+            key.clone(),
+            self.encoding.serialize(val)?,
+            expiry_millis(ttl),
+        )
+        .ignore();
+
+        let res: (Option<Vec<u8>>,) = pipe.query(&mut *conn)?;
+        match res.0 {
+            None => Ok(None),
+            Some(bytes) => self.encoding.read(&key, bytes).map(|(value, _)| value),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -236,31 +453,221 @@ pub enum RedisCacheError {
     PoolError(#[from] r2d2::Error),
     #[error("Error deserializing cached value {cached_value:?}: {error:?}")]
     CacheDeserializationError {
-        cached_value: String,
-        error: serde_json::Error,
+        cached_value: Vec<u8>,
+        error: SerializerError,
     },
     #[error("Error serializing cached value: {error:?}")]
-    CacheSerializationError { error: serde_json::Error },
+    CacheSerializationError { error: SerializerError },
 }
 
+// `version` comes first so it can be read on its own, without knowing how
+// the value was shaped, in formats that aren't self-describing
 #[derive(serde::Serialize, serde::Deserialize)]
 struct CachedRedisValue<V> {
-    pub(crate) value: V,
     pub(crate) version: Option<u64>,
+    pub(crate) value: V,
 }
-impl<V> CachedRedisValue<V> {
-    fn new(value: V) -> Self {
-        Self {
+
+#[derive(serde::Deserialize)]
+struct CachedRedisVersion {
+    version: Option<u64>,
+}
+
+// how values are stored: wrapped in a `CachedRedisValue`, serialized and compressed
+struct Encoding<V, S> {
+    serializer: S,
+    compression: Option<(Compression, usize)>,
+    version: u64,
+    migration: Option<Migration<V>>,
+    evict_undeserializable: bool,
+}
+
+impl<V, S> Encoding<V, S>
+where
+    V: Serialize + DeserializeOwned,
+    S: Serializer,
+{
+    fn serialize(&self, value: V) -> Result<Vec<u8>, RedisCacheError> {
+        let value = CachedRedisValue {
+            version: Some(self.version),
             value,
-            version: Some(1),
+        };
+        self.serializer
+            .serialize(&value)
+            .and_then(|bytes| compression::compress(self.compression, bytes))
+            .map_err(|error| RedisCacheError::CacheSerializationError { error })
+    }
+
+    // values stored with another version are missing unless migrated
+    fn deserialize(&self, bytes: Vec<u8>) -> Result<Option<V>, RedisCacheError> {
+        self.decode(&bytes)
+            .map_err(|error| RedisCacheError::CacheDeserializationError {
+                cached_value: bytes,
+                error,
+            })
+    }
+
+    // with `evict_undeserializable`, values that can't be deserialized are logged
+    // and read as missing, the flag tells callers that the key should be deleted
+    fn read(&self, key: &str, bytes: Vec<u8>) -> Result<(Option<V>, bool), RedisCacheError> {
+        match self.deserialize(bytes) {
+            Err(RedisCacheError::CacheDeserializationError { error, .. })
+                if self.evict_undeserializable =>
+            {
+                log::warn!("evicting undeserializable cached value {}: {}", key, error);
+                Ok((None, true))
+            }
+            value => value.map(|value| (value, false)),
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Option<V>, SerializerError> {
+        let bytes = compression::decompress(self.compression, bytes)?;
+        match self.serializer.deserialize::<CachedRedisValue<V>>(&bytes) {
+            Ok(CachedRedisValue { version, value }) => {
+                let version = version.unwrap_or(DEFAULT_VERSION);
+                if version == self.version {
+                    Ok(Some(value))
+                } else {
+                    Ok(self
+                        .migration
+                        .as_ref()
+                        .and_then(|migrate| migrate(version, value)))
+                }
+            }
+            // values stored with another version may no longer fit `V`
+            Err(error) => match self.serializer.deserialize::<CachedRedisVersion>(&bytes) {
+                Ok(CachedRedisVersion { version })
+                    if version.unwrap_or(DEFAULT_VERSION) != self.version =>
+                {
+                    Ok(None)
+                }
+                _ => Err(error),
+            },
+        }
+    }
+}
+
+// a `SCAN MATCH {namespace}{prefix}*` over the keys of a cache, fetching them
+// in batches so that redis isn't blocked the way `KEYS` would block it
+struct KeyScan {
+    pattern: String,
+    strip: usize,
+    cursor: Option<u64>,
+}
+
+impl KeyScan {
+    fn new(namespace: &str, prefix: &str) -> Self {
+        Self {
+            pattern: format!("{}{}*", escape_pattern(namespace), escape_pattern(prefix)),
+            strip: namespace.len() + prefix.len(),
+            cursor: Some(0),
+        }
+    }
+
+    // the command fetching the next batch, `None` once the scan is complete
+    fn next_cmd(&self) -> Option<redis::Cmd> {
+        let cursor = self.cursor?;
+        let mut cmd = redis::cmd("SCAN");
+        cmd.arg(cursor)
+            .arg("MATCH")
+            .arg(&self.pattern)
+            .arg("COUNT")
+            .arg(SCAN_COUNT);
+        Some(cmd)
+    }
+
+    // a failed batch ends the scan
+    fn advance(
+        &mut self,
+        batch: redis::RedisResult<(u64, Vec<String>)>,
+    ) -> Result<Vec<String>, RedisCacheError> {
+        match batch {
+            Ok((cursor, keys)) => {
+                self.cursor = Some(cursor).filter(|&cursor| cursor != 0);
+                Ok(keys)
+            }
+            Err(error) => {
+                self.cursor = None;
+                Err(error.into())
+            }
+        }
+    }
+
+    fn strip(&self, key: &str) -> String {
+        key[self.strip..].to_string()
+    }
+}
+
+// redis adds the current time to expiries in milliseconds, a signed 64 bit
+// integer, so they're clamped well below its maximum and rounded up to at
+// least a millisecond
+const MAX_EXPIRY_MILLIS: u128 = i64::MAX as u128 / 2;
+
+fn expiry_millis(lifespan: Duration) -> usize {
+    let millis = lifespan.as_millis().clamp(1, MAX_EXPIRY_MILLIS);
+    usize::try_from(millis).unwrap_or(usize::MAX)
+}
+
+// `SCAN MATCH` patterns are globs, the namespace and prefix are matched literally
+fn escape_pattern(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// `UNLINK` frees the values in the background, it's only missing before redis 4
+fn unlink_cmd(keys: &[String]) -> redis::Cmd {
+    let mut cmd = redis::cmd("UNLINK");
+    cmd.arg(keys);
+    cmd
+}
+
+fn del_cmd(keys: &[String]) -> redis::Cmd {
+    let mut cmd = redis::cmd("DEL");
+    cmd.arg(keys);
+    cmd
+}
+
+fn is_unknown_command(error: &redis::RedisError) -> bool {
+    error.kind() == redis::ErrorKind::ResponseError
+        && error.to_string().to_lowercase().contains("unknown command")
+}
+
+/// Iterator over the keys of a [`RedisCache`], see [`RedisCache::cache_keys`]
+pub struct RedisCacheKeys {
+    conn: r2d2::PooledConnection<redis::Client>,
+    scan: KeyScan,
+    batch: std::vec::IntoIter<String>,
+}
+
+impl Iterator for RedisCacheKeys {
+    type Item = Result<String, RedisCacheError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(key) = self.batch.next() {
+                return Some(Ok(self.scan.strip(&key)));
+            }
+            let cmd = self.scan.next_cmd()?;
+            match self.scan.advance(cmd.query(&mut *self.conn)) {
+                Ok(keys) => self.batch = keys.into_iter(),
+                Err(error) => return Some(Err(error)),
+            }
         }
     }
 }
 
-impl<K, V> IOCached<K, V> for RedisCache<K, V>
+impl<K, V, S> IOCached<K, V> for RedisCache<K, V, S>
 where
     K: Display,
     V: Serialize + DeserializeOwned,
+    S: Serializer,
 {
     type Error = RedisCacheError;
 
@@ -271,84 +678,94 @@ where
 
         pipe.get(key.clone());
         if self.refresh {
-            pipe.expire(key, self.seconds as usize).ignore();
+            pipe.pexpire(key.clone(), expiry_millis(self.lifespan))
+                .ignore();
         }
         // ugh: https://github.com/mitsuhiko/redis-rs/pull/388#issuecomment-910919137
-        let res: (Option<String>,) = pipe.query(&mut *conn)?;
+        let res: (Option<Vec<u8>>,) = pipe.query(&mut *conn)?;
         match res.0 {
             None => Ok(None),
-            Some(s) => {
-                let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                    RedisCacheError::CacheDeserializationError {
-                        cached_value: s,
-                        error: e,
-                    }
-                })?;
-                Ok(Some(v.value))
-            }
+            Some(bytes) => self.read_value(&mut conn, &key, bytes),
         }
     }
 
     fn cache_set(&self, key: K, val: V) -> Result<Option<V>, RedisCacheError> {
+        self.cache_set_with_ttl(key, val, self.lifespan)
+    }
+
+    fn cache_remove(&self, key: &K) -> Result<Option<V>, RedisCacheError> {
         let mut conn = self.pool.get()?;
         let mut pipe = redis::pipe();
-        let key = self.generate_key(&key);
+        let key = self.generate_key(key);
 
-        let val = CachedRedisValue::new(val);
         pipe.get(key.clone());
-        pipe.set_ex::<String, String>(
-            key,
-            serde_json::to_string(&val)
-                .map_err(|e| RedisCacheError::CacheSerializationError { error: e })?,
-            self.seconds as usize,
-        )
-        .ignore();
-
-        let res: (Option<String>,) = pipe.query(&mut *conn)?;
+        pipe.del::<String>(key.clone()).ignore();
+        let res: (Option<Vec<u8>>,) = pipe.query(&mut *conn)?;
         match res.0 {
             None => Ok(None),
-            Some(s) => {
-                let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                    RedisCacheError::CacheDeserializationError {
-                        cached_value: s,
-                        error: e,
-                    }
-                })?;
-                Ok(Some(v.value))
-            }
+            Some(bytes) => self.encoding.read(&key, bytes).map(|(value, _)| value),
         }
     }
 
-    fn cache_remove(&self, key: &K) -> Result<Option<V>, RedisCacheError> {
+    /// Values stored with another version, or that are evicted as undeserializable,
+    /// aren't cached values. The lifespan isn't refreshed
+    fn cache_contains(&self, key: &K) -> Result<bool, RedisCacheError> {
         let mut conn = self.pool.get()?;
-        let mut pipe = redis::pipe();
         let key = self.generate_key(key);
+        let bytes: Option<Vec<u8>> = redis::cmd("GET").arg(&key).query(&mut *conn)?;
+        match bytes {
+            None => Ok(false),
+            Some(bytes) => Ok(self.read_value(&mut conn, &key, bytes)?.is_some()),
+        }
+    }
 
-        pipe.get(key.clone());
-        pipe.del::<String>(key).ignore();
-        let res: (Option<String>,) = pipe.query(&mut *conn)?;
-        match res.0 {
-            None => Ok(None),
-            Some(s) => {
-                let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                    RedisCacheError::CacheDeserializationError {
-                        cached_value: s,
-                        error: e,
-                    }
-                })?;
-                Ok(Some(v.value))
+    /// Remove the keys matching `{namespace}{prefix}*`, see `cache_keys`
+    fn cache_clear(&self) -> Result<Option<usize>, RedisCacheError> {
+        let mut conn = self.pool.get()?;
+        let mut scan = KeyScan::new(&self.namespace, &self.prefix);
+        let mut removed = 0;
+        while let Some(cmd) = scan.next_cmd() {
+            let keys = scan.advance(cmd.query(&mut *conn))?;
+            if keys.is_empty() {
+                continue;
             }
+            removed += match unlink_cmd(&keys).query::<usize>(&mut *conn) {
+                Err(error) if is_unknown_command(&error) => {
+                    del_cmd(&keys).query::<usize>(&mut *conn)?
+                }
+                removed => removed?,
+            };
+        }
+        Ok(Some(removed))
+    }
+
+    /// Count the keys matching `{namespace}{prefix}*`, see `cache_keys`.
+    /// Keys may be counted twice if redis resizes its keyspace during the scan.
+    fn cache_size(&self) -> Result<Option<usize>, RedisCacheError> {
+        let mut conn = self.pool.get()?;
+        let mut scan = KeyScan::new(&self.namespace, &self.prefix);
+        let mut size = 0;
+        while let Some(cmd) = scan.next_cmd() {
+            size += scan.advance(cmd.query(&mut *conn))?.len();
         }
+        Ok(Some(size))
     }
 
     fn cache_lifespan(&self) -> Option<u64> {
-        Some(self.seconds)
+        Some(self.lifespan.as_secs())
     }
 
     fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
-        let old = self.seconds;
-        self.seconds = seconds;
-        Some(old)
+        self.cache_set_lifespan_duration(Duration::from_secs(seconds))
+            .map(|old| old.as_secs())
+    }
+
+    fn cache_lifespan_duration(&self) -> Option<Duration> {
+        Some(self.lifespan)
+    }
+
+    fn cache_set_lifespan_duration(&mut self, lifespan: Duration) -> Option<Duration> {
+        Some(std::mem::replace(&mut self.lifespan, lifespan))
     }
 
     fn cache_set_refresh(&mut self, refresh: bool) -> bool {
@@ -364,17 +781,24 @@ where
 ))]
 mod async_redis {
     use super::{
-        CachedRedisValue, DeserializeOwned, Display, PhantomData, RedisCacheBuildError,
-        RedisCacheError, Serialize, DEFAULT_NAMESPACE, ENV_KEY,
+        del_cmd, expiry_millis, is_unknown_command, unlink_cmd, Compression, DeserializeOwned,
+        Display, Duration, Encoding, JsonSerializer, KeyScan, Migration, PhantomData,
+        RedisCacheBuildError, RedisCacheError, Serialize, Serializer, TryFrom, DEFAULT_NAMESPACE,
+        DEFAULT_VERSION, ENV_KEY,
     };
     use {crate::IOCachedAsync, async_trait::async_trait};
 
-    pub struct AsyncRedisCacheBuilder<K, V> {
-        seconds: u64,
+    pub struct AsyncRedisCacheBuilder<K, V, S = JsonSerializer> {
+        lifespan: Duration,
         refresh: bool,
         namespace: String,
         prefix: String,
         connection_string: Option<String>,
+        serializer: S,
+        compression: Option<(Compression, usize)>,
+        version: u64,
+        migration: Option<Migration<V>>,
+        evict_undeserializable: bool,
         _phantom: PhantomData<(K, V)>,
     }
 
@@ -386,19 +810,99 @@ mod async_redis {
         /// Initialize a `RedisCacheBuilder`
         pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> AsyncRedisCacheBuilder<K, V> {
             Self {
-                seconds,
+                lifespan: Duration::from_secs(seconds),
                 refresh: false,
                 namespace: DEFAULT_NAMESPACE.to_string(),
                 prefix: prefix.as_ref().to_string(),
                 connection_string: None,
+                serializer: JsonSerializer,
+                compression: None,
+                version: DEFAULT_VERSION,
+                migration: None,
+                evict_undeserializable: false,
+                _phantom: PhantomData,
+            }
+        }
+    }
+
+    impl<K, V, S> AsyncRedisCacheBuilder<K, V, S>
+    where
+        K: Display,
+        V: Serialize + DeserializeOwned,
+        S: Serializer,
+    {
+        /// Specify the format values are stored in. Defaults to [`JsonSerializer`].
+        #[must_use]
+        pub fn set_serializer<T: Serializer>(
+            self,
+            serializer: T,
+        ) -> AsyncRedisCacheBuilder<K, V, T> {
+            AsyncRedisCacheBuilder {
+                lifespan: self.lifespan,
+                refresh: self.refresh,
+                namespace: self.namespace,
+                prefix: self.prefix,
+                connection_string: self.connection_string,
+                serializer,
+                compression: self.compression,
+                version: self.version,
+                migration: self.migration,
+                evict_undeserializable: self.evict_undeserializable,
                 _phantom: PhantomData,
             }
         }
 
+        /// Compress values whose serialized size is at least `threshold` bytes.
+        /// Each value is then stored after a 4 byte marker, values stored
+        /// without one by a cache that didn't compress are still read.
+        #[must_use]
+        pub fn set_compression(mut self, compression: Compression, threshold: usize) -> Self {
+            self.compression = Some((compression, threshold));
+            self
+        }
+
+        /// Set the schema version stored with values. Defaults to `1`.
+        /// Values stored with another version are treated as missing,
+        /// unless they are converted by the closure given to `set_migration`.
+        #[must_use]
+        pub fn set_version(mut self, version: u64) -> Self {
+            self.version = version;
+            self
+        }
+
+        /// Convert values stored with another schema version instead of treating
+        /// them as missing. The closure is called with the stored version and value,
+        /// which must still deserialize as `V` (e.g. because new fields have a
+        /// `#[serde(default)]`), and returns `None` to treat the value as missing.
+        /// Converted values aren't written back.
+        #[must_use]
+        pub fn set_migration<F>(mut self, migration: F) -> Self
+        where
+            F: Fn(u64, V) -> Option<V> + Send + Sync + 'static,
+        {
+            self.migration = Some(Box::new(migration));
+            self
+        }
+
+        /// Specify whether values that can't be deserialized are treated as missing.
+        /// They are logged as a warning and deleted, so that they're recomputed,
+        /// instead of failing with `RedisCacheError::CacheDeserializationError`.
+        #[must_use]
+        pub fn set_evict_undeserializable(mut self, evict: bool) -> Self {
+            self.evict_undeserializable = evict;
+            self
+        }
+
         /// Specify the cache TTL/lifespan in seconds
         #[must_use]
-        pub fn set_lifespan(mut self, seconds: u64) -> Self {
-            self.seconds = seconds;
+        pub fn set_lifespan(self, seconds: u64) -> Self {
+            self.set_lifespan_duration(Duration::from_secs(seconds))
+        }
+
+        /// Specify the cache TTL/lifespan, with millisecond precision
+        #[must_use]
+        pub fn set_lifespan_duration(mut self, lifespan: Duration) -> Self {
+            self.lifespan = lifespan;
             self
         }
 
@@ -414,7 +918,7 @@ mod async_redis {
         /// Note that no delimiters are implicitly added so you may pass
         /// an empty string if you want there to be no namespace on keys.
         #[must_use]
-        pub fn set_namespace<S: AsRef<str>>(mut self, namespace: S) -> Self {
+        pub fn set_namespace<N: AsRef<str>>(mut self, namespace: N) -> Self {
             self.namespace = namespace.as_ref().to_string();
             self
         }
@@ -424,7 +928,7 @@ mod async_redis {
         /// Note that no delimiters are implicitly added so you may pass
         /// an empty string if you want there to be no prefix on keys.
         #[must_use]
-        pub fn set_prefix<S: AsRef<str>>(mut self, prefix: S) -> Self {
+        pub fn set_prefix<P: AsRef<str>>(mut self, prefix: P) -> Self {
             self.prefix = prefix.as_ref().to_string();
             self
         }
@@ -483,9 +987,9 @@ mod async_redis {
         /// # Errors
         ///
         /// Will return a `RedisCacheBuildError`, depending on the error
-        pub async fn build(self) -> Result<AsyncRedisCache<K, V>, RedisCacheBuildError> {
+        pub async fn build(self) -> Result<AsyncRedisCache<K, V, S>, RedisCacheBuildError> {
             Ok(AsyncRedisCache {
-                seconds: self.seconds,
+                lifespan: self.lifespan,
                 refresh: self.refresh,
                 connection_string: self.connection_string()?,
                 #[cfg(not(feature = "redis_connection_manager"))]
@@ -494,6 +998,13 @@ mod async_redis {
                 connection: self.create_connection_manager().await?,
                 namespace: self.namespace,
                 prefix: self.prefix,
+                encoding: Encoding {
+                    serializer: self.serializer,
+                    compression: self.compression,
+                    version: self.version,
+                    migration: self.migration,
+                    evict_undeserializable: self.evict_undeserializable,
+                },
                 _phantom: PhantomData,
             })
         }
@@ -504,8 +1015,9 @@ mod async_redis {
     /// Values have a ttl applied and enforced by redis.
     /// Uses a `redis::aio::MultiplexedConnection` or `redis::aio::ConnectionManager`
     /// under the hood depending if feature `redis_connection_manager` is used or not.
-    pub struct AsyncRedisCache<K, V> {
-        pub(super) seconds: u64,
+    /// Values are stored in the format of a [`Serializer`], JSON by default.
+    pub struct AsyncRedisCache<K, V, S = JsonSerializer> {
+        pub(super) lifespan: Duration,
         pub(super) refresh: bool,
         pub(super) namespace: String,
         pub(super) prefix: String,
@@ -514,6 +1026,7 @@ mod async_redis {
         connection: redis::aio::MultiplexedConnection,
         #[cfg(feature = "redis_connection_manager")]
         connection: redis::aio::ConnectionManager,
+        encoding: Encoding<V, S>,
         _phantom: PhantomData<(K, V)>,
     }
 
@@ -527,23 +1040,138 @@ mod async_redis {
         pub fn new<S: AsRef<str>>(prefix: S, seconds: u64) -> AsyncRedisCacheBuilder<K, V> {
             AsyncRedisCacheBuilder::new(prefix, seconds)
         }
+    }
 
+    impl<K, V, S> AsyncRedisCache<K, V, S>
+    where
+        K: Display + Send + Sync,
+        V: Serialize + DeserializeOwned + Send + Sync,
+        S: Serializer + Send + Sync,
+    {
         fn generate_key(&self, key: &K) -> String {
             format!("{}{}{}", self.namespace, self.prefix, key)
         }
 
+        // read a value fetched from `key`, deleting it when it's evicted
+        async fn read_value(
+            &self,
+            key: &str,
+            bytes: Vec<u8>,
+        ) -> Result<Option<V>, RedisCacheError> {
+            let (value, evict) = self.encoding.read(key, bytes)?;
+            if evict {
+                let mut conn = self.connection.clone();
+                redis::cmd("DEL")
+                    .arg(key)
+                    .query_async::<_, ()>(&mut conn)
+                    .await?;
+            }
+            Ok(value)
+        }
+
         /// Return the redis connection string used
         #[must_use]
         pub fn connection_string(&self) -> String {
             self.connection_string.clone()
         }
+
+        /// Stream the cached keys, without their `{namespace}{prefix}`
+        ///
+        /// Keys are fetched in batches with `SCAN MATCH {namespace}{prefix}*`, so keys
+        /// of other caches whose prefix starts with this prefix are included, and keys
+        /// set or removed while streaming may or may not be returned. The stream
+        /// returns the errors of fetching a batch.
+        pub fn cache_keys(&self) -> impl futures::Stream<Item = Result<String, RedisCacheError>> {
+            let scan = KeyScan::new(&self.namespace, &self.prefix);
+            let state = (
+                self.connection.clone(),
+                scan,
+                Vec::<String>::new().into_iter(),
+            );
+            futures::stream::unfold(state, |(mut conn, mut scan, mut batch)| async move {
+                loop {
+                    if let Some(key) = batch.next() {
+                        let key = scan.strip(&key);
+                        return Some((Ok(key), (conn, scan, batch)));
+                    }
+                    let cmd = scan.next_cmd()?;
+                    match scan.advance(cmd.query_async(&mut conn).await) {
+                        Ok(keys) => batch = keys.into_iter(),
+                        Err(error) => return Some((Err(error), (conn, scan, batch))),
+                    }
+                }
+            })
+        }
+
+        /// Get a cached value along with the time it has left to live, `None` if
+        /// it doesn't expire. The lifespan isn't refreshed, even with `set_refresh`
+        ///
+        /// # Errors
+        ///
+        /// Will return a `RedisCacheError` if the value can't be read
+        pub async fn cache_get_with_ttl(
+            &self,
+            key: &K,
+        ) -> Result<Option<(V, Option<Duration>)>, RedisCacheError> {
+            let mut conn = self.connection.clone();
+            let mut pipe = redis::pipe();
+            let key = self.generate_key(key);
+
+            pipe.get(key.clone());
+            pipe.pttl(key.clone());
+            let res: (Option<Vec<u8>>, i64) = pipe.query_async(&mut conn).await?;
+            match res {
+                (None, _) => Ok(None),
+                (Some(bytes), ttl) => {
+                    let ttl = u64::try_from(ttl).ok().map(Duration::from_millis);
+                    Ok(self
+                        .read_value(&key, bytes)
+                        .await?
+                        .map(|value| (value, ttl)))
+                }
+            }
+        }
+
+        /// Set a cached value that expires after `ttl` instead of the
+        /// lifespan of the cache, rounded up to at least a millisecond and
+        /// clamped to what redis accepts
+        ///
+        /// # Errors
+        ///
+        /// Will return a `RedisCacheError` if the value can't be set or the
+        /// previous value can't be read
+        pub async fn cache_set_with_ttl(
+            &self,
+            key: K,
+            val: V,
+            ttl: Duration,
+        ) -> Result<Option<V>, RedisCacheError> {
+            let mut conn = self.connection.clone();
+            let mut pipe = redis::pipe();
+            let key = self.generate_key(&key);
+
+            pipe.get(key.clone());
+            pipe.pset_ex::<String, Vec<u8>>(
+                key.clone(),
+                self.encoding.serialize(val)?,
+                expiry_millis(ttl),
+            )
+            .ignore();
+
+            let res: (Option<Vec<u8>>,) = pipe.query_async(&mut conn).await?;
+            match res.0 {
+                None => Ok(None),
+                Some(bytes) => self.encoding.read(&key, bytes).map(|(value, _)| value),
+            }
+        }
     }
 
     #[async_trait]
-    impl<K, V> IOCachedAsync<K, V> for AsyncRedisCache<K, V>
+    impl<K, V, S> IOCachedAsync<K, V> for AsyncRedisCache<K, V, S>
     where
         K: Display + Send + Sync,
         V: Serialize + DeserializeOwned + Send + Sync,
+        S: Serializer + Send + Sync,
     {
         type Error = RedisCacheError;
 
@@ -555,75 +1183,79 @@ mod async_redis {
 
             pipe.get(key.clone());
             if self.refresh {
-                pipe.expire(key, self.seconds as usize).ignore();
+                pipe.pexpire(key.clone(), expiry_millis(self.lifespan))
+                    .ignore();
             }
-            let res: (Option<String>,) = pipe.query_async(&mut conn).await?;
+            let res: (Option<Vec<u8>>,) = pipe.query_async(&mut conn).await?;
             match res.0 {
                 None => Ok(None),
-                Some(s) => {
-                    let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                        RedisCacheError::CacheDeserializationError {
-                            cached_value: s,
-                            error: e,
-                        }
-                    })?;
-                    Ok(Some(v.value))
-                }
+                Some(bytes) => self.read_value(&key, bytes).await,
             }
         }
 
         /// Set a cached value
         async fn cache_set(&self, key: K, val: V) -> Result<Option<V>, Self::Error> {
+            self.cache_set_with_ttl(key, val, self.lifespan).await
+        }
+
+        /// Remove a cached value
+        async fn cache_remove(&self, key: &K) -> Result<Option<V>, Self::Error> {
             let mut conn = self.connection.clone();
             let mut pipe = redis::pipe();
-            let key = self.generate_key(&key);
+            let key = self.generate_key(key);
 
-            let val = CachedRedisValue::new(val);
             pipe.get(key.clone());
-            pipe.set_ex::<String, String>(
-                key,
-                serde_json::to_string(&val)
-                    .map_err(|e| RedisCacheError::CacheSerializationError { error: e })?,
-                self.seconds as usize,
-            )
-            .ignore();
-
-            let res: (Option<String>,) = pipe.query_async(&mut conn).await?;
+            pipe.del::<String>(key.clone()).ignore();
+            let res: (Option<Vec<u8>>,) = pipe.query_async(&mut conn).await?;
             match res.0 {
                 None => Ok(None),
-                Some(s) => {
-                    let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                        RedisCacheError::CacheDeserializationError {
-                            cached_value: s,
-                            error: e,
-                        }
-                    })?;
-                    Ok(Some(v.value))
-                }
+                Some(bytes) => self.encoding.read(&key, bytes).map(|(value, _)| value),
             }
         }
 
-        /// Remove a cached value
-        async fn cache_remove(&self, key: &K) -> Result<Option<V>, Self::Error> {
+        /// Return whether a value is cached for a key, without refreshing its ttl
+        /// Values stored with another version, or that are evicted as undeserializable,
+        /// aren't cached values. The lifespan isn't refreshed
+        async fn cache_contains(&self, key: &K) -> Result<bool, Self::Error> {
             let mut conn = self.connection.clone();
-            let mut pipe = redis::pipe();
             let key = self.generate_key(key);
+            let bytes: Option<Vec<u8>> = redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
+            match bytes {
+                None => Ok(false),
+                Some(bytes) => Ok(self.read_value(&key, bytes).await?.is_some()),
+            }
+        }
 
-            pipe.get(key.clone());
-            pipe.del::<String>(key).ignore();
-            let res: (Option<String>,) = pipe.query_async(&mut conn).await?;
-            match res.0 {
-                None => Ok(None),
-                Some(s) => {
-                    let v: CachedRedisValue<V> = serde_json::from_str(&s).map_err(|e| {
-                        RedisCacheError::CacheDeserializationError {
-                            cached_value: s,
-                            error: e,
-                        }
-                    })?;
-                    Ok(Some(v.value))
+        /// Remove the keys matching `{namespace}{prefix}*`, see `cache_keys`
+        async fn cache_clear(&self) -> Result<Option<usize>, Self::Error> {
+            let mut conn = self.connection.clone();
+            let mut scan = KeyScan::new(&self.namespace, &self.prefix);
+            let mut removed = 0;
+            while let Some(cmd) = scan.next_cmd() {
+                let keys = scan.advance(cmd.query_async(&mut conn).await)?;
+                if keys.is_empty() {
+                    continue;
                 }
+                removed += match unlink_cmd(&keys).query_async::<_, usize>(&mut conn).await {
+                    Err(error) if is_unknown_command(&error) => {
+                        del_cmd(&keys).query_async::<_, usize>(&mut conn).await?
+                    }
+                    removed => removed?,
+                };
             }
+            Ok(Some(removed))
+        }
+
+        /// Count the keys matching `{namespace}{prefix}*`, see `cache_keys`.
+        /// Keys may be counted twice if redis resizes its keyspace during the scan.
+        async fn cache_size(&self) -> Result<Option<usize>, Self::Error> {
+            let mut conn = self.connection.clone();
+            let mut scan = KeyScan::new(&self.namespace, &self.prefix);
+            let mut size = 0;
+            while let Some(cmd) = scan.next_cmd() {
+                size += scan.advance(cmd.query_async(&mut conn).await)?.len();
+            }
+            Ok(Some(size))
         }
 
         /// Set the flag to control whether cache hits refresh the ttl of cached values, returns the old flag value
@@ -635,14 +1267,23 @@ mod async_redis {
 
         /// Return the lifespan of cached values (time to eviction)
         fn cache_lifespan(&self) -> Option<u64> {
-            Some(self.seconds)
+            Some(self.lifespan.as_secs())
         }
 
         /// Set the lifespan of cached values, returns the old value
         fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
-            let old = self.seconds;
-            self.seconds = seconds;
-            Some(old)
+            self.cache_set_lifespan_duration(Duration::from_secs(seconds))
+                .map(|old| old.as_secs())
+        }
+
+        /// Return the lifespan of cached values (time to eviction)
+        fn cache_lifespan_duration(&self) -> Option<Duration> {
+            Some(self.lifespan)
+        }
+
+        /// Set the lifespan of cached values, returns the old value
+        fn cache_set_lifespan_duration(&mut self, lifespan: Duration) -> Option<Duration> {
+            Some(std::mem::replace(&mut self.lifespan, lifespan))
         }
     }
 
@@ -689,6 +1330,27 @@ mod async_redis {
             assert_eq!(c.cache_get(&1).await.unwrap().unwrap(), 100);
             assert_eq!(c.cache_get(&1).await.unwrap().unwrap(), 100);
         }
+
+        #[tokio::test]
+        async fn test_async_redis_cache_clear() {
+            use futures::StreamExt;
+
+            let c: AsyncRedisCache<u32, u32> =
+                AsyncRedisCache::new(format!("{}:async-redis-cache-test-clear", now_millis()), 60)
+                    .build()
+                    .await
+                    .unwrap();
+
+            for n in 0..10 {
+                c.cache_set(n, n).await.unwrap();
+            }
+            assert_eq!(c.cache_size().await.unwrap(), Some(10));
+            let keys = c.cache_keys().collect::<Vec<_>>().await;
+            assert_eq!(keys.len(), 10);
+            assert!(keys.iter().all(|key| key.is_ok()));
+            assert_eq!(c.cache_clear().await.unwrap(), Some(10));
+            assert_eq!(c.cache_size().await.unwrap(), Some(0));
+        }
     }
 }
 
@@ -764,4 +1426,275 @@ mod tests {
 
         assert_eq!(100, c.cache_remove(&1).unwrap().unwrap());
     }
+
+    #[test]
+    fn get_with_ttl() {
+        let c: RedisCache<u32, u32> = RedisCache::new(
+            format!("{}:redis-cache-test-get-with-ttl", now_millis()),
+            60,
+        )
+        .build()
+        .unwrap();
+
+        assert!(c.cache_get_with_ttl(&1).unwrap().is_none());
+        c.cache_set(1, 100).unwrap();
+        let (value, ttl) = c.cache_get_with_ttl(&1).unwrap().unwrap();
+        assert_eq!(value, 100);
+        let ttl = ttl.unwrap();
+        assert!(ttl > Duration::from_secs(50) && ttl <= Duration::from_secs(60));
+    }
+
+    #[cfg(feature = "redis_bincode")]
+    #[test]
+    fn set_serializer() {
+        let prefix = format!("{}:redis-cache-test-set-serializer", now_millis());
+        let c: RedisCache<u32, String, BincodeSerializer> = RedisCache::new(&prefix, 60)
+            .set_serializer(BincodeSerializer)
+            .build()
+            .unwrap();
+
+        assert!(c.cache_set(1, "one".to_string()).unwrap().is_none());
+        assert_eq!(c.cache_get(&1).unwrap(), Some("one".to_string()));
+
+        // values written in another format can't be read
+        let json: RedisCache<u32, String> = RedisCache::new(&prefix, 60).build().unwrap();
+        assert!(matches!(
+            json.cache_get(&1),
+            Err(RedisCacheError::CacheDeserializationError { .. })
+        ));
+    }
+
+    #[cfg(feature = "redis_zstd")]
+    #[test]
+    fn set_compression() {
+        let prefix = format!("{}:redis-cache-test-set-compression", now_millis());
+        let plain: RedisCache<u32, String> = RedisCache::new(&prefix, 60).build().unwrap();
+        let c: RedisCache<u32, String> = RedisCache::new(&prefix, 60)
+            .set_compression(Compression::Zstd, 64)
+            .build()
+            .unwrap();
+
+        let large = "cached ".repeat(100);
+        assert!(c.cache_set(1, large.clone()).unwrap().is_none());
+        assert!(c.cache_set(2, "small".to_string()).unwrap().is_none());
+        assert_eq!(c.cache_get(&1).unwrap(), Some(large));
+        assert_eq!(c.cache_get(&2).unwrap(), Some("small".to_string()));
+
+        // values written without compression are still read
+        assert!(plain.cache_set(3, "plain".to_string()).unwrap().is_none());
+        assert_eq!(c.cache_get(&3).unwrap(), Some("plain".to_string()));
+    }
+
+    #[cfg(all(feature = "redis_zstd", feature = "redis_bincode"))]
+    #[test]
+    fn set_compression_bincode() {
+        let prefix = format!("{}:redis-cache-test-set-compression-bincode", now_millis());
+        let plain: RedisCache<u32, String, BincodeSerializer> = RedisCache::new(&prefix, 60)
+            .set_serializer(BincodeSerializer)
+            .build()
+            .unwrap();
+        // the envelopes of these values start with the bytes of an `Option` tag
+        assert!(plain.cache_set(1, "plain".to_string()).unwrap().is_none());
+        let unversioned = bincode::serialize(&(None::<u64>, "unversioned")).unwrap();
+        let mut conn = plain.pool.get().unwrap();
+        redis::cmd("SET")
+            .arg(plain.generate_key(&2))
+            .arg(unversioned)
+            .query::<()>(&mut *conn)
+            .unwrap();
+
+        let c: RedisCache<u32, String, BincodeSerializer> = RedisCache::new(&prefix, 60)
+            .set_serializer(BincodeSerializer)
+            .set_compression(Compression::Zstd, 64)
+            .build()
+            .unwrap();
+        assert_eq!(c.cache_get(&1).unwrap(), Some("plain".to_string()));
+        assert_eq!(c.cache_get(&2).unwrap(), Some("unversioned".to_string()));
+
+        let large = "cached ".repeat(100);
+        assert!(c.cache_set(3, large.clone()).unwrap().is_none());
+        assert_eq!(c.cache_get(&3).unwrap(), Some(large));
+    }
+
+    #[test]
+    fn expiry_millis_clamped() {
+        assert_eq!(expiry_millis(Duration::from_millis(1500)), 1500);
+        assert_eq!(expiry_millis(Duration::from_micros(10)), 1);
+        let max = usize::try_from(MAX_EXPIRY_MILLIS).unwrap_or(usize::MAX);
+        assert_eq!(expiry_millis(Duration::MAX), max);
+        assert_eq!(expiry_millis(Duration::from_secs(u64::MAX / 1000)), max);
+    }
+
+    #[test]
+    fn set_with_ttl_max() {
+        let c: RedisCache<u32, u32> = RedisCache::new(
+            format!("{}:redis-cache-test-set-with-ttl-max", now_millis()),
+            60,
+        )
+        .set_lifespan_duration(Duration::MAX)
+        .set_refresh(true)
+        .build()
+        .unwrap();
+
+        assert!(c.cache_set(1, 100).unwrap().is_none());
+        assert!(c
+            .cache_set_with_ttl(2, 200, Duration::MAX)
+            .unwrap()
+            .is_none());
+        assert_eq!(c.cache_get(&1).unwrap(), Some(100));
+        assert_eq!(c.cache_get(&2).unwrap(), Some(200));
+    }
+
+    #[test]
+    fn key_scan() {
+        let scan = KeyScan::new("ns:", "fn[1]*:");
+        assert_eq!(scan.pattern, "ns:fn\\[1\\]\\*:*");
+        assert_eq!(scan.strip("ns:fn[1]*:42"), "42");
+    }
+
+    #[test]
+    fn clear_and_size() {
+        let prefix = format!("{}:redis-cache-test-clear", now_millis());
+        let c: RedisCache<u32, u32> = RedisCache::new(&prefix, 60).build().unwrap();
+        let other: RedisCache<u32, u32> = RedisCache::new(format!("{}-other", prefix), 60)
+            .set_namespace("in-tests:")
+            .build()
+            .unwrap();
+
+        assert_eq!(c.cache_size().unwrap(), Some(0));
+        for n in 0..2500 {
+            c.cache_set(n, n).unwrap();
+        }
+        other.cache_set(1, 1).unwrap();
+        assert_eq!(c.cache_size().unwrap(), Some(2500));
+
+        let mut keys = c
+            .cache_keys()
+            .unwrap()
+            .map(|key| key.unwrap().parse().unwrap())
+            .collect::<Vec<u32>>();
+        keys.sort_unstable();
+        assert_eq!(keys, (0..2500).collect::<Vec<_>>());
+
+        assert_eq!(c.cache_clear().unwrap(), Some(2500));
+        assert_eq!(c.cache_size().unwrap(), Some(0));
+        assert_eq!(other.cache_get(&1).unwrap(), Some(1));
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct User {
+        name: String,
+        #[serde(default)]
+        admin: bool,
+    }
+
+    fn encoding<V>(version: u64, migration: Option<Migration<V>>) -> Encoding<V, JsonSerializer> {
+        Encoding {
+            serializer: JsonSerializer,
+            compression: None,
+            version,
+            migration,
+            evict_undeserializable: false,
+        }
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let old = encoding::<String>(1, None)
+            .serialize("cached".to_string())
+            .unwrap();
+        assert_eq!(
+            encoding::<String>(1, None)
+                .deserialize(old.clone())
+                .unwrap(),
+            Some("cached".to_string())
+        );
+
+        // values of another version are missing, even when they no longer deserialize
+        assert!(encoding::<User>(2, None)
+            .deserialize(old.clone())
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            encoding::<User>(1, None).deserialize(old),
+            Err(RedisCacheError::CacheDeserializationError { .. })
+        ));
+
+        let old = encoding::<User>(1, None)
+            .serialize(User {
+                name: "root".to_string(),
+                admin: false,
+            })
+            .unwrap();
+        let migrated = encoding::<User>(
+            2,
+            Some(Box::new(|version, user: User| {
+                assert_eq!(version, 1);
+                Some(User {
+                    admin: user.name == "root",
+                    ..user
+                })
+            })),
+        )
+        .deserialize(old)
+        .unwrap()
+        .unwrap();
+        assert!(migrated.admin);
+    }
+
+    #[test]
+    fn read_undeserializable() {
+        let mut strict = encoding::<u32>(1, None);
+        assert!(strict.read("key", b"[".to_vec()).is_err());
+        strict.evict_undeserializable = true;
+        assert!(matches!(
+            strict.read("key", b"[".to_vec()),
+            Ok((None, true))
+        ));
+        let value = strict.serialize(1).unwrap();
+        assert!(matches!(strict.read("key", value), Ok((Some(1), false))));
+    }
+
+    #[test]
+    fn set_evict_undeserializable() {
+        let prefix = format!("{}:redis-cache-test-evict-undeserializable", now_millis());
+        let strings: RedisCache<u32, String> = RedisCache::new(&prefix, 60).build().unwrap();
+        let strict: RedisCache<u32, u32> = RedisCache::new(&prefix, 60).build().unwrap();
+        let evicting: RedisCache<u32, u32> = RedisCache::new(&prefix, 60)
+            .set_evict_undeserializable(true)
+            .build()
+            .unwrap();
+
+        assert!(strings.cache_set(1, "one".to_string()).unwrap().is_none());
+        assert!(strict.cache_get(&1).is_err());
+        assert!(evicting.cache_get(&1).unwrap().is_none());
+        assert!(!evicting.cache_contains(&1).unwrap());
+
+        // looking the key up evicts the value like `cache_get`
+        assert!(strings.cache_set(2, "two".to_string()).unwrap().is_none());
+        assert!(!evicting.cache_contains(&2).unwrap());
+        assert!(!strings.cache_contains(&2).unwrap());
+    }
+
+    #[test]
+    fn set_version() {
+        let prefix = format!("{}:redis-cache-test-set-version", now_millis());
+        let v1: RedisCache<u32, String> = RedisCache::new(&prefix, 60).build().unwrap();
+        let v2: RedisCache<u32, String> =
+            RedisCache::new(&prefix, 60).set_version(2).build().unwrap();
+        let migrating: RedisCache<u32, String> = RedisCache::new(&prefix, 60)
+            .set_version(2)
+            .set_migration(|version, value: String| Some(format!("{}@v{}", value, version)))
+            .build()
+            .unwrap();
+
+        assert!(v1.cache_set(1, "one".to_string()).unwrap().is_none());
+        assert!(v2.cache_get(&1).unwrap().is_none());
+        assert!(!v2.cache_contains(&1).unwrap());
+        assert!(v1.cache_contains(&1).unwrap());
+        assert_eq!(migrating.cache_get(&1).unwrap(), Some("one@v1".to_string()));
+        assert!(migrating.cache_contains(&1).unwrap());
+        assert!(v2.cache_set(1, "two".to_string()).unwrap().is_none());
+        assert_eq!(v2.cache_get(&1).unwrap(), Some("two".to_string()));
+    }
 }
@@ -0,0 +1,168 @@
+use super::SerializerError;
+use std::borrow::Cow;
+
+// the marker written before values by caches that compress: a magic that can't
+// start a serialized value, followed by the algorithm. 0xff is neither valid
+// UTF-8, an `Option` tag of bincode or postcard, nor the start of a MessagePack
+// or CBOR map. Values without a marker were written by a cache that doesn't
+// compress and are read as is
+const MAGIC: &[u8] = b"\xffcz";
+const UNCOMPRESSED: u8 = 0x00;
+#[cfg(feature = "redis_zstd")]
+const ZSTD: u8 = 0x01;
+#[cfg(feature = "redis_lz4")]
+const LZ4: u8 = 0x02;
+#[cfg(feature = "redis_gzip")]
+const GZIP: u8 = 0x03;
+
+/// Algorithm used to compress values stored in redis
+///
+/// Available behind the `redis_zstd`, `redis_lz4` and `redis_gzip` features.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+    /// Compress with `zstd` at its default level
+    #[cfg(feature = "redis_zstd")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "redis_zstd")))]
+    Zstd,
+    /// Compress with `lz4_flex`, faster but larger than the others
+    #[cfg(feature = "redis_lz4")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "redis_lz4")))]
+    Lz4,
+    /// Compress with gzip using `flate2` at its default level
+    #[cfg(feature = "redis_gzip")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "redis_gzip")))]
+    Gzip,
+}
+
+impl Compression {
+    // without any of the features there are no variants to use `bytes`
+    #[cfg_attr(
+        not(any(feature = "redis_zstd", feature = "redis_lz4", feature = "redis_gzip")),
+        allow(unused_variables)
+    )]
+    fn compress(self, bytes: &[u8]) -> Result<Vec<u8>, SerializerError> {
+        match self {
+            #[cfg(feature = "redis_zstd")]
+            Compression::Zstd => {
+                let mut compressed = marker(ZSTD);
+                zstd::stream::copy_encode(bytes, &mut compressed, 0)?;
+                Ok(compressed)
+            }
+            #[cfg(feature = "redis_lz4")]
+            Compression::Lz4 => {
+                let mut compressed = marker(LZ4);
+                compressed.extend(lz4_flex::compress_prepend_size(bytes));
+                Ok(compressed)
+            }
+            #[cfg(feature = "redis_gzip")]
+            Compression::Gzip => {
+                use std::io::Write;
+                let mut encoder =
+                    flate2::write::GzEncoder::new(marker(GZIP), flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+}
+
+fn marker(algorithm: u8) -> Vec<u8> {
+    let mut marker = MAGIC.to_vec();
+    marker.push(algorithm);
+    marker
+}
+
+// compress values of at least `threshold` bytes, smaller values are only marked
+pub(super) fn compress(
+    compression: Option<(Compression, usize)>,
+    bytes: Vec<u8>,
+) -> Result<Vec<u8>, SerializerError> {
+    match compression {
+        None => Ok(bytes),
+        Some((compression, threshold)) if bytes.len() >= threshold => compression.compress(&bytes),
+        Some(_) => {
+            let mut marked = marker(UNCOMPRESSED);
+            marked.extend(bytes);
+            Ok(marked)
+        }
+    }
+}
+
+// values read by a cache that compresses may have been written before it did,
+// those don't start with a marker
+pub(super) fn decompress(
+    compression: Option<(Compression, usize)>,
+    bytes: &[u8],
+) -> Result<Cow<'_, [u8]>, SerializerError> {
+    if compression.is_none() {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    let marked = bytes
+        .strip_prefix(MAGIC)
+        .and_then(|marked| marked.split_first());
+    match marked {
+        Some((&UNCOMPRESSED, rest)) => Ok(Cow::Borrowed(rest)),
+        #[cfg(feature = "redis_zstd")]
+        Some((&ZSTD, rest)) => Ok(Cow::Owned(zstd::stream::decode_all(rest)?)),
+        #[cfg(feature = "redis_lz4")]
+        Some((&LZ4, rest)) => Ok(Cow::Owned(lz4_flex::decompress_size_prepended(rest)?)),
+        #[cfg(feature = "redis_gzip")]
+        Some((&GZIP, rest)) => {
+            use std::io::Read;
+            let mut decompressed = Vec::new();
+            flate2::read::GzDecoder::new(rest).read_to_end(&mut decompressed)?;
+            Ok(Cow::Owned(decompressed))
+        }
+        // a value compressed with an algorithm whose feature isn't enabled
+        Some((algorithm, _)) => {
+            Err(format!("value compressed with unknown algorithm {:#04x}", algorithm).into())
+        }
+        None => Ok(Cow::Borrowed(bytes)),
+    }
+}
+
+#[cfg(all(
+    test,
+    any(feature = "redis_zstd", feature = "redis_lz4", feature = "redis_gzip")
+))]
+mod tests {
+    use super::*;
+
+    fn round_trip(compression: Compression) {
+        let bytes = format!(r#"{{"value":"{}","version":1}}"#, "cached ".repeat(100)).into_bytes();
+        let small = compress(Some((compression, 4096)), bytes.clone()).unwrap();
+        assert_eq!(small[..MAGIC.len() + 1], marker(UNCOMPRESSED)[..]);
+        let large = compress(Some((compression, 16)), bytes.clone()).unwrap();
+        assert!(large.starts_with(MAGIC));
+        assert_ne!(large[MAGIC.len()], UNCOMPRESSED);
+        assert!(large.len() < bytes.len());
+        for stored in [small, large, bytes.clone()] {
+            let read = decompress(Some((compression, 16)), &stored).unwrap();
+            assert_eq!(read.as_ref(), bytes.as_slice());
+        }
+        // bincode and postcard envelopes start with the `Option` tag of the version
+        for stored in [vec![0x00, 0x01, 0x02], vec![0x01, 0x02, 0x03], vec![0x03]] {
+            let read = decompress(Some((compression, 16)), &stored).unwrap();
+            assert_eq!(read.as_ref(), stored.as_slice());
+        }
+    }
+
+    #[cfg(feature = "redis_zstd")]
+    #[test]
+    fn zstd() {
+        round_trip(Compression::Zstd);
+    }
+
+    #[cfg(feature = "redis_lz4")]
+    #[test]
+    fn lz4() {
+        round_trip(Compression::Lz4);
+    }
+
+    #[cfg(feature = "redis_gzip")]
+    #[test]
+    fn gzip() {
+        round_trip(Compression::Gzip);
+    }
+}
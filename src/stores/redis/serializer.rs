@@ -0,0 +1,161 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Error returned by a [`Serializer`], boxed so that
+/// [`RedisCacheError`](super::RedisCacheError) doesn't depend on the format
+pub type SerializerError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Format used to convert cached values to and from the bytes stored in redis
+///
+/// [`JsonSerializer`] is used by default. Binary formats are available behind the
+/// `redis_bincode`, `redis_msgpack`, `redis_cbor` and `redis_postcard` features.
+/// Values written in one format can't be read by a cache using another.
+pub trait Serializer {
+    /// Serialize a value into bytes
+    ///
+    /// # Errors
+    ///
+    /// Will return a `SerializerError` if the value can't be represented in this format
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, SerializerError>;
+
+    /// Deserialize a value from bytes
+    ///
+    /// # Errors
+    ///
+    /// Will return a `SerializerError` if the bytes aren't a valid value in this format
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, SerializerError>;
+}
+
+/// Serialize values as JSON with `serde_json`, the default format
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonSerializer;
+
+impl Serializer for JsonSerializer {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, SerializerError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, SerializerError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Serialize values with `bincode`
+#[cfg(feature = "redis_bincode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis_bincode")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BincodeSerializer;
+
+#[cfg(feature = "redis_bincode")]
+impl Serializer for BincodeSerializer {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, SerializerError> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, SerializerError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// Serialize values as MessagePack with `rmp-serde`, keeping the names of struct fields
+#[cfg(feature = "redis_msgpack")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis_msgpack")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePackSerializer;
+
+#[cfg(feature = "redis_msgpack")]
+impl Serializer for MessagePackSerializer {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, SerializerError> {
+        Ok(rmp_serde::to_vec_named(value)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, SerializerError> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
+
+/// Serialize values as CBOR with `ciborium`
+#[cfg(feature = "redis_cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis_cbor")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CborSerializer;
+
+#[cfg(feature = "redis_cbor")]
+impl Serializer for CborSerializer {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, SerializerError> {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(value, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, SerializerError> {
+        Ok(ciborium::de::from_reader(bytes)?)
+    }
+}
+
+/// Serialize values with `postcard`
+#[cfg(feature = "redis_postcard")]
+#[cfg_attr(docsrs, doc(cfg(feature = "redis_postcard")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PostcardSerializer;
+
+#[cfg(feature = "redis_postcard")]
+impl Serializer for PostcardSerializer {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, SerializerError> {
+        Ok(postcard::to_allocvec(value)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, SerializerError> {
+        Ok(postcard::from_bytes(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Value {
+        name: String,
+        version: Option<u64>,
+    }
+
+    fn round_trip<S: Serializer>(serializer: S) {
+        let value = Value {
+            name: "cached".to_string(),
+            version: Some(1),
+        };
+        let bytes = serializer.serialize(&value).unwrap();
+        assert_eq!(serializer.deserialize::<Value>(&bytes).unwrap(), value);
+        assert!(serializer.deserialize::<Value>(&[0xff, 0xff]).is_err());
+    }
+
+    #[test]
+    fn json() {
+        round_trip(JsonSerializer);
+    }
+
+    #[cfg(feature = "redis_bincode")]
+    #[test]
+    fn bincode() {
+        round_trip(BincodeSerializer);
+    }
+
+    #[cfg(feature = "redis_msgpack")]
+    #[test]
+    fn msgpack() {
+        round_trip(MessagePackSerializer);
+    }
+
+    #[cfg(feature = "redis_cbor")]
+    #[test]
+    fn cbor() {
+        round_trip(CborSerializer);
+    }
+
+    #[cfg(feature = "redis_postcard")]
+    #[test]
+    fn postcard() {
+        round_trip(PostcardSerializer);
+    }
+}
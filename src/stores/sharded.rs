@@ -0,0 +1,212 @@
+use crate::{Cached, ConcurrentCached};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Mutex, MutexGuard};
+
+/// Sharded Cache
+///
+/// Splits keys by hash across a number of independently locked inner
+/// [`Cached`] stores, so that concurrent callers only contend when
+/// their keys fall in the same shard. Any bound of the inner stores
+/// (size, lifespan, ...) applies per shard.
+///
+/// Note: This cache is in-memory only
+#[derive(Debug)]
+pub struct ShardedCache<C, S = RandomState> {
+    pub(super) shards: Box<[Mutex<C>]>,
+    pub(super) hash_builder: S,
+}
+
+impl<C> ShardedCache<C> {
+    /// Creates a new `ShardedCache` with `shards` inner stores,
+    /// each built by calling `create`
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `shards` is 0
+    #[must_use]
+    pub fn with_shards<F: FnMut() -> C>(shards: usize, mut create: F) -> ShardedCache<C> {
+        if shards == 0 {
+            panic!("`shards` of `ShardedCache` must be greater than zero.");
+        }
+        ShardedCache {
+            shards: (0..shards).map(|_| Mutex::new(create())).collect(),
+            hash_builder: RandomState::new(),
+        }
+    }
+}
+
+impl<C, S: BuildHasher> ShardedCache<C, S> {
+    /// Return the number of shards
+    #[must_use]
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Lock and return the shard responsible for `key`
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the shard's lock is poisoned
+    pub fn shard<Q: Hash + ?Sized>(&self, key: &Q) -> MutexGuard<'_, C> {
+        let index = (self.hash_builder.hash_one(key) % self.shards.len() as u64) as usize;
+        self.shards[index].lock().unwrap()
+    }
+
+    fn each_shard(&self) -> impl Iterator<Item = MutexGuard<'_, C>> {
+        self.shards.iter().map(|shard| shard.lock().unwrap())
+    }
+}
+
+impl<K, V, C, S> ConcurrentCached<K, V> for ShardedCache<C, S>
+where
+    K: Hash + Eq,
+    C: Cached<K, V>,
+    S: BuildHasher,
+{
+    fn cache_get<Q>(&self, k: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.shard(k).cache_get(k).cloned()
+    }
+
+    fn cache_set(&self, k: K, v: V) -> Option<V> {
+        self.shard(&k).cache_set(k, v)
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&self, k: K, f: F) -> V
+    where
+        V: Clone,
+    {
+        self.shard(&k).cache_get_or_set_with(k, f).clone()
+    }
+
+    fn cache_remove<Q>(&self, k: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.shard(k).cache_remove(k)
+    }
+
+    fn cache_contains<Q>(&self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.shard(k).cache_contains(k)
+    }
+    fn cache_clear(&self) {
+        self.each_shard().for_each(|mut shard| shard.cache_clear());
+    }
+
+    fn cache_reset(&self) {
+        self.each_shard().for_each(|mut shard| shard.cache_reset());
+    }
+
+    fn cache_reset_metrics(&self) {
+        self.each_shard()
+            .for_each(|mut shard| shard.cache_reset_metrics());
+    }
+
+    fn cache_flush(&self) {
+        self.each_shard().for_each(|mut shard| shard.cache_flush());
+    }
+
+    fn cache_size(&self) -> usize {
+        self.each_shard().map(|shard| shard.cache_size()).sum()
+    }
+
+    fn cache_hits(&self) -> Option<u64> {
+        self.each_shard().map(|shard| shard.cache_hits()).sum()
+    }
+
+    fn cache_misses(&self) -> Option<u64> {
+        self.each_shard().map(|shard| shard.cache_misses()).sum()
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+    use crate::{SizedCache, UnboundCache};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn sharded_cache() {
+        let c = ShardedCache::with_shards(4, UnboundCache::new);
+        assert_eq!(c.shard_count(), 4);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_misses(), Some(1));
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_get(&1), Some(100));
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_set(1, 101), Some(100));
+        assert_eq!(c.cache_get_or_set_with(2, || 200), 200);
+        assert_eq!(c.cache_get_or_set_with(2, || 201), 200);
+        assert_eq!(c.cache_size(), 2);
+
+        assert_eq!(c.cache_remove(&1), Some(101));
+        assert_eq!(c.cache_remove(&1), None);
+        assert_eq!(c.cache_size(), 1);
+
+        c.cache_reset_metrics();
+        assert_eq!(c.cache_hits(), Some(0));
+        assert_eq!(c.cache_misses(), Some(0));
+
+        c.cache_clear();
+        assert_eq!(c.cache_size(), 0);
+    }
+
+    #[test]
+    fn borrowed_keys() {
+        let c = ShardedCache::with_shards(8, UnboundCache::new);
+        c.cache_set("key".to_string(), 1);
+        assert_eq!(c.cache_get("key"), Some(1));
+        assert_eq!(c.cache_remove("key"), Some(1));
+    }
+
+    #[test]
+    fn bound_per_shard() {
+        let c = ShardedCache::with_shards(1, || SizedCache::with_size(2));
+        c.cache_set(1, 1);
+        c.cache_set(2, 2);
+        c.cache_set(3, 3);
+        assert_eq!(c.cache_size(), 2);
+        assert_eq!(c.shard(&1).cache_capacity(), Some(2));
+        assert!(c.cache_get(&1).is_none());
+    }
+
+    #[test]
+    fn concurrent_access() {
+        let c = Arc::new(ShardedCache::with_shards(8, UnboundCache::new));
+        let handles = (0..8)
+            .map(|t| {
+                let c = c.clone();
+                thread::spawn(move || {
+                    for n in 0..100 {
+                        c.cache_set(t * 100 + n, n);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(c.cache_size(), 800);
+        assert_eq!(c.cache_get(&742), Some(42));
+    }
+
+    #[test]
+    #[should_panic]
+    fn no_shards() {
+        let _c: ShardedCache<UnboundCache<u32, u32>> =
+            ShardedCache::with_shards(0, UnboundCache::new);
+    }
+}
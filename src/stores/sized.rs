@@ -220,6 +220,17 @@ impl<K: Hash + Eq + Clone, V> SizedCache<K, V> {
         }
     }
 
+    /// Return the value of `key` without counting a hit or miss
+    /// and without moving it to the front
+    pub(super) fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.get_index(self.hash(key), key)
+            .map(|index| &self.order.get(index).1)
+    }
+
     pub(super) fn get_if<F: FnOnce(&V) -> bool, Q>(&mut self, key: &Q, is_valid: F) -> Option<&V>
     where
         K: std::borrow::Borrow<Q>,
@@ -466,6 +477,13 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for SizedCache<K, V> {
             None
         }
     }
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.peek(k).is_some()
+    }
     fn cache_clear(&mut self) {
         // clear both the store and the order list
         self.store.clear();
@@ -633,6 +651,22 @@ mod tests {
         assert_eq!(c.cache_set(4, 100), None);
     }
 
+    #[test]
+    fn contains() {
+        let mut c = SizedCache::with_size(2);
+        c.cache_set(1, 100);
+        c.cache_set(2, 200);
+
+        assert!(c.cache_contains(&1));
+        assert!(!c.cache_contains(&3));
+        assert_eq!(c.cache_hits(), Some(0));
+        assert_eq!(c.cache_misses(), Some(0));
+        // checking does not make 1 the most recently used key
+        c.cache_set(3, 300);
+        assert!(!c.cache_contains(&1));
+        assert!(c.cache_contains(&2));
+    }
+
     #[test]
     fn clear() {
         let mut c = SizedCache::with_size(3);
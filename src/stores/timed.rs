@@ -2,6 +2,7 @@ use std::cmp::Eq;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::time::Duration;
 
 use instant::Instant;
 
@@ -9,6 +10,7 @@ use instant::Instant;
 use {super::CachedAsync, async_trait::async_trait, futures::Future};
 
 use super::Cached;
+use crate::clock::{Clock, SystemClock};
 
 /// Enum used for defining the status of time-cached values
 #[derive(Debug)]
@@ -22,50 +24,91 @@ pub(super) enum Status {
 ///
 /// Values are timestamped when inserted and are
 /// evicted if expired at time of retrieval.
+/// Time is read from a [`Clock`], the [`SystemClock`] by default.
 ///
 /// Note: This cache is in-memory only
 #[derive(Clone, Debug)]
-pub struct TimedCache<K, V> {
+pub struct TimedCache<K, V, C = SystemClock> {
     pub(super) store: HashMap<K, (Instant, V)>,
-    pub(super) seconds: u64,
+    pub(super) lifespan: Duration,
     pub(super) hits: u64,
     pub(super) misses: u64,
     pub(super) initial_capacity: Option<usize>,
     pub(super) refresh: bool,
+    pub(super) clock: C,
 }
 
 impl<K: Hash + Eq, V> TimedCache<K, V> {
-    /// Creates a new `TimedCache` with a specified lifespan
+    /// Creates a new `TimedCache` with a specified lifespan in seconds
     #[must_use]
     pub fn with_lifespan(seconds: u64) -> TimedCache<K, V> {
-        Self::with_lifespan_and_refresh(seconds, false)
+        Self::with_lifespan_duration(Duration::from_secs(seconds))
     }
 
-    /// Creates a new `TimedCache` with a specified lifespan and
+    /// Creates a new `TimedCache` with a specified lifespan
+    #[must_use]
+    pub fn with_lifespan_duration(lifespan: Duration) -> TimedCache<K, V> {
+        Self::with_lifespan_duration_and_refresh(lifespan, false)
+    }
+
+    /// Creates a new `TimedCache` with a specified lifespan in seconds and
     /// cache-store with the specified pre-allocated capacity
     #[must_use]
     pub fn with_lifespan_and_capacity(seconds: u64, size: usize) -> TimedCache<K, V> {
+        Self::with_lifespan_duration_and_capacity(Duration::from_secs(seconds), size)
+    }
+
+    /// Creates a new `TimedCache` with a specified lifespan and
+    /// cache-store with the specified pre-allocated capacity
+    #[must_use]
+    pub fn with_lifespan_duration_and_capacity(
+        lifespan: Duration,
+        size: usize,
+    ) -> TimedCache<K, V> {
         TimedCache {
             store: Self::new_store(Some(size)),
-            seconds,
+            lifespan,
             hits: 0,
             misses: 0,
             initial_capacity: Some(size),
             refresh: false,
+            clock: SystemClock,
         }
     }
 
-    /// Creates a new `TimedCache` with a specified lifespan which
+    /// Creates a new `TimedCache` with a specified lifespan in seconds which
     /// refreshes the ttl when the entry is retrieved
     #[must_use]
     pub fn with_lifespan_and_refresh(seconds: u64, refresh: bool) -> TimedCache<K, V> {
+        Self::with_lifespan_duration_and_refresh(Duration::from_secs(seconds), refresh)
+    }
+
+    /// Creates a new `TimedCache` with a specified lifespan which
+    /// refreshes the ttl when the entry is retrieved
+    #[must_use]
+    pub fn with_lifespan_duration_and_refresh(
+        lifespan: Duration,
+        refresh: bool,
+    ) -> TimedCache<K, V> {
+        let mut cache = Self::with_lifespan_duration_and_clock(lifespan, SystemClock);
+        cache.refresh = refresh;
+        cache
+    }
+}
+
+impl<K: Hash + Eq, V, C: Clock> TimedCache<K, V, C> {
+    /// Creates a new `TimedCache` with a specified lifespan which
+    /// reads the current time from `clock`
+    #[must_use]
+    pub fn with_lifespan_duration_and_clock(lifespan: Duration, clock: C) -> TimedCache<K, V, C> {
         TimedCache {
             store: Self::new_store(None),
-            seconds,
+            lifespan,
             hits: 0,
             misses: 0,
             initial_capacity: None,
-            refresh,
+            refresh: false,
+            clock,
         }
     }
 
@@ -92,13 +135,30 @@ impl<K: Hash + Eq, V> TimedCache<K, V> {
 
     /// Remove any expired values from the cache
     pub fn flush(&mut self) {
-        let seconds = self.seconds;
+        let lifespan = self.lifespan;
+        let now = self.clock.now();
         self.store
-            .retain(|_, (instant, _)| instant.elapsed().as_secs() < seconds);
+            .retain(|_, (instant, _)| now.duration_since(*instant) < lifespan);
+    }
+
+    /// Returns a reference to the value of `key` along with the time elapsed
+    /// since it was stored, or last refreshed. Behaves like `cache_get`
+    /// otherwise, expired values are removed and counted as a miss.
+    pub fn cache_get_with_age<Q>(&mut self, key: &Q) -> Option<(&V, Duration)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+        let age = self
+            .store
+            .get(key)
+            .map(|(instant, _)| now.duration_since(*instant));
+        self.cache_get(key).zip(age)
     }
 }
 
-impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
+impl<K: Hash + Eq, V, C: Clock> Cached<K, V> for TimedCache<K, V, C> {
     fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
     where
         K: std::borrow::Borrow<Q>,
@@ -107,9 +167,9 @@ impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
         let status = {
             let mut val = self.store.get_mut(key);
             if let Some(&mut (instant, _)) = val.as_mut() {
-                if instant.elapsed().as_secs() < self.seconds {
+                if self.clock.now().duration_since(*instant) < self.lifespan {
                     if self.refresh {
-                        *instant = Instant::now();
+                        *instant = self.clock.now();
                     }
                     Status::Found
                 } else {
@@ -144,9 +204,9 @@ impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
         let status = {
             let mut val = self.store.get_mut(key);
             if let Some(&mut (instant, _)) = val.as_mut() {
-                if instant.elapsed().as_secs() < self.seconds {
+                if self.clock.now().duration_since(*instant) < self.lifespan {
                     if self.refresh {
-                        *instant = Instant::now();
+                        *instant = self.clock.now();
                     }
                     Status::Found
                 } else {
@@ -176,30 +236,30 @@ impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
         match self.store.entry(key) {
             Entry::Occupied(mut occupied) => {
-                if occupied.get().0.elapsed().as_secs() < self.seconds {
+                if self.clock.now().duration_since(occupied.get().0) < self.lifespan {
                     if self.refresh {
-                        occupied.get_mut().0 = Instant::now();
+                        occupied.get_mut().0 = self.clock.now();
                     }
                     self.hits += 1;
                 } else {
                     self.misses += 1;
                     let val = f();
-                    occupied.insert((Instant::now(), val));
+                    occupied.insert((self.clock.now(), val));
                 }
                 &mut occupied.into_mut().1
             }
             Entry::Vacant(vacant) => {
                 self.misses += 1;
                 let val = f();
-                &mut vacant.insert((Instant::now(), val)).1
+                &mut vacant.insert((self.clock.now(), val)).1
             }
         }
     }
 
     fn cache_set(&mut self, key: K, val: V) -> Option<V> {
-        let stamped = (Instant::now(), val);
+        let stamped = (self.clock.now(), val);
         self.store.insert(key, stamped).and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
+            if self.clock.now().duration_since(instant) < self.lifespan {
                 Some(v)
             } else {
                 None
@@ -212,13 +272,22 @@ impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
         Q: std::hash::Hash + Eq + ?Sized,
     {
         self.store.remove(k).and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
+            if self.clock.now().duration_since(instant) < self.lifespan {
                 Some(v)
             } else {
                 None
             }
         })
     }
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store
+            .get(k)
+            .is_some_and(|(instant, _)| self.clock.now().duration_since(*instant) < self.lifespan)
+    }
     fn cache_clear(&mut self) {
         self.store.clear();
     }
@@ -229,6 +298,9 @@ impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
     fn cache_reset(&mut self) {
         self.store = Self::new_store(self.initial_capacity);
     }
+    fn cache_flush(&mut self) {
+        self.flush();
+    }
     fn cache_size(&self) -> usize {
         self.store.len()
     }
@@ -239,21 +311,29 @@ impl<K: Hash + Eq, V> Cached<K, V> for TimedCache<K, V> {
         Some(self.misses)
     }
     fn cache_lifespan(&self) -> Option<u64> {
-        Some(self.seconds)
+        Some(self.lifespan.as_secs())
     }
 
     fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
-        let old = self.seconds;
-        self.seconds = seconds;
-        Some(old)
+        self.cache_set_lifespan_duration(Duration::from_secs(seconds))
+            .map(|old| old.as_secs())
+    }
+
+    fn cache_lifespan_duration(&self) -> Option<Duration> {
+        Some(self.lifespan)
+    }
+
+    fn cache_set_lifespan_duration(&mut self, lifespan: Duration) -> Option<Duration> {
+        Some(std::mem::replace(&mut self.lifespan, lifespan))
     }
 }
 
 #[cfg(feature = "async")]
 #[async_trait]
-impl<K, V> CachedAsync<K, V> for TimedCache<K, V>
+impl<K, V, C> CachedAsync<K, V> for TimedCache<K, V, C>
 where
     K: Hash + Eq + Clone + Send,
+    C: Clock + Send,
 {
     async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
     where
@@ -263,20 +343,20 @@ where
     {
         match self.store.entry(k) {
             Entry::Occupied(mut occupied) => {
-                if occupied.get().0.elapsed().as_secs() < self.seconds {
+                if self.clock.now().duration_since(occupied.get().0) < self.lifespan {
                     if self.refresh {
-                        occupied.get_mut().0 = Instant::now();
+                        occupied.get_mut().0 = self.clock.now();
                     }
                     self.hits += 1;
                 } else {
                     self.misses += 1;
-                    occupied.insert((Instant::now(), f().await));
+                    occupied.insert((self.clock.now(), f().await));
                 }
                 &mut occupied.into_mut().1
             }
             Entry::Vacant(vacant) => {
                 self.misses += 1;
-                &mut vacant.insert((Instant::now(), f().await)).1
+                &mut vacant.insert((self.clock.now(), f().await)).1
             }
         }
     }
@@ -289,20 +369,20 @@ where
     {
         let v = match self.store.entry(k) {
             Entry::Occupied(mut occupied) => {
-                if occupied.get().0.elapsed().as_secs() < self.seconds {
+                if self.clock.now().duration_since(occupied.get().0) < self.lifespan {
                     if self.refresh {
-                        occupied.get_mut().0 = Instant::now();
+                        occupied.get_mut().0 = self.clock.now();
                     }
                     self.hits += 1;
                 } else {
                     self.misses += 1;
-                    occupied.insert((Instant::now(), f().await?));
+                    occupied.insert((self.clock.now(), f().await?));
                 }
                 &mut occupied.into_mut().1
             }
             Entry::Vacant(vacant) => {
                 self.misses += 1;
-                &mut vacant.insert((Instant::now(), f().await?)).1
+                &mut vacant.insert((self.clock.now(), f().await?)).1
             }
         };
 
@@ -375,6 +455,85 @@ mod tests {
         assert_eq!(c.cache_get(&2), None);
     }
 
+    #[test]
+    fn sub_second_lifespan() {
+        let mut c = TimedCache::with_lifespan_duration(Duration::from_millis(100));
+        assert_eq!(
+            c.cache_lifespan_duration(),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(c.cache_lifespan(), Some(0));
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        sleep(Duration::from_millis(150));
+        assert_eq!(c.cache_get(&1), None);
+
+        let old = c.cache_set_lifespan_duration(Duration::from_millis(1500));
+        assert_eq!(old, Some(Duration::from_millis(100)));
+        assert_eq!(c.cache_set_lifespan(2), Some(1));
+        assert_eq!(c.cache_lifespan_duration(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn mock_clock() {
+        use crate::MockClock;
+        let clock = MockClock::new();
+        let mut c =
+            TimedCache::with_lifespan_duration_and_clock(Duration::from_secs(10), clock.clone());
+        c.set_refresh(true);
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(c.cache_get(&1), Some(&100));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_get(&2), None);
+        clock.advance(Duration::from_secs(10));
+        c.flush();
+        assert_eq!(c.cache_size(), 0);
+    }
+
+    #[test]
+    fn contains() {
+        use crate::MockClock;
+        let clock = MockClock::new();
+        let mut c =
+            TimedCache::with_lifespan_duration_and_clock(Duration::from_secs(10), clock.clone());
+        c.set_refresh(true);
+
+        c.cache_set(1, 100);
+        clock.advance(Duration::from_secs(9));
+        assert!(c.cache_contains(&1));
+        // checking does not refresh the value
+        clock.advance(Duration::from_secs(1));
+        assert!(!c.cache_contains(&1));
+        assert_eq!(c.cache_hits(), Some(0));
+        assert_eq!(c.cache_misses(), Some(0));
+    }
+
+    #[test]
+    fn get_with_age() {
+        use crate::MockClock;
+        let clock = MockClock::new();
+        let mut c =
+            TimedCache::with_lifespan_duration_and_clock(Duration::from_secs(10), clock.clone());
+
+        assert_eq!(c.cache_get_with_age(&1), None);
+        c.cache_set(1, 100);
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(
+            c.cache_get_with_age(&1),
+            Some((&100, Duration::from_secs(3)))
+        );
+        clock.advance(Duration::from_secs(7));
+        assert_eq!(c.cache_get_with_age(&1), None);
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(2));
+        assert_eq!(c.cache_size(), 0);
+    }
+
     #[test]
     fn clear() {
         let mut c = TimedCache::with_lifespan(3600);
@@ -1,5 +1,6 @@
 use std::cmp::Eq;
 use std::hash::Hash;
+use std::time::Duration;
 
 use instant::Instant;
 
@@ -9,6 +10,7 @@ use {super::CachedAsync, async_trait::async_trait, futures::Future};
 use crate::stores::timed::Status;
 
 use super::{Cached, SizedCache};
+use crate::clock::{Clock, SystemClock};
 
 /// Timed LRU Cache
 ///
@@ -19,13 +21,14 @@ use super::{Cached, SizedCache};
 ///
 /// Note: This cache is in-memory only
 #[derive(Clone, Debug)]
-pub struct TimedSizedCache<K, V> {
+pub struct TimedSizedCache<K, V, C = SystemClock> {
     pub(super) store: SizedCache<K, (Instant, V)>,
     pub(super) size: usize,
-    pub(super) seconds: u64,
+    pub(super) lifespan: Duration,
     pub(super) hits: u64,
     pub(super) misses: u64,
     pub(super) refresh: bool,
+    pub(super) clock: C,
 }
 
 impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
@@ -35,6 +38,16 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
         Self::with_size_and_lifespan_and_refresh(size, seconds, false)
     }
 
+    /// Creates a new `SizedCache` with a given size limit, a lifespan as a `Duration`
+    /// and pre-allocated backing data
+    #[must_use]
+    pub fn with_size_and_lifespan_duration(
+        size: usize,
+        lifespan: Duration,
+    ) -> TimedSizedCache<K, V> {
+        Self::with_size_and_lifespan_duration_and_refresh(size, lifespan, false)
+    }
+
     /// Creates a new `SizedCache` with a given size limit and pre-allocated backing data.
     /// Also set if the ttl should be refreshed on retrieving
     ///
@@ -47,17 +60,29 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
         seconds: u64,
         refresh: bool,
     ) -> TimedSizedCache<K, V> {
-        if size == 0 {
-            panic!("`size` of `TimedSizedCache` must be greater than zero.");
-        }
-        TimedSizedCache {
-            store: SizedCache::with_size(size),
+        Self::with_size_and_lifespan_duration_and_refresh(
             size,
-            seconds,
-            hits: 0,
-            misses: 0,
+            Duration::from_secs(seconds),
             refresh,
-        }
+        )
+    }
+
+    /// Creates a new `SizedCache` with a given size limit, a lifespan as a `Duration`
+    /// and pre-allocated backing data. Also set if the ttl should be refreshed on retrieving
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0
+    #[must_use]
+    pub fn with_size_and_lifespan_duration_and_refresh(
+        size: usize,
+        lifespan: Duration,
+        refresh: bool,
+    ) -> TimedSizedCache<K, V> {
+        let mut cache =
+            Self::with_size_and_lifespan_duration_and_clock(size, lifespan, SystemClock);
+        cache.refresh = refresh;
+        cache
     }
 
     /// Creates a new `TimedSizedCache` with a specified lifespan and a given size limit and pre-allocated backing data
@@ -68,6 +93,19 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
     pub fn try_with_size_and_lifespan(
         size: usize,
         seconds: u64,
+    ) -> std::io::Result<TimedSizedCache<K, V>> {
+        Self::try_with_size_and_lifespan_duration(size, Duration::from_secs(seconds))
+    }
+
+    /// Creates a new `TimedSizedCache` with a specified lifespan as a `Duration` and a given
+    /// size limit and pre-allocated backing data
+    ///
+    /// # Errors
+    ///
+    /// Will return a `std::io::Error`, depending on the error
+    pub fn try_with_size_and_lifespan_duration(
+        size: usize,
+        lifespan: Duration,
     ) -> std::io::Result<TimedSizedCache<K, V>> {
         if size == 0 {
             // EINVAL
@@ -76,30 +114,60 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
         Ok(TimedSizedCache {
             store: SizedCache::try_with_size(size)?,
             size,
-            seconds,
+            lifespan,
             hits: 0,
             misses: 0,
             refresh: false,
+            clock: SystemClock,
         })
     }
+}
+
+impl<K: Hash + Eq + Clone, V, C: Clock> TimedSizedCache<K, V, C> {
+    /// Creates a new `SizedCache` with a given size limit, a lifespan as a `Duration`
+    /// and pre-allocated backing data, which reads the current time from `clock`
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0
+    #[must_use]
+    pub fn with_size_and_lifespan_duration_and_clock(
+        size: usize,
+        lifespan: Duration,
+        clock: C,
+    ) -> TimedSizedCache<K, V, C> {
+        if size == 0 {
+            panic!("`size` of `TimedSizedCache` must be greater than zero.");
+        }
+        TimedSizedCache {
+            store: SizedCache::with_size(size),
+            size,
+            lifespan,
+            hits: 0,
+            misses: 0,
+            refresh: false,
+            clock,
+        }
+    }
 
     fn iter_order(&self) -> impl Iterator<Item = &(K, (Instant, V))> {
-        let max_seconds = self.seconds;
+        let lifespan = self.lifespan;
+        let now = self.clock.now();
         self.store
             .iter_order()
-            .filter(move |(_k, stamped)| stamped.0.elapsed().as_secs() < max_seconds)
+            .filter(move |(_k, stamped)| now.duration_since(stamped.0) < lifespan)
     }
 
     /// Return an iterator of keys in the current order from most
     /// to least recently used.
-    /// Items passed their expiration seconds will be excluded.
+    /// Items passed their expiration lifespan will be excluded.
     pub fn key_order(&self) -> impl Iterator<Item = &K> {
         self.iter_order().map(|(k, _v)| k)
     }
 
     /// Return an iterator of timestamped values in the current order
     /// from most to least recently used.
-    /// Items passed their expiration seconds will be excluded.
+    /// Items passed their expiration lifespan will be excluded.
     pub fn value_order(&self) -> impl Iterator<Item = &(Instant, V)> {
         self.iter_order().map(|(_k, v)| v)
     }
@@ -123,13 +191,30 @@ impl<K: Hash + Eq + Clone, V> TimedSizedCache<K, V> {
 
     /// Remove any expired values from the cache
     pub fn flush(&mut self) {
-        let seconds = self.seconds;
+        let lifespan = self.lifespan;
+        let now = self.clock.now();
         self.store
-            .retain(|_, (instant, _)| instant.elapsed().as_secs() < seconds);
+            .retain(|_, (instant, _)| now.duration_since(*instant) < lifespan);
+    }
+
+    /// Returns a reference to the value of `key` along with the time elapsed
+    /// since it was stored, or last refreshed. Behaves like `cache_get`
+    /// otherwise, expired values are removed and counted as a miss.
+    pub fn cache_get_with_age<Q>(&mut self, key: &Q) -> Option<(&V, Duration)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+        let age = self
+            .store
+            .get_if(key, |_| true)
+            .map(|(instant, _)| now.duration_since(*instant));
+        self.cache_get(key).zip(age)
     }
 }
 
-impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
+impl<K: Hash + Eq + Clone, V, C: Clock> Cached<K, V> for TimedSizedCache<K, V, C> {
     fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
     where
         K: std::borrow::Borrow<Q>,
@@ -138,9 +223,9 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
         let status = {
             let mut val = self.store.get_mut_if(key, |_| true);
             if let Some(&mut (instant, _)) = val.as_mut() {
-                if instant.elapsed().as_secs() < self.seconds {
+                if self.clock.now().duration_since(*instant) < self.lifespan {
                     if self.refresh {
-                        *instant = Instant::now();
+                        *instant = self.clock.now();
                     }
                     Status::Found
                 } else {
@@ -175,9 +260,9 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
         let status = {
             let mut val = self.store.get_mut_if(key, |_| true);
             if let Some(&mut (instant, _)) = val.as_mut() {
-                if instant.elapsed().as_secs() < self.seconds {
+                if self.clock.now().duration_since(*instant) < self.lifespan {
                     if self.refresh {
-                        *instant = Instant::now();
+                        *instant = self.clock.now();
                     }
                     Status::Found
                 } else {
@@ -205,15 +290,16 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
     }
 
     fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
-        let setter = || (Instant::now(), f());
-        let max_seconds = self.seconds;
+        let clock = &self.clock;
+        let setter = || (clock.now(), f());
+        let lifespan = self.lifespan;
         let (was_present, was_valid, stamped) =
             self.store.get_or_set_with_if(key, setter, |stamped| {
-                stamped.0.elapsed().as_secs() < max_seconds
+                clock.now().duration_since(stamped.0) < lifespan
             });
         if was_present && was_valid {
             if self.refresh {
-                stamped.0 = Instant::now();
+                stamped.0 = self.clock.now();
             }
             self.hits += 1;
         } else {
@@ -223,9 +309,9 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
     }
 
     fn cache_set(&mut self, key: K, val: V) -> Option<V> {
-        let stamped = self.store.cache_set(key, (Instant::now(), val));
+        let stamped = self.store.cache_set(key, (self.clock.now(), val));
         stamped.and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
+            if self.clock.now().duration_since(instant) < self.lifespan {
                 Some(v)
             } else {
                 None
@@ -240,19 +326,31 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
     {
         let stamped = self.store.cache_remove(k);
         stamped.and_then(|(instant, v)| {
-            if instant.elapsed().as_secs() < self.seconds {
+            if self.clock.now().duration_since(instant) < self.lifespan {
                 Some(v)
             } else {
                 None
             }
         })
     }
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store
+            .peek(k)
+            .is_some_and(|(instant, _)| self.clock.now().duration_since(*instant) < self.lifespan)
+    }
     fn cache_clear(&mut self) {
         self.store.cache_clear();
     }
     fn cache_reset(&mut self) {
         self.cache_clear();
     }
+    fn cache_flush(&mut self) {
+        self.flush();
+    }
     fn cache_reset_metrics(&mut self) {
         self.misses = 0;
         self.hits = 0;
@@ -270,20 +368,26 @@ impl<K: Hash + Eq + Clone, V> Cached<K, V> for TimedSizedCache<K, V> {
         Some(self.size)
     }
     fn cache_lifespan(&self) -> Option<u64> {
-        Some(self.seconds)
+        Some(self.lifespan.as_secs())
     }
     fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
-        let old = self.seconds;
-        self.seconds = seconds;
-        Some(old)
+        self.cache_set_lifespan_duration(Duration::from_secs(seconds))
+            .map(|old| old.as_secs())
+    }
+    fn cache_lifespan_duration(&self) -> Option<Duration> {
+        Some(self.lifespan)
+    }
+    fn cache_set_lifespan_duration(&mut self, lifespan: Duration) -> Option<Duration> {
+        Some(std::mem::replace(&mut self.lifespan, lifespan))
     }
 }
 
 #[cfg(feature = "async")]
 #[async_trait]
-impl<K, V> CachedAsync<K, V> for TimedSizedCache<K, V>
+impl<K, V, C> CachedAsync<K, V> for TimedSizedCache<K, V, C>
 where
     K: Hash + Eq + Clone + Send,
+    C: Clock + Send + Sync,
 {
     async fn get_or_set_with<F, Fut>(&mut self, key: K, f: F) -> &mut V
     where
@@ -291,17 +395,18 @@ where
         F: FnOnce() -> Fut + Send,
         Fut: Future<Output = V> + Send,
     {
-        let setter = || async { (Instant::now(), f().await) };
-        let max_seconds = self.seconds;
+        let clock = &self.clock;
+        let setter = || async { (clock.now(), f().await) };
+        let lifespan = self.lifespan;
         let (was_present, was_valid, stamped) = self
             .store
             .get_or_set_with_if_async(key, setter, |stamped| {
-                stamped.0.elapsed().as_secs() < max_seconds
+                clock.now().duration_since(stamped.0) < lifespan
             })
             .await;
         if was_present && was_valid {
             if self.refresh {
-                stamped.0 = Instant::now();
+                stamped.0 = self.clock.now();
             }
             self.hits += 1;
         } else {
@@ -316,20 +421,21 @@ where
         F: FnOnce() -> Fut + Send,
         Fut: Future<Output = Result<V, E>> + Send,
     {
+        let clock = &self.clock;
         let setter = || async {
             let new_val = f().await?;
-            Ok((Instant::now(), new_val))
+            Ok((clock.now(), new_val))
         };
-        let max_seconds = self.seconds;
+        let lifespan = self.lifespan;
         let (was_present, was_valid, stamped) = self
             .store
             .try_get_or_set_with_if_async(key, setter, |stamped| {
-                stamped.0.elapsed().as_secs() < max_seconds
+                clock.now().duration_since(stamped.0) < lifespan
             })
             .await?;
         if was_present && was_valid {
             if self.refresh {
-                stamped.0 = Instant::now();
+                stamped.0 = self.clock.now();
             }
             self.hits += 1;
         } else {
@@ -451,6 +557,65 @@ mod tests {
         assert_eq!(c.cache_get(&2), None);
     }
 
+    #[test]
+    fn sub_second_lifespan() {
+        let mut c = TimedSizedCache::with_size_and_lifespan_duration(2, Duration::from_millis(100));
+        assert_eq!(
+            c.cache_lifespan_duration(),
+            Some(Duration::from_millis(100))
+        );
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&2, &1]);
+        sleep(Duration::from_millis(150));
+        assert_eq!(c.key_order().next(), None);
+        assert_eq!(c.cache_get(&1), None);
+    }
+
+    #[test]
+    fn mock_clock() {
+        use crate::MockClock;
+        let clock = MockClock::new();
+        let mut c = TimedSizedCache::with_size_and_lifespan_duration_and_clock(
+            2,
+            Duration::from_secs(10),
+            clock.clone(),
+        );
+
+        assert_eq!(c.cache_set(1, 100), None);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(c.cache_set(2, 200), None);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(c.key_order().collect::<Vec<_>>(), [&2]);
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.cache_get_or_set_with(1, || 101), &101);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(c.cache_get(&2), None);
+        assert_eq!(c.cache_get(&1), Some(&101));
+    }
+
+    #[test]
+    fn get_with_age() {
+        use crate::MockClock;
+        let clock = MockClock::new();
+        let mut c = TimedSizedCache::with_size_and_lifespan_duration_and_clock(
+            2,
+            Duration::from_secs(10),
+            clock.clone(),
+        );
+
+        c.cache_set(1, 100);
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(
+            c.cache_get_with_age(&1),
+            Some((&100, Duration::from_secs(4)))
+        );
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(c.cache_get_with_age(&1), None);
+        assert_eq!(c.cache_size(), 0);
+    }
+
     #[test]
     fn try_new() {
         let c: std::io::Result<TimedSizedCache<i32, i32>> =
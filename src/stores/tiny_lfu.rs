@@ -0,0 +1,659 @@
+use super::Cached;
+use crate::lru_list::LRUList;
+use std::cmp::Eq;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+
+#[cfg(feature = "async")]
+use {super::CachedAsync, async_trait::async_trait, futures::Future};
+
+/// Number of rows in the frequency sketch
+const SKETCH_DEPTH: usize = 4;
+/// Per-row seeds mixed into a key's hash to pick a counter
+const SKETCH_SEEDS: [u64; SKETCH_DEPTH] = [
+    0xc3a5_c85c_97cb_3127,
+    0xb492_b66f_be98_f273,
+    0x9ae1_6a3b_2f90_404f,
+    0xcbf2_9ce4_8422_2325,
+];
+/// Counters saturate at this value
+const SKETCH_MAX_COUNT: u8 = 15;
+
+/// Count-min sketch estimating how often keys have been seen recently.
+///
+/// All counters are halved once `sample_size` increments have been recorded
+/// so that keys that were popular a long time ago lose their advantage.
+#[derive(Clone, Debug)]
+pub(super) struct FrequencySketch {
+    table: Vec<u8>,
+    width_mask: usize,
+    additions: usize,
+    sample_size: usize,
+    hash_builder: RandomState,
+}
+
+impl FrequencySketch {
+    fn with_capacity(capacity: usize) -> FrequencySketch {
+        let width = capacity.saturating_mul(4).max(16).next_power_of_two();
+        FrequencySketch {
+            table: vec![0; width * SKETCH_DEPTH],
+            width_mask: width - 1,
+            additions: 0,
+            sample_size: capacity.saturating_mul(10).max(16),
+            hash_builder: RandomState::new(),
+        }
+    }
+
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        let hasher = &mut self.hash_builder.build_hasher();
+        key.hash(hasher);
+        hasher.finish()
+    }
+
+    fn index(&self, hash: u64, row: usize) -> usize {
+        let h = (hash ^ SKETCH_SEEDS[row]).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        row * (self.width_mask + 1) + ((h >> 32) as usize & self.width_mask)
+    }
+
+    fn increment(&mut self, hash: u64) {
+        let mut added = false;
+        for row in 0..SKETCH_DEPTH {
+            let i = self.index(hash, row);
+            if self.table[i] < SKETCH_MAX_COUNT {
+                self.table[i] += 1;
+                added = true;
+            }
+        }
+        if added {
+            self.additions += 1;
+            if self.additions >= self.sample_size {
+                self.age();
+            }
+        }
+    }
+
+    fn frequency(&self, hash: u64) -> u8 {
+        (0..SKETCH_DEPTH)
+            .map(|row| self.table[self.index(hash, row)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn age(&mut self) {
+        for counter in &mut self.table {
+            *counter >>= 1;
+        }
+        self.additions /= 2;
+    }
+
+    fn clear(&mut self) {
+        self.table.iter_mut().for_each(|counter| *counter = 0);
+        self.additions = 0;
+    }
+}
+
+/// The LRU segment of a `TinyLfuCache` a key currently lives in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Segment {
+    Window,
+    Probation,
+    Protected,
+}
+
+/// W-TinyLFU Cache
+///
+/// Stores up to a specified size. New keys enter a small LRU "window"
+/// (1% of the size). Keys evicted from the window only make it into the
+/// main segmented LRU if a frequency sketch estimates they are used more
+/// often than the entry they would replace. Keys hit while on probation
+/// are promoted to the protected segment (80% of the main space).
+///
+/// This keeps frequently used keys around when they are interleaved with
+/// scans of keys that are only ever seen once, which would flush them
+/// out of a `SizedCache`.
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct TinyLfuCache<K, V> {
+    // `store` contains K -> the segment and index of the (K, V) tuple in that segment's list
+    pub(super) store: HashMap<K, (Segment, usize)>,
+    pub(super) window: LRUList<(K, V)>,
+    pub(super) probation: LRUList<(K, V)>,
+    pub(super) protected: LRUList<(K, V)>,
+    pub(super) window_len: usize,
+    pub(super) probation_len: usize,
+    pub(super) protected_len: usize,
+    pub(super) window_capacity: usize,
+    pub(super) protected_capacity: usize,
+    pub(super) capacity: usize,
+    pub(super) sketch: FrequencySketch,
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> TinyLfuCache<K, V> {
+    /// Creates a new `TinyLfuCache` with a given size limit and pre-allocated backing data
+    ///
+    /// # Panics
+    ///
+    /// Will panic if size is 0
+    #[must_use]
+    pub fn with_size(size: usize) -> TinyLfuCache<K, V> {
+        if size == 0 {
+            panic!("`size` of `TinyLfuCache` must be greater than zero.");
+        }
+        TinyLfuCache::with_store(size, HashMap::with_capacity(size))
+    }
+
+    /// Creates a new `TinyLfuCache` with a given size limit and pre-allocated backing data
+    ///
+    /// # Errors
+    ///
+    /// Will return a `std::io::Error`, depending on the error
+    pub fn try_with_size(size: usize) -> std::io::Result<TinyLfuCache<K, V>> {
+        if size == 0 {
+            // EINVAL
+            return Err(std::io::Error::from_raw_os_error(22));
+        }
+
+        let mut store = HashMap::new();
+        if store.try_reserve(size).is_err() {
+            // ENOMEM
+            return Err(std::io::Error::from_raw_os_error(12));
+        }
+
+        Ok(TinyLfuCache::with_store(size, store))
+    }
+
+    fn with_store(size: usize, store: HashMap<K, (Segment, usize)>) -> TinyLfuCache<K, V> {
+        let window_capacity = (size / 100).max(1);
+        let protected_capacity = (size - window_capacity) * 8 / 10;
+        TinyLfuCache {
+            store,
+            window: LRUList::with_capacity(window_capacity),
+            probation: LRUList::with_capacity(size - window_capacity - protected_capacity),
+            protected: LRUList::with_capacity(protected_capacity),
+            window_len: 0,
+            probation_len: 0,
+            protected_len: 0,
+            window_capacity,
+            protected_capacity,
+            capacity: size,
+            sketch: FrequencySketch::with_capacity(size),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Return the estimated number of recent uses of `key`,
+    /// as used by the admission policy.
+    pub fn frequency<Q>(&self, key: &Q) -> u8
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.sketch.frequency(self.sketch.hash(key))
+    }
+
+    fn list(&self, segment: Segment) -> &LRUList<(K, V)> {
+        match segment {
+            Segment::Window => &self.window,
+            Segment::Probation => &self.probation,
+            Segment::Protected => &self.protected,
+        }
+    }
+
+    fn list_mut(&mut self, segment: Segment) -> &mut LRUList<(K, V)> {
+        match segment {
+            Segment::Window => &mut self.window,
+            Segment::Probation => &mut self.probation,
+            Segment::Protected => &mut self.protected,
+        }
+    }
+
+    fn len_mut(&mut self, segment: Segment) -> &mut usize {
+        match segment {
+            Segment::Window => &mut self.window_len,
+            Segment::Probation => &mut self.probation_len,
+            Segment::Protected => &mut self.protected_len,
+        }
+    }
+
+    // remove the entry at `index` from `segment`, leaving the `store` untouched
+    fn unlink(&mut self, segment: Segment, index: usize) -> (K, V) {
+        *self.len_mut(segment) -= 1;
+        self.list_mut(segment).remove(index)
+    }
+
+    // push an entry to the front of `segment` and point the `store` at it
+    fn link(&mut self, segment: Segment, entry: (K, V)) -> usize {
+        *self.len_mut(segment) += 1;
+        let location = self
+            .store
+            .get_mut(&entry.0)
+            .expect("TinyLfuCache key is not stored");
+        let index = match segment {
+            Segment::Window => self.window.push_front(entry),
+            Segment::Probation => self.probation.push_front(entry),
+            Segment::Protected => self.protected.push_front(entry),
+        };
+        *location = (segment, index);
+        index
+    }
+
+    // record a use of `key` and move it to where its next access should
+    // find it. Returns the key's location if it is present.
+    fn access<Q>(&mut self, key: &Q) -> Option<(Segment, usize)>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        let hash = self.sketch.hash(key);
+        self.sketch.increment(hash);
+        let (segment, index) = *self.store.get(key)?;
+        match segment {
+            Segment::Window | Segment::Protected => {
+                self.list_mut(segment).move_to_front(index);
+                Some((segment, index))
+            }
+            Segment::Probation => {
+                let entry = self.unlink(Segment::Probation, index);
+                self.link(Segment::Protected, entry);
+                if self.protected_len > self.protected_capacity {
+                    // demote the least recently used protected entry
+                    let back = self.protected.back();
+                    let demoted = self.unlink(Segment::Protected, back);
+                    self.link(Segment::Probation, demoted);
+                }
+                // with a tiny protected segment the key itself may have been demoted
+                self.store.get(key).copied()
+            }
+        }
+    }
+
+    // insert a key that is known to not be present
+    fn insert_new(&mut self, key: K, value: V) -> (Segment, usize) {
+        self.store.insert(key.clone(), (Segment::Window, 0));
+        let index = self.link(Segment::Window, (key, value));
+        if self.window_len > self.window_capacity {
+            let back = self.window.back();
+            let candidate = self.unlink(Segment::Window, back);
+            self.admit(candidate);
+        }
+        (Segment::Window, index)
+    }
+
+    // decide whether an entry evicted from the window replaces the main segment's victim
+    fn admit(&mut self, candidate: (K, V)) {
+        let main_capacity = self.capacity - self.window_capacity;
+        if self.probation_len + self.protected_len < main_capacity {
+            self.link(Segment::Probation, candidate);
+            return;
+        }
+        let victim_segment = if self.probation_len > 0 {
+            Segment::Probation
+        } else if self.protected_len > 0 {
+            Segment::Protected
+        } else {
+            self.store.remove(&candidate.0);
+            return;
+        };
+        let victim_index = self.list(victim_segment).back();
+        let victim_key = &self.list(victim_segment).get(victim_index).0;
+        let candidate_freq = self.sketch.frequency(self.sketch.hash(&candidate.0));
+        let victim_freq = self.sketch.frequency(self.sketch.hash(victim_key));
+        if candidate_freq > victim_freq {
+            let (victim_key, _) = self.unlink(victim_segment, victim_index);
+            self.store.remove(&victim_key);
+            self.link(Segment::Probation, candidate);
+        } else {
+            self.store.remove(&candidate.0);
+        }
+    }
+
+    fn get_mut_at(&mut self, (segment, index): (Segment, usize)) -> &mut V {
+        &mut self.list_mut(segment).get_mut(index).1
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cached<K, V> for TinyLfuCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        match self.access(key) {
+            Some((segment, index)) => {
+                self.hits += 1;
+                Some(&self.list(segment).get(index).1)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        match self.access(key) {
+            Some(location) => {
+                self.hits += 1;
+                Some(self.get_mut_at(location))
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        match self.access(&key) {
+            Some(location) => Some(std::mem::replace(self.get_mut_at(location), val)),
+            None => {
+                self.insert_new(key, val);
+                None
+            }
+        }
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let location = match self.access(&key) {
+            Some(location) => {
+                self.hits += 1;
+                location
+            }
+            None => {
+                self.misses += 1;
+                self.insert_new(key, f())
+            }
+        };
+        self.get_mut_at(location)
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        let (segment, index) = self.store.remove(k)?;
+        let (_key, value) = self.unlink(segment, index);
+        Some(value)
+    }
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store.contains_key(k)
+    }
+    fn cache_clear(&mut self) {
+        self.store.clear();
+        self.window.clear();
+        self.probation.clear();
+        self.protected.clear();
+        self.window_len = 0;
+        self.probation_len = 0;
+        self.protected_len = 0;
+    }
+    fn cache_reset(&mut self) {
+        self.cache_clear();
+        self.sketch.clear();
+    }
+    fn cache_reset_metrics(&mut self) {
+        self.misses = 0;
+        self.hits = 0;
+    }
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl<K, V> CachedAsync<K, V> for TinyLfuCache<K, V>
+where
+    K: Hash + Eq + Clone + Send,
+{
+    async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+    {
+        let location = match self.access(&k) {
+            Some(location) => {
+                self.hits += 1;
+                location
+            }
+            None => {
+                self.misses += 1;
+                let v = f().await;
+                self.insert_new(k, v)
+            }
+        };
+        self.get_mut_at(location)
+    }
+
+    async fn try_get_or_set_with<F, Fut, E>(&mut self, k: K, f: F) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+    {
+        let location = match self.access(&k) {
+            Some(location) => {
+                self.hits += 1;
+                location
+            }
+            None => {
+                self.misses += 1;
+                let v = f().await?;
+                self.insert_new(k, v)
+            }
+        };
+        Ok(self.get_mut_at(location))
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiny_lfu_cache() {
+        let mut c = TinyLfuCache::with_size(3);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_misses(), Some(1));
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(1));
+
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.cache_set(3, 301), Some(300));
+        assert_eq!(c.cache_get_mut(&2), Some(&mut 200));
+
+        assert_eq!(c.cache_set(4, 400), None);
+        assert_eq!(c.cache_size(), 3);
+        assert_eq!(c.cache_capacity(), Some(3));
+
+        c.cache_reset_metrics();
+        assert_eq!(c.cache_hits(), Some(0));
+        assert_eq!(c.cache_misses(), Some(0));
+    }
+
+    #[test]
+    fn try_new() {
+        let c: std::io::Result<TinyLfuCache<i32, i32>> = TinyLfuCache::try_with_size(0);
+        assert_eq!(c.unwrap_err().raw_os_error(), Some(22));
+
+        let mut c = TinyLfuCache::try_with_size(3).unwrap();
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_capacity(), Some(3));
+    }
+
+    #[test]
+    fn scan_does_not_flush_hot_keys() {
+        let mut c = TinyLfuCache::with_size(100);
+        for _ in 0..5 {
+            for n in 0..10 {
+                c.cache_get_or_set_with(n, || n);
+            }
+        }
+        // a `SizedCache` of the same size would lose the hot keys
+        // after every 100 one-off keys
+        for n in 1000..5000 {
+            c.cache_get_or_set_with(n, || n);
+            if n % 200 == 0 {
+                for h in 0..10 {
+                    c.cache_get_or_set_with(h, || h);
+                }
+            }
+        }
+        assert_eq!(c.cache_size(), 100);
+        for n in 0..10 {
+            assert_eq!(c.cache_get(&n), Some(&n));
+        }
+    }
+
+    #[test]
+    fn segments_stay_within_capacity() {
+        let mut c = TinyLfuCache::with_size(10);
+        for round in 0..20 {
+            for n in 0..(round * 3) {
+                c.cache_get_or_set_with(n % 17, || n);
+            }
+            assert!(c.cache_size() <= 10);
+            assert_eq!(
+                c.cache_size(),
+                c.window_len + c.probation_len + c.protected_len
+            );
+            assert!(c.window_len <= c.window_capacity);
+            assert!(c.protected_len <= c.protected_capacity);
+        }
+    }
+
+    #[test]
+    fn frequency_sketch_ages() {
+        let mut sketch = FrequencySketch::with_capacity(1);
+        let hash = sketch.hash(&1);
+        for _ in 0..20 {
+            sketch.increment(hash);
+        }
+        assert_eq!(sketch.frequency(hash), SKETCH_MAX_COUNT);
+
+        // counters are halved once `sample_size` increments were recorded
+        for n in 2..16 {
+            sketch.increment(sketch.hash(&n));
+        }
+        assert!(sketch.frequency(hash) < SKETCH_MAX_COUNT);
+    }
+
+    #[test]
+    fn clear() {
+        let mut c = TinyLfuCache::with_size(3);
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+        c.cache_clear();
+
+        assert_eq!(0, c.cache_size());
+        assert!(c.cache_get(&1).is_none());
+
+        c.cache_reset();
+        assert_eq!(c.frequency(&1), 0);
+    }
+
+    #[test]
+    fn remove() {
+        let mut c = TinyLfuCache::with_size(3);
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(c.cache_set(2, 200), None);
+        assert_eq!(c.cache_set(3, 300), None);
+
+        assert_eq!(Some(100), c.cache_remove(&1));
+        assert_eq!(2, c.cache_size());
+
+        assert_eq!(Some(200), c.cache_remove(&2));
+        assert_eq!(1, c.cache_size());
+
+        assert_eq!(None, c.cache_remove(&2));
+        assert_eq!(1, c.cache_size());
+    }
+
+    #[test]
+    fn get_or_set_with() {
+        let mut c = TinyLfuCache::with_size(5);
+
+        assert_eq!(c.cache_get_or_set_with(0, || 0), &0);
+        assert_eq!(c.cache_get_or_set_with(1, || 1), &1);
+        assert_eq!(c.cache_misses(), Some(2));
+
+        assert_eq!(c.cache_get_or_set_with(0, || 42), &0);
+        assert_eq!(c.cache_get_or_set_with(1, || 42), &1);
+        assert_eq!(c.cache_misses(), Some(2));
+        assert_eq!(c.cache_hits(), Some(2));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_trait() {
+        use crate::CachedAsync;
+        let mut c = TinyLfuCache::with_size(5);
+
+        async fn _get(n: usize) -> usize {
+            n
+        }
+
+        assert_eq!(c.get_or_set_with(0, || async { _get(0).await }).await, &0);
+        assert_eq!(c.get_or_set_with(1, || async { _get(1).await }).await, &1);
+        assert_eq!(c.get_or_set_with(0, || async { _get(3).await }).await, &0);
+        assert_eq!(c.get_or_set_with(1, || async { _get(3).await }).await, &1);
+
+        c.cache_reset();
+        async fn _try_get(n: usize) -> Result<usize, String> {
+            if n < 10 {
+                Ok(n)
+            } else {
+                Err("dead".to_string())
+            }
+        }
+
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(10).await })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(c.cache_size(), 0);
+
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(1).await })
+            .await;
+        assert_eq!(res.unwrap(), &1);
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(5).await })
+            .await;
+        assert_eq!(res.unwrap(), &1);
+    }
+}
@@ -0,0 +1,474 @@
+use std::cmp::Eq;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+
+use instant::Instant;
+
+#[cfg(feature = "async")]
+use {super::CachedAsync, async_trait::async_trait, futures::Future};
+
+use super::Cached;
+use crate::clock::{Clock, SystemClock};
+
+/// An entry of a `TtlCache`: when it was inserted, its time to live and value
+#[derive(Clone, Debug)]
+pub(super) struct TtlEntry<V> {
+    pub(super) instant: Instant,
+    pub(super) ttl: Duration,
+    pub(super) value: V,
+}
+
+impl<V> TtlEntry<V> {
+    fn new(value: V, ttl: Duration, now: Instant) -> Self {
+        TtlEntry {
+            instant: now,
+            ttl,
+            value,
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        now.duration_since(self.instant) >= self.ttl
+    }
+
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.ttl.checked_sub(now.duration_since(self.instant))
+    }
+}
+
+/// Cache store with a time to live per entry
+///
+/// Each value carries its own lifespan, given when inserted with
+/// `cache_set_with_ttl`, and is evicted if expired at time of retrieval.
+/// Values inserted through the `Cached` trait use the default lifespan.
+/// Time is read from a [`Clock`], the [`SystemClock`] by default.
+///
+/// Note: This cache is in-memory only
+#[derive(Clone, Debug)]
+pub struct TtlCache<K, V, C = SystemClock> {
+    pub(super) store: HashMap<K, TtlEntry<V>>,
+    pub(super) default_ttl: Duration,
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+    pub(super) clock: C,
+}
+
+impl<K: Hash + Eq, V> TtlCache<K, V> {
+    /// Creates a new `TtlCache` where values inserted without
+    /// an explicit lifespan live for `default_ttl`
+    #[must_use]
+    pub fn with_default_ttl(default_ttl: Duration) -> TtlCache<K, V> {
+        Self::with_default_ttl_and_clock(default_ttl, SystemClock)
+    }
+}
+
+impl<K: Hash + Eq, V, C: Clock> TtlCache<K, V, C> {
+    /// Creates a new `TtlCache` where values inserted without an explicit
+    /// lifespan live for `default_ttl`, which reads the current time from `clock`
+    #[must_use]
+    pub fn with_default_ttl_and_clock(default_ttl: Duration, clock: C) -> TtlCache<K, V, C> {
+        TtlCache {
+            store: HashMap::new(),
+            default_ttl,
+            hits: 0,
+            misses: 0,
+            clock,
+        }
+    }
+
+    /// Returns the lifespan of values inserted without an explicit one
+    #[must_use]
+    pub fn default_ttl(&self) -> Duration {
+        self.default_ttl
+    }
+
+    /// Sets the lifespan of values inserted without an explicit one,
+    /// values already in the cache keep their lifespan
+    pub fn set_default_ttl(&mut self, default_ttl: Duration) -> Duration {
+        std::mem::replace(&mut self.default_ttl, default_ttl)
+    }
+
+    /// Insert a key, value pair that expires after `ttl`
+    /// and return the previous unexpired value
+    ///
+    /// ```rust
+    /// use cached::{Cached, TtlCache};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = TtlCache::with_default_ttl(Duration::from_secs(60));
+    /// cache.cache_set_with_ttl("token", "abc", Duration::from_secs(3600));
+    /// cache.cache_set("answer", "42");
+    /// assert!(cache.ttl("token").unwrap() > Duration::from_secs(60));
+    /// assert!(cache.ttl("answer").unwrap() <= Duration::from_secs(60));
+    /// ```
+    pub fn cache_set_with_ttl(&mut self, key: K, val: V, ttl: Duration) -> Option<V> {
+        self.store
+            .insert(key, TtlEntry::new(val, ttl, self.clock.now()))
+            .filter(|entry| !entry.is_expired(self.clock.now()))
+            .map(|entry| entry.value)
+    }
+
+    /// Returns the remaining lifespan of an unexpired value
+    pub fn ttl<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store
+            .get(key)
+            .and_then(|entry| entry.remaining(self.clock.now()))
+    }
+
+    /// Remove any expired values from the cache
+    pub fn flush(&mut self) {
+        let now = self.clock.now();
+        self.store.retain(|_, entry| !entry.is_expired(now));
+    }
+
+    // remove the entry for `key` if it is expired and
+    // record the lookup in the hit/miss counters
+    fn check_expiry<Q>(&mut self, key: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        match self
+            .store
+            .get(key)
+            .map(|entry| entry.is_expired(self.clock.now()))
+        {
+            Some(false) => {
+                self.hits += 1;
+                true
+            }
+            Some(true) => {
+                self.misses += 1;
+                self.store.remove(key);
+                false
+            }
+            None => {
+                self.misses += 1;
+                false
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, C: Clock> Cached<K, V> for TtlCache<K, V, C> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        if self.check_expiry(key) {
+            self.store.get(key).map(|entry| &entry.value)
+        } else {
+            None
+        }
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        if self.check_expiry(key) {
+            self.store.get_mut(key).map(|entry| &mut entry.value)
+        } else {
+            None
+        }
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        match self.store.entry(key) {
+            Entry::Occupied(mut occupied) => {
+                if occupied.get().is_expired(self.clock.now()) {
+                    self.misses += 1;
+                    occupied.insert(TtlEntry::new(f(), self.default_ttl, self.clock.now()));
+                } else {
+                    self.hits += 1;
+                }
+                &mut occupied.into_mut().value
+            }
+            Entry::Vacant(vacant) => {
+                self.misses += 1;
+                &mut vacant
+                    .insert(TtlEntry::new(f(), self.default_ttl, self.clock.now()))
+                    .value
+            }
+        }
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        self.cache_set_with_ttl(key, val, self.default_ttl)
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store
+            .remove(k)
+            .filter(|entry| !entry.is_expired(self.clock.now()))
+            .map(|entry| entry.value)
+    }
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store
+            .get(k)
+            .is_some_and(|entry| !entry.is_expired(self.clock.now()))
+    }
+    fn cache_clear(&mut self) {
+        self.store.clear();
+    }
+    fn cache_reset_metrics(&mut self) {
+        self.misses = 0;
+        self.hits = 0;
+    }
+    fn cache_reset(&mut self) {
+        self.store = HashMap::new();
+    }
+    fn cache_flush(&mut self) {
+        self.flush();
+    }
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_lifespan(&self) -> Option<u64> {
+        Some(self.default_ttl.as_secs())
+    }
+
+    fn cache_set_lifespan(&mut self, seconds: u64) -> Option<u64> {
+        let old = self.set_default_ttl(Duration::from_secs(seconds));
+        Some(old.as_secs())
+    }
+
+    fn cache_lifespan_duration(&self) -> Option<Duration> {
+        Some(self.default_ttl)
+    }
+
+    fn cache_set_lifespan_duration(&mut self, lifespan: Duration) -> Option<Duration> {
+        Some(self.set_default_ttl(lifespan))
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl<K, V, C> CachedAsync<K, V> for TtlCache<K, V, C>
+where
+    K: Hash + Eq + Clone + Send,
+    C: Clock + Send,
+{
+    async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+    {
+        match self.store.entry(k) {
+            Entry::Occupied(mut occupied) => {
+                if occupied.get().is_expired(self.clock.now()) {
+                    self.misses += 1;
+                    occupied.insert(TtlEntry::new(f().await, self.default_ttl, self.clock.now()));
+                } else {
+                    self.hits += 1;
+                }
+                &mut occupied.into_mut().value
+            }
+            Entry::Vacant(vacant) => {
+                self.misses += 1;
+                &mut vacant
+                    .insert(TtlEntry::new(f().await, self.default_ttl, self.clock.now()))
+                    .value
+            }
+        }
+    }
+
+    async fn try_get_or_set_with<F, Fut, E>(&mut self, k: K, f: F) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+    {
+        let v = match self.store.entry(k) {
+            Entry::Occupied(mut occupied) => {
+                if occupied.get().is_expired(self.clock.now()) {
+                    self.misses += 1;
+                    occupied.insert(TtlEntry::new(
+                        f().await?,
+                        self.default_ttl,
+                        self.clock.now(),
+                    ));
+                } else {
+                    self.hits += 1;
+                }
+                &mut occupied.into_mut().value
+            }
+            Entry::Vacant(vacant) => {
+                self.misses += 1;
+                &mut vacant
+                    .insert(TtlEntry::new(
+                        f().await?,
+                        self.default_ttl,
+                        self.clock.now(),
+                    ))
+                    .value
+            }
+        };
+
+        Ok(v)
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use std::thread::sleep;
+
+    use super::*;
+
+    #[test]
+    fn ttl_cache() {
+        let mut c = TtlCache::with_default_ttl(Duration::from_secs(2));
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_misses(), Some(1));
+
+        assert_eq!(c.cache_set(1, 100), None);
+        assert_eq!(
+            c.cache_set_with_ttl(2, 200, Duration::from_millis(100)),
+            None
+        );
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert_eq!(c.cache_get(&2), Some(&200));
+        assert_eq!(c.cache_hits(), Some(2));
+
+        sleep(Duration::from_millis(150));
+        assert_eq!(c.cache_get(&1), Some(&100));
+        assert!(c.cache_get(&2).is_none());
+        assert_eq!(c.cache_hits(), Some(3));
+        assert_eq!(c.cache_misses(), Some(2));
+        assert_eq!(c.cache_size(), 1);
+    }
+
+    #[test]
+    fn mock_clock() {
+        use crate::MockClock;
+        let clock = MockClock::new();
+        let mut c = TtlCache::with_default_ttl_and_clock(Duration::from_secs(60), clock.clone());
+        c.cache_set(1, 100);
+        c.cache_set_with_ttl(2, 200, Duration::from_secs(3600));
+
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(c.ttl(&1), Some(Duration::from_secs(1)));
+        assert_eq!(c.cache_get(&1), Some(&100));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(c.cache_get(&1), None);
+        assert_eq!(c.ttl(&2), Some(Duration::from_secs(3540)));
+    }
+
+    #[test]
+    fn remaining_ttl() {
+        let mut c = TtlCache::with_default_ttl(Duration::from_secs(10));
+        c.cache_set(1, 100);
+        c.cache_set_with_ttl(2, 200, Duration::from_secs(1000));
+        c.cache_set_with_ttl(3, 300, Duration::ZERO);
+
+        assert!(c.ttl(&1).unwrap() <= Duration::from_secs(10));
+        assert!(c.ttl(&2).unwrap() > Duration::from_secs(10));
+        assert!(c.ttl(&3).is_none());
+        assert!(c.ttl(&4).is_none());
+
+        assert_eq!(
+            c.set_default_ttl(Duration::from_secs(1)),
+            Duration::from_secs(10)
+        );
+        assert!(c.ttl(&1).unwrap() > Duration::from_secs(1));
+        assert_eq!(c.cache_set_lifespan(5), Some(1));
+        assert_eq!(c.cache_lifespan(), Some(5));
+    }
+
+    #[test]
+    fn set_and_remove_expired() {
+        let mut c = TtlCache::with_default_ttl(Duration::from_secs(10));
+        c.cache_set_with_ttl(1, 100, Duration::ZERO);
+        assert_eq!(c.cache_set(1, 101), None);
+        assert_eq!(c.cache_set(1, 102), Some(101));
+
+        c.cache_set_with_ttl(2, 200, Duration::ZERO);
+        assert_eq!(c.cache_remove(&2), None);
+        assert_eq!(c.cache_remove(&1), Some(102));
+        assert_eq!(c.cache_size(), 0);
+    }
+
+    #[test]
+    fn flush() {
+        let mut c = TtlCache::with_default_ttl(Duration::from_secs(10));
+        c.cache_set(1, 100);
+        c.cache_set_with_ttl(2, 200, Duration::ZERO);
+        c.cache_set_with_ttl(3, 300, Duration::ZERO);
+        assert_eq!(c.cache_size(), 3);
+        c.flush();
+        assert_eq!(c.cache_size(), 1);
+        assert_eq!(c.cache_get(&1), Some(&100));
+    }
+
+    #[test]
+    fn get_or_set_with() {
+        let mut c = TtlCache::with_default_ttl(Duration::from_secs(10));
+        c.cache_set_with_ttl(1, 100, Duration::ZERO);
+        assert_eq!(c.cache_get_or_set_with(1, || 101), &101);
+        assert_eq!(c.cache_get_or_set_with(1, || 102), &101);
+        assert_eq!(c.cache_get_or_set_with(2, || 200), &200);
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.cache_misses(), Some(2));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_trait() {
+        use crate::CachedAsync;
+        let mut c = TtlCache::with_default_ttl(Duration::from_secs(10));
+
+        async fn _get(n: usize) -> usize {
+            n
+        }
+
+        c.cache_set_with_ttl(0, 100, Duration::ZERO);
+        assert_eq!(c.get_or_set_with(0, || async { _get(0).await }).await, &0);
+        assert_eq!(c.get_or_set_with(0, || async { _get(1).await }).await, &0);
+
+        async fn _try_get(n: usize) -> Result<usize, String> {
+            if n < 10 {
+                Ok(n)
+            } else {
+                Err("dead".to_string())
+            }
+        }
+
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(1, || async { _try_get(10).await })
+            .await;
+        assert!(res.is_err());
+
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(1, || async { _try_get(1).await })
+            .await;
+        assert_eq!(res.unwrap(), &1);
+        assert_eq!(
+            c.ttl(&1).map(|ttl| ttl > Duration::from_secs(5)),
+            Some(true)
+        );
+    }
+}
@@ -123,6 +123,13 @@ impl<K: Hash + Eq, V> Cached<K, V> for UnboundCache<K, V> {
     {
         self.store.remove(k)
     }
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.store.contains_key(k)
+    }
     fn cache_clear(&mut self) {
         self.store.clear();
     }
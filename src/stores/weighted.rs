@@ -0,0 +1,488 @@
+use super::Cached;
+use crate::lru_list::LRUList;
+use std::cmp::Eq;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+
+#[cfg(feature = "async")]
+use {super::CachedAsync, async_trait::async_trait, futures::Future};
+
+/// Function used by a `WeightedCache` to determine the weight of an entry
+pub type Weigher<K, V> = Arc<dyn Fn(&K, &V) -> usize + Send + Sync>;
+
+/// Least Recently Used / `Weighted` Cache
+///
+/// Stores values until their total weight, as determined by a
+/// user supplied weigher function (e.g. the size of a value in bytes),
+/// exceeds a specified maximum and then evicts the least recently
+/// used keys until the total weight fits again.
+///
+/// The weight of an entry is computed when it is inserted. Values modified
+/// through `cache_get_mut` are not re-weighed. An entry heavier than the
+/// maximum weight is not cached: `cache_set` only removes the previous value
+/// of its key, and `cache_get_or_set_with` returns the value without caching it.
+/// That value is only held for the returned reference, and is dropped by the
+/// next call taking `&mut self`, so it never stays next to the cached values.
+///
+/// Note: This cache is in-memory only
+#[derive(Clone)]
+pub struct WeightedCache<K, V> {
+    // `store` contains K -> index of the (K, V, weight) tuple in `order`
+    pub(super) store: HashMap<K, usize>,
+    pub(super) order: LRUList<(K, V, usize)>,
+    pub(super) weigher: Weigher<K, V>,
+    pub(super) weight: usize,
+    pub(super) max_weight: usize,
+    // the last value too heavy to be cached, held to return a reference to it
+    // and dropped by the next call taking `&mut self`
+    pub(super) oversized: Option<V>,
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+}
+
+impl<K, V> fmt::Debug for WeightedCache<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeightedCache")
+            .field("order", &self.order)
+            .field("weight", &self.weight)
+            .field("max_weight", &self.max_weight)
+            .field("hits", &self.hits)
+            .field("misses", &self.misses)
+            .finish()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> WeightedCache<K, V> {
+    /// Creates a new `WeightedCache` that holds entries up to a total
+    /// weight of `max_weight`, as computed by `weigher`
+    ///
+    /// ```rust
+    /// use cached::{Cached, WeightedCache};
+    ///
+    /// let mut cache = WeightedCache::with_max_weight(1024, |_k: &u32, v: &Vec<u8>| v.len());
+    /// cache.cache_set(1, vec![0; 1000]);
+    /// cache.cache_set(2, vec![0; 100]);
+    /// assert_eq!(cache.cache_weight(), Some(100));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `max_weight` is 0
+    #[must_use]
+    pub fn with_max_weight<F>(max_weight: usize, weigher: F) -> WeightedCache<K, V>
+    where
+        F: Fn(&K, &V) -> usize + Send + Sync + 'static,
+    {
+        if max_weight == 0 {
+            panic!("`max_weight` of `WeightedCache` must be greater than zero.");
+        }
+        WeightedCache {
+            store: HashMap::new(),
+            order: LRUList::with_capacity(0),
+            weigher: Arc::new(weigher),
+            weight: 0,
+            max_weight,
+            oversized: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Return the maximum total weight of cached values
+    #[must_use]
+    pub fn max_weight(&self) -> usize {
+        self.max_weight
+    }
+
+    /// Return an iterator of keys in the current order from most
+    /// to least recently used.
+    pub fn key_order(&self) -> impl Iterator<Item = &K> {
+        self.order.iter().map(|(k, _v, _w)| k)
+    }
+
+    /// Return an iterator of values in the current order from most
+    /// to least recently used.
+    pub fn value_order(&self) -> impl Iterator<Item = &V> {
+        self.order.iter().map(|(_k, v, _w)| v)
+    }
+
+    fn touch<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        let index = *self.store.get(key)?;
+        self.order.move_to_front(index);
+        Some(index)
+    }
+
+    // insert a key that is known to not be present after evicting the least
+    // recently used keys until its weight fits, values heavier than the
+    // maximum weight are handed back
+    fn insert_new(&mut self, key: K, value: V) -> Result<usize, V> {
+        let weight = (self.weigher)(&key, &value);
+        if weight > self.max_weight {
+            return Err(value);
+        }
+        self.make_room(weight);
+        let index = self.order.push_front((key.clone(), value, weight));
+        self.store.insert(key, index);
+        self.weight = self.weight.saturating_add(weight);
+        Ok(index)
+    }
+
+    // compared without adding, so the weights can't overflow
+    fn make_room(&mut self, weight: usize) {
+        while self.weight > self.max_weight - weight {
+            let (key, _value, weight) = self.order.remove(self.order.back());
+            self.store.remove(&key);
+            self.weight -= weight;
+        }
+    }
+
+    // the value of an entry returned by `insert_new`
+    fn inserted_mut(&mut self, inserted: Result<usize, V>) -> &mut V {
+        match inserted {
+            Ok(index) => &mut self.order.get_mut(index).1,
+            Err(value) => self.oversized.insert(value),
+        }
+    }
+
+    /// Remove the entries for which `keep` returns `false`
+    ///
+    /// ```rust
+    /// use cached::{Cached, WeightedCache};
+    ///
+    /// let mut cache = WeightedCache::with_max_weight(1024, |_k: &u32, v: &Vec<u8>| v.len());
+    /// cache.cache_set(1, vec![0; 10]);
+    /// cache.cache_set(2, vec![0; 20]);
+    /// cache.retain(|_k, v| v.len() > 10);
+    /// assert_eq!(cache.cache_weight(), Some(20));
+    /// ```
+    pub fn retain<F: Fn(&K, &V) -> bool>(&mut self, keep: F) {
+        self.oversized = None;
+        let remove_keys = self
+            .key_order()
+            .zip(self.value_order())
+            .filter_map(|(k, v)| if keep(k, v) { None } else { Some(k.clone()) })
+            .collect::<Vec<_>>();
+        for k in remove_keys {
+            self.cache_remove(&k);
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Cached<K, V> for WeightedCache<K, V> {
+    fn cache_get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.oversized = None;
+        if let Some(index) = self.touch(key) {
+            self.hits += 1;
+            Some(&self.order.get(index).1)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.oversized = None;
+        if let Some(index) = self.touch(key) {
+            self.hits += 1;
+            Some(&mut self.order.get_mut(index).1)
+        } else {
+            self.misses += 1;
+            None
+        }
+    }
+
+    fn cache_set(&mut self, key: K, val: V) -> Option<V> {
+        self.oversized = None;
+        let old = self.cache_remove(&key);
+        // a value too heavy to be cached is dropped
+        let _ = self.insert_new(key, val);
+        old
+    }
+
+    fn cache_get_or_set_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        self.oversized = None;
+        let inserted = if let Some(index) = self.touch(&key) {
+            self.hits += 1;
+            Ok(index)
+        } else {
+            self.misses += 1;
+            self.insert_new(key, f())
+        };
+        self.inserted_mut(inserted)
+    }
+
+    fn cache_remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.oversized = None;
+        let index = self.store.remove(k)?;
+        let (_key, value, weight) = self.order.remove(index);
+        self.weight -= weight;
+        Some(value)
+    }
+    fn cache_contains<Q>(&mut self, k: &Q) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: std::hash::Hash + Eq + ?Sized,
+    {
+        self.oversized = None;
+        self.store.contains_key(k)
+    }
+    fn cache_clear(&mut self) {
+        self.store.clear();
+        self.order.clear();
+        self.oversized = None;
+        self.weight = 0;
+    }
+    fn cache_reset(&mut self) {
+        self.store = HashMap::new();
+        self.order = LRUList::with_capacity(0);
+        self.oversized = None;
+        self.weight = 0;
+    }
+    fn cache_reset_metrics(&mut self) {
+        self.oversized = None;
+        self.misses = 0;
+        self.hits = 0;
+    }
+    fn cache_size(&self) -> usize {
+        self.store.len()
+    }
+    fn cache_hits(&self) -> Option<u64> {
+        Some(self.hits)
+    }
+    fn cache_misses(&self) -> Option<u64> {
+        Some(self.misses)
+    }
+    fn cache_weight(&self) -> Option<usize> {
+        Some(self.weight)
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl<K, V> CachedAsync<K, V> for WeightedCache<K, V>
+where
+    K: Hash + Eq + Clone + Send,
+{
+    async fn get_or_set_with<F, Fut>(&mut self, k: K, f: F) -> &mut V
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = V> + Send,
+    {
+        self.oversized = None;
+        let inserted = if let Some(index) = self.touch(&k) {
+            self.hits += 1;
+            Ok(index)
+        } else {
+            self.misses += 1;
+            let v = f().await;
+            self.insert_new(k, v)
+        };
+        self.inserted_mut(inserted)
+    }
+
+    async fn try_get_or_set_with<F, Fut, E>(&mut self, k: K, f: F) -> Result<&mut V, E>
+    where
+        V: Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<V, E>> + Send,
+    {
+        self.oversized = None;
+        let inserted = if let Some(index) = self.touch(&k) {
+            self.hits += 1;
+            Ok(index)
+        } else {
+            self.misses += 1;
+            let v = f().await?;
+            self.insert_new(k, v)
+        };
+        Ok(self.inserted_mut(inserted))
+    }
+}
+
+#[cfg(test)]
+/// Cache store tests
+mod tests {
+    use super::*;
+
+    fn byte_cache(max_weight: usize) -> WeightedCache<u32, Vec<u8>> {
+        WeightedCache::with_max_weight(max_weight, |_k, v: &Vec<u8>| v.len())
+    }
+
+    #[test]
+    fn weighted_cache() {
+        let mut c = byte_cache(100);
+        assert!(c.cache_get(&1).is_none());
+        assert_eq!(c.cache_misses(), Some(1));
+
+        assert_eq!(c.cache_set(1, vec![0; 40]), None);
+        assert_eq!(c.cache_set(2, vec![0; 40]), None);
+        assert_eq!(c.cache_weight(), Some(80));
+        assert_eq!(c.cache_get(&1).map(Vec::len), Some(40));
+        assert_eq!(c.cache_hits(), Some(1));
+
+        // 2 is the least recently used
+        assert_eq!(c.cache_set(3, vec![0; 30]), None);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [3, 1]);
+        assert_eq!(c.cache_weight(), Some(70));
+
+        // replacing a value re-weighs it
+        assert_eq!(c.cache_set(1, vec![0; 10]).map(|v| v.len()), Some(40));
+        assert_eq!(c.cache_weight(), Some(40));
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [1, 3]);
+
+        // many small values can be stored
+        for n in 10..19 {
+            c.cache_set(n, vec![0; 5]);
+        }
+        assert_eq!(c.cache_size(), 11);
+        assert_eq!(c.cache_weight(), Some(85));
+    }
+
+    #[test]
+    fn oversized_value() {
+        let mut c = byte_cache(10);
+        assert_eq!(c.cache_set(1, vec![0; 5]), None);
+        assert_eq!(c.cache_set(2, vec![0; 50]), None);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [1]);
+        assert_eq!(c.cache_weight(), Some(5));
+
+        // the previous value of the key is removed
+        assert_eq!(c.cache_set(1, vec![0; 50]).map(|v| v.len()), Some(5));
+        assert_eq!(c.cache_size(), 0);
+        assert_eq!(c.cache_weight(), Some(0));
+
+        // the value is returned without being cached
+        assert_eq!(c.cache_set(3, vec![0; 5]), None);
+        assert_eq!(c.cache_get_or_set_with(4, || vec![4; 11]), &[4; 11]);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [3]);
+        assert_eq!(c.cache_weight(), Some(5));
+        assert_eq!(c.cache_get(&4), None);
+    }
+
+    #[test]
+    fn oversized_value_dropped() {
+        let mut c = WeightedCache::with_max_weight(10, |_k: &u32, v: &Arc<Vec<u8>>| v.len());
+        let value = Arc::new(vec![0; 50]);
+        let weak = Arc::downgrade(&value);
+        assert_eq!(c.cache_get_or_set_with(1, || value).len(), 50);
+        assert!(weak.upgrade().is_some());
+        // the next call frees it
+        assert!(c.cache_get(&1).is_none());
+        assert!(weak.upgrade().is_none());
+        assert_eq!(c.cache_size(), 0);
+    }
+
+    #[test]
+    fn weight_overflow() {
+        let mut c = WeightedCache::with_max_weight(usize::MAX, |_k: &u32, v: &usize| *v);
+        assert_eq!(c.cache_set(1, usize::MAX), None);
+        assert_eq!(c.cache_set(2, usize::MAX), None);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [2]);
+        assert_eq!(c.cache_weight(), Some(usize::MAX));
+    }
+
+    #[test]
+    fn clear() {
+        let mut c = byte_cache(100);
+
+        assert_eq!(c.cache_set(1, vec![1]), None);
+        assert_eq!(c.cache_set(2, vec![2]), None);
+        c.cache_clear();
+
+        assert_eq!(0, c.cache_size());
+        assert_eq!(Some(0), c.cache_weight());
+    }
+
+    #[test]
+    fn remove() {
+        let mut c = byte_cache(100);
+
+        assert_eq!(c.cache_set(1, vec![1]), None);
+        assert_eq!(c.cache_set(2, vec![2, 2]), None);
+
+        assert_eq!(Some(vec![1]), c.cache_remove(&1));
+        assert_eq!(1, c.cache_size());
+        assert_eq!(Some(2), c.cache_weight());
+        assert_eq!(None, c.cache_remove(&1));
+
+        c.retain(|k, _| *k != 2);
+        assert_eq!(Some(0), c.cache_weight());
+    }
+
+    #[test]
+    fn get_or_set_with() {
+        let mut c = byte_cache(3);
+
+        assert_eq!(c.cache_get_or_set_with(0, || vec![0]), &[0]);
+        assert_eq!(c.cache_get_or_set_with(1, || vec![1]), &[1]);
+        assert_eq!(c.cache_get_or_set_with(0, || vec![42]), &[0]);
+        assert_eq!(c.cache_get_or_set_with(2, || vec![2, 2]), &[2, 2]);
+        assert_eq!(c.cache_misses(), Some(3));
+        assert_eq!(c.cache_hits(), Some(1));
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [2, 0]);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_trait() {
+        use crate::CachedAsync;
+        let mut c = WeightedCache::with_max_weight(2, |_k: &usize, _v: &usize| 1);
+
+        async fn _get(n: usize) -> usize {
+            n
+        }
+
+        assert_eq!(c.get_or_set_with(0, || async { _get(0).await }).await, &0);
+        assert_eq!(c.get_or_set_with(1, || async { _get(1).await }).await, &1);
+        assert_eq!(c.get_or_set_with(0, || async { _get(3).await }).await, &0);
+        assert_eq!(c.get_or_set_with(2, || async { _get(2).await }).await, &2);
+        assert_eq!(c.key_order().copied().collect::<Vec<_>>(), [2, 0]);
+
+        c.cache_reset();
+        async fn _try_get(n: usize) -> Result<usize, String> {
+            if n < 10 {
+                Ok(n)
+            } else {
+                Err("dead".to_string())
+            }
+        }
+
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(10).await })
+            .await;
+        assert!(res.is_err());
+        assert!(c.key_order().next().is_none());
+
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(1).await })
+            .await;
+        assert_eq!(res.unwrap(), &1);
+        let res: Result<&mut usize, String> = c
+            .try_get_or_set_with(0, || async { _try_get(5).await })
+            .await;
+        assert_eq!(res.unwrap(), &1);
+    }
+}
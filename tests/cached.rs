@@ -5,10 +5,13 @@ Full tests of macro-defined functions
 extern crate cached;
 
 use cached::{
-    proc_macro::cached, proc_macro::once, Cached, CanExpire, ExpiringValueCache, SizedCache,
-    TimedCache, TimedSizedCache, UnboundCache,
+    proc_macro::cached, proc_macro::once, ArcCache, Cached, CanExpire, ConcurrentCached,
+    ExpiringValueCache, LfuCache, MockClock, SizedCache, TimedCache, TimedSizedCache, TinyLfuCache,
+    UnboundCache,
 };
 use serial_test::serial;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Barrier;
 use std::thread::{self, sleep};
 use std::time::Duration;
 
@@ -604,6 +607,40 @@ fn test_only_cached_once_per_second() {
     assert_eq!(vec!["b".to_string()], b);
 }
 
+#[once(time_ms = 200)]
+fn only_cached_once_per_200ms(s: String) -> Vec<String> {
+    vec![s]
+}
+
+#[test]
+fn test_only_cached_once_per_200ms() {
+    let a = only_cached_once_per_200ms("a".to_string());
+    let b = only_cached_once_per_200ms("b".to_string());
+    assert_eq!(a, b);
+    sleep(Duration::from_millis(250));
+    let b = only_cached_once_per_200ms("b".to_string());
+    assert_eq!(vec!["b".to_string()], b);
+}
+
+static ONCE_CLOCK: cached::once_cell::sync::Lazy<MockClock> =
+    cached::once_cell::sync::Lazy::new(MockClock::new);
+
+#[once(time = 60, clock = "ONCE_CLOCK")]
+fn only_cached_once_per_minute(s: String) -> Vec<String> {
+    vec![s]
+}
+
+#[test]
+fn test_only_cached_once_per_minute() {
+    let a = only_cached_once_per_minute("a".to_string());
+    ONCE_CLOCK.advance(Duration::from_secs(59));
+    let b = only_cached_once_per_minute("b".to_string());
+    assert_eq!(a, b);
+    ONCE_CLOCK.advance(Duration::from_secs(1));
+    let b = only_cached_once_per_minute("b".to_string());
+    assert_eq!(vec!["b".to_string()], b);
+}
+
 #[cfg(feature = "async")]
 #[once(time = 1)]
 async fn only_cached_once_per_second_a(s: String) -> Vec<String> {
@@ -1181,14 +1218,879 @@ fn test_mutable_args_once() {
     assert_eq!((2, 2), mutable_args_once(5, 6));
 }
 
+#[cached(size = 2, policy = "lfu")]
+fn cached_lfu(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_cached_lfu() {
+    assert_eq!(cached_lfu(1), 1);
+    assert_eq!(cached_lfu(1), 1);
+    assert_eq!(cached_lfu(2), 2);
+    // 2 is evicted instead of the more frequently used 1
+    assert_eq!(cached_lfu(3), 3);
+    {
+        let cache = CACHED_LFU.lock().unwrap();
+        assert_eq!(cache.key_order().copied().collect::<Vec<_>>(), [1, 3]);
+        assert_eq!(cache.cache_hits(), Some(1));
+        assert_eq!(cache.cache_misses(), Some(3));
+    }
+}
+
+#[cached(type = "LfuCache<u32, u32>", create = "{ LfuCache::with_size(2) }")]
+fn cached_lfu_type(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_cached_lfu_type() {
+    assert_eq!(cached_lfu_type(1), 1);
+    assert_eq!(cached_lfu_type(1), 1);
+    {
+        let cache = CACHED_LFU_TYPE.lock().unwrap();
+        assert_eq!(cache.frequency(&1), Some(2));
+        assert_eq!(cache.cache_capacity(), Some(2));
+    }
+}
+
+#[cached(
+    type = "TinyLfuCache<u32, u32>",
+    create = "{ TinyLfuCache::with_size(10) }"
+)]
+fn cached_tiny_lfu(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_cached_tiny_lfu() {
+    assert_eq!(cached_tiny_lfu(1), 1);
+    assert_eq!(cached_tiny_lfu(1), 1);
+    {
+        let cache = CACHED_TINY_LFU.lock().unwrap();
+        assert_eq!(cache.cache_size(), 1);
+        assert_eq!(cache.cache_hits(), Some(1));
+        assert_eq!(cache.cache_misses(), Some(1));
+    }
+}
+
+#[cached(type = "ArcCache<u32, u32>", create = "{ ArcCache::with_size(2) }")]
+fn cached_arc(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_cached_arc() {
+    assert_eq!(cached_arc(1), 1);
+    assert_eq!(cached_arc(1), 1);
+    assert_eq!(cached_arc(2), 2);
+    assert_eq!(cached_arc(3), 3);
+    {
+        let cache = CACHED_ARC.lock().unwrap();
+        assert_eq!(cache.key_order().copied().collect::<Vec<_>>(), [1, 3]);
+        assert_eq!(cache.cache_hits(), Some(1));
+        assert_eq!(cache.cache_misses(), Some(3));
+    }
+}
+
+#[cached(time_ms = 200)]
+fn cached_timed_ms(n: u32) -> u32 {
+    n
+}
+
+#[cached(size = 2, time_ms = 200)]
+fn cached_timed_sized_ms(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_cached_time_ms() {
+    assert_eq!(cached_timed_ms(1), 1);
+    assert_eq!(cached_timed_ms(1), 1);
+    assert_eq!(cached_timed_sized_ms(1), 1);
+    assert_eq!(cached_timed_sized_ms(1), 1);
+    sleep(Duration::from_millis(250));
+    assert_eq!(cached_timed_ms(1), 1);
+    assert_eq!(cached_timed_sized_ms(1), 1);
+    let cache = CACHED_TIMED_MS.lock().unwrap();
+    assert_eq!(
+        cache.cache_lifespan_duration(),
+        Some(Duration::from_millis(200))
+    );
+    assert_eq!(cache.cache_hits(), Some(1));
+    assert_eq!(cache.cache_misses(), Some(2));
+    let cache = CACHED_TIMED_SIZED_MS.lock().unwrap();
+    assert_eq!(
+        cache.cache_lifespan_duration(),
+        Some(Duration::from_millis(200))
+    );
+    assert_eq!(cache.cache_hits(), Some(1));
+    assert_eq!(cache.cache_misses(), Some(2));
+}
+
+#[cached(max_weight = 10, weigher = "|_k: &usize, v: &String| v.len()")]
+fn cached_weighted(n: usize) -> String {
+    "x".repeat(n)
+}
+
+#[test]
+fn test_cached_weighted() {
+    assert_eq!(cached_weighted(4), "xxxx");
+    assert_eq!(cached_weighted(5), "xxxxx");
+    assert_eq!(cached_weighted(4), "xxxx");
+    assert_eq!(cached_weighted(3), "xxx");
+    {
+        let cache = CACHED_WEIGHTED.lock().unwrap();
+        assert_eq!(cache.key_order().copied().collect::<Vec<_>>(), [3, 4]);
+        assert_eq!(cache.cache_weight(), Some(7));
+        assert_eq!(cache.cache_hits(), Some(1));
+        assert_eq!(cache.cache_misses(), Some(3));
+    }
+}
+
+#[cached(shards = 4, size = 8)]
+fn cached_sharded(n: u32) -> u32 {
+    n * 2
+}
+
+#[test]
+fn test_cached_sharded() {
+    let handles = (0..4)
+        .map(|t| thread::spawn(move || (0..4).map(|n| cached_sharded(t * 4 + n)).sum::<u32>()))
+        .collect::<Vec<_>>();
+    let total = handles.into_iter().map(|h| h.join().unwrap()).sum::<u32>();
+    assert_eq!(total, (0..16).map(|n| n * 2).sum::<u32>());
+    assert_eq!(CACHED_SHARDED.shard_count(), 4);
+    assert!(CACHED_SHARDED.cache_size() <= 8);
+    assert_eq!(CACHED_SHARDED.cache_misses(), Some(16));
+    assert_eq!(cached_sharded_prime_cache(1), 2);
+}
+
+#[cached(shards = 2, sync_writes = true)]
+fn cached_sharded_sync_writes(s: String) -> Vec<String> {
+    sleep(Duration::new(1, 0));
+    vec![s]
+}
+
+#[test]
+fn test_cached_sharded_sync_writes() {
+    let a = thread::spawn(|| cached_sharded_sync_writes("a".to_string()));
+    sleep(Duration::new(0, 500000));
+    let b = thread::spawn(|| cached_sharded_sync_writes("a".to_string()));
+    assert_eq!(a.join().unwrap(), b.join().unwrap());
+    assert_eq!(CACHED_SHARDED_SYNC_WRITES.cache_misses(), Some(1));
+    assert_eq!(CACHED_SHARDED_SYNC_WRITES.cache_hits(), Some(1));
+}
+
+#[cfg(feature = "async")]
+#[cached(shards = 4, result = true)]
+async fn cached_sharded_a(n: u32) -> Result<u32, ()> {
+    Ok(n)
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_sharded_a() {
+    assert_eq!(cached_sharded_a(1).await, Ok(1));
+    assert_eq!(cached_sharded_a(1).await, Ok(1));
+    assert_eq!(CACHED_SHARDED_A.cache_hits(), Some(1));
+    assert_eq!(CACHED_SHARDED_A.cache_misses(), Some(1));
+}
+
+#[cached(time_ms = 200, flush_interval = 1)]
+fn cached_flush_interval(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_cached_flush_interval() {
+    cached_flush_interval(1);
+    cached_flush_interval(2);
+    assert_eq!(CACHED_FLUSH_INTERVAL.lock().unwrap().cache_size(), 2);
+    sleep(Duration::from_millis(1500));
+    assert_eq!(CACHED_FLUSH_INTERVAL.lock().unwrap().cache_size(), 0);
+}
+
+#[cfg(feature = "async")]
+#[cached(time_ms = 200, flush_interval = 1)]
+async fn cached_flush_interval_a(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_flush_interval_a() {
+    cached_flush_interval_a(1).await;
+    assert_eq!(CACHED_FLUSH_INTERVAL_A.lock().await.cache_size(), 1);
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(CACHED_FLUSH_INTERVAL_A.lock().await.cache_size(), 0);
+}
+
+static SWR_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(time_ms = 200, stale_while_revalidate = 10)]
+fn cached_stale_while_revalidate(n: u32) -> u32 {
+    n + SWR_CALLS.fetch_add(1, Ordering::SeqCst)
+}
+
+#[test]
+fn test_cached_stale_while_revalidate() {
+    assert_eq!(cached_stale_while_revalidate(10), 10);
+    assert_eq!(cached_stale_while_revalidate(10), 10);
+    sleep(Duration::from_millis(250));
+    // stale, a refresh is started in the background
+    assert_eq!(cached_stale_while_revalidate(10), 10);
+    assert_eq!(cached_stale_while_revalidate(10), 10);
+    sleep(Duration::from_millis(100));
+    assert_eq!(cached_stale_while_revalidate(10), 11);
+    assert_eq!(SWR_CALLS.load(Ordering::SeqCst), 2);
+}
+
+static SWR_PANIC_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(time_ms = 200, stale_while_revalidate = 10)]
+fn cached_stale_while_revalidate_panic(n: u32) -> u32 {
+    // the first refresh panics
+    let calls = SWR_PANIC_CALLS.fetch_add(1, Ordering::SeqCst);
+    if calls == 1 {
+        panic!("refresh of {} failed", n);
+    }
+    n + calls
+}
+
+#[test]
+fn test_cached_stale_while_revalidate_panic() {
+    assert_eq!(cached_stale_while_revalidate_panic(10), 10);
+    sleep(Duration::from_millis(250));
+    assert_eq!(cached_stale_while_revalidate_panic(10), 10);
+    sleep(Duration::from_millis(100));
+    // the panicked refresh released the key, so the next call refreshes again
+    assert_eq!(cached_stale_while_revalidate_panic(10), 10);
+    sleep(Duration::from_millis(100));
+    assert_eq!(cached_stale_while_revalidate_panic(10), 12);
+    assert_eq!(SWR_PANIC_CALLS.load(Ordering::SeqCst), 3);
+}
+
+#[cfg(feature = "async")]
+static SWR_CALLS_A: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[cached(time_ms = 200, stale_while_revalidate = 10, result = true)]
+async fn cached_stale_while_revalidate_a(n: u32) -> Result<u32, ()> {
+    Ok(n + SWR_CALLS_A.fetch_add(1, Ordering::SeqCst))
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_stale_while_revalidate_a() {
+    assert_eq!(cached_stale_while_revalidate_a(10).await, Ok(10));
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(cached_stale_while_revalidate_a(10).await, Ok(10));
+    assert_eq!(cached_stale_while_revalidate_a(10).await, Ok(10));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(cached_stale_while_revalidate_a(10).await, Ok(11));
+    assert_eq!(SWR_CALLS_A.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "async")]
+static SWR_PANIC_CALLS_A: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[cached(time_ms = 200, stale_while_revalidate = 10)]
+async fn cached_stale_while_revalidate_panic_a(n: u32) -> u32 {
+    let calls = SWR_PANIC_CALLS_A.fetch_add(1, Ordering::SeqCst);
+    if calls == 1 {
+        panic!("refresh of {} failed", n);
+    }
+    n + calls
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_stale_while_revalidate_panic_a() {
+    assert_eq!(cached_stale_while_revalidate_panic_a(10).await, 10);
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(cached_stale_while_revalidate_panic_a(10).await, 10);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(cached_stale_while_revalidate_panic_a(10).await, 10);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(cached_stale_while_revalidate_panic_a(10).await, 12);
+    assert_eq!(SWR_PANIC_CALLS_A.load(Ordering::SeqCst), 3);
+}
+
+static STALE_ON_ERROR_FAIL: AtomicBool = AtomicBool::new(false);
+
+#[cached(time_ms = 200, serve_stale_on_error = 1, result = true)]
+fn cached_serve_stale_on_error(n: u32) -> Result<u32, String> {
+    if STALE_ON_ERROR_FAIL.load(Ordering::SeqCst) {
+        return Err(format!("failed {}", n));
+    }
+    Ok(n)
+}
+
+#[cached(
+    time_ms = 200,
+    serve_stale_on_error = 1,
+    result = true,
+    with_cached_flag = true
+)]
+fn cached_serve_stale_on_error_flag(n: u32) -> Result<cached::Return<u32>, String> {
+    if STALE_ON_ERROR_FAIL.load(Ordering::SeqCst) {
+        return Err(format!("failed {}", n));
+    }
+    Ok(cached::Return::new(n))
+}
+
+#[test]
+fn test_cached_serve_stale_on_error() {
+    assert_eq!(cached_serve_stale_on_error(1), Ok(1));
+    let r = cached_serve_stale_on_error_flag(1).unwrap();
+    assert!(!r.was_cached && !r.was_stale);
+    STALE_ON_ERROR_FAIL.store(true, Ordering::SeqCst);
+    let r = cached_serve_stale_on_error_flag(1).unwrap();
+    assert!(r.was_cached && !r.was_stale);
+    // never cached, there's nothing to fall back to
+    assert_eq!(cached_serve_stale_on_error(2), Err("failed 2".to_string()));
+    sleep(Duration::from_millis(250));
+    // expired, but still within the grace period
+    assert_eq!(cached_serve_stale_on_error(1), Ok(1));
+    let r = cached_serve_stale_on_error_flag(1).unwrap();
+    assert!(r.was_cached && r.was_stale);
+    assert_eq!(*r, 1);
+    STALE_ON_ERROR_FAIL.store(false, Ordering::SeqCst);
+    let r = cached_serve_stale_on_error_flag(1).unwrap();
+    assert!(!r.was_cached && !r.was_stale);
+}
+
+static CACHE_ERR_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cached(result = true, cache_err_for = 1)]
+fn cached_cache_err_for(n: u32) -> Result<u32, String> {
+    CACHE_ERR_CALLS.fetch_add(1, Ordering::SeqCst);
+    if n % 2 == 1 {
+        return Err(format!("odd {}", n));
+    }
+    Ok(n)
+}
+
+#[test]
+fn test_cached_cache_err_for() {
+    assert_eq!(cached_cache_err_for(1), Err("odd 1".to_string()));
+    assert_eq!(cached_cache_err_for(1), Err("odd 1".to_string()));
+    assert_eq!(cached_cache_err_for(2), Ok(2));
+    assert_eq!(cached_cache_err_for(2), Ok(2));
+    assert_eq!(CACHE_ERR_CALLS.load(Ordering::SeqCst), 2);
+    assert_eq!(CACHED_CACHE_ERR_FOR_ERRORS.lock().unwrap().cache_size(), 1);
+    assert_eq!(cached_cache_err_for_cache_remove(1), None);
+    assert_eq!(cached_cache_err_for(1), Err("odd 1".to_string()));
+    assert_eq!(CACHE_ERR_CALLS.load(Ordering::SeqCst), 3);
+    // errors expire separately from values
+    sleep(Duration::from_millis(1100));
+    assert_eq!(cached_cache_err_for(1), Err("odd 1".to_string()));
+    assert_eq!(cached_cache_err_for(2), Ok(2));
+    assert_eq!(CACHE_ERR_CALLS.load(Ordering::SeqCst), 4);
+}
+
+#[cfg(feature = "async")]
+static CACHE_ERR_CALLS_A: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[cached(result = true, cache_err_for = 60)]
+async fn cached_cache_err_for_a(n: u32) -> Result<u32, String> {
+    CACHE_ERR_CALLS_A.fetch_add(1, Ordering::SeqCst);
+    Err(format!("failed {}", n))
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_cache_err_for_a() {
+    assert_eq!(cached_cache_err_for_a(1).await, Err("failed 1".to_string()));
+    assert_eq!(cached_cache_err_for_a(1).await, Err("failed 1".to_string()));
+    assert_eq!(CACHE_ERR_CALLS_A.load(Ordering::SeqCst), 1);
+    cached_cache_err_for_a_cache_clear().await;
+    assert_eq!(cached_cache_err_for_a(1).await, Err("failed 1".to_string()));
+    assert_eq!(CACHE_ERR_CALLS_A.load(Ordering::SeqCst), 2);
+}
+
+static BY_KEY_CALLS: AtomicU32 = AtomicU32::new(0);
+// both keys have to be computed at the same time to get past the barrier
+static BY_KEY_BARRIER: cached::once_cell::sync::Lazy<Barrier> =
+    cached::once_cell::sync::Lazy::new(|| Barrier::new(2));
+
+#[cached(sync_writes = "by_key")]
+fn cached_sync_writes_by_key(n: u32) -> u32 {
+    BY_KEY_CALLS.fetch_add(1, Ordering::SeqCst);
+    BY_KEY_BARRIER.wait();
+    n
+}
+
+#[test]
+fn test_cached_sync_writes_by_key() {
+    let handles = [1, 1, 1, 2, 2]
+        .iter()
+        .map(|&n| thread::spawn(move || cached_sync_writes_by_key(n)))
+        .collect::<Vec<_>>();
+    let results = handles
+        .into_iter()
+        .map(|h| h.join().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(results, [1, 1, 1, 2, 2]);
+    // each key is computed once
+    assert_eq!(BY_KEY_CALLS.load(Ordering::SeqCst), 2);
+    assert!(CACHED_SYNC_WRITES_BY_KEY_KEY_LOCKS.is_empty());
+}
+
+#[cfg(feature = "async")]
+static BY_KEY_CALLS_A: AtomicU32 = AtomicU32::new(0);
+#[cfg(feature = "async")]
+static BY_KEY_RUNNING_A: AtomicU32 = AtomicU32::new(0);
+#[cfg(feature = "async")]
+static BY_KEY_MAX_RUNNING_A: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "async")]
+#[cached(sync_writes = "by_key")]
+async fn cached_sync_writes_by_key_a(n: u32) -> u32 {
+    BY_KEY_CALLS_A.fetch_add(1, Ordering::SeqCst);
+    let running = BY_KEY_RUNNING_A.fetch_add(1, Ordering::SeqCst) + 1;
+    BY_KEY_MAX_RUNNING_A.fetch_max(running, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    BY_KEY_RUNNING_A.fetch_sub(1, Ordering::SeqCst);
+    n
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_sync_writes_by_key_a() {
+    let results = futures::future::join_all(
+        [1, 1, 1, 2, 2]
+            .iter()
+            .map(|&n| cached_sync_writes_by_key_a(n)),
+    )
+    .await;
+    assert_eq!(results, [1, 1, 1, 2, 2]);
+    assert_eq!(BY_KEY_CALLS_A.load(Ordering::SeqCst), 2);
+    assert_eq!(BY_KEY_MAX_RUNNING_A.load(Ordering::SeqCst), 2);
+    assert!(CACHED_SYNC_WRITES_BY_KEY_A_KEY_LOCKS.is_empty());
+    // the cached future can be spawned
+    assert_eq!(
+        tokio::spawn(cached_sync_writes_by_key_a(1)).await.unwrap(),
+        1
+    );
+}
+
+#[cached(key = "String", convert = r#"{ name.as_ref().to_string() }"#)]
+fn cached_generic<S: AsRef<str>>(name: S) -> usize {
+    name.as_ref().len()
+}
+
+#[cached(key = "u64", convert = "{ n.into() }")]
+fn cached_impl_trait(n: impl Into<u64> + Copy) -> u64 {
+    n.into() * 2
+}
+
+#[cached(size = 10, key = "String", convert = "{ value.to_string() }")]
+fn cached_where_clause<'a, T>(value: &'a T) -> String
+where
+    T: ToString + ?Sized,
+{
+    format!("<{}>", value.to_string())
+}
+
+#[once]
+fn once_generic<T: Into<u32>>(n: T) -> u32 {
+    n.into()
+}
+
+#[test]
+fn test_cached_generic() {
+    assert_eq!(cached_generic("abc"), 3);
+    assert_eq!(cached_generic(String::from("abc")), 3);
+    assert_eq!(CACHED_GENERIC.lock().unwrap().cache_hits(), Some(1));
+
+    assert_eq!(cached_impl_trait(2u8), 4);
+    assert_eq!(cached_impl_trait(2u64), 4);
+    assert_eq!(CACHED_IMPL_TRAIT.lock().unwrap().cache_hits(), Some(1));
+
+    assert_eq!(cached_where_clause("a"), "<a>");
+    assert_eq!(cached_where_clause(&1), "<1>");
+    assert_eq!(cached_where_clause_no_cache(&1), "<1>");
+
+    assert_eq!(once_generic(1u8), 1);
+    assert_eq!(once_generic(2u16), 1);
+}
+
+#[cfg(feature = "async")]
+#[cached(key = "String", convert = r#"{ name.as_ref().to_string() }"#)]
+async fn cached_generic_a<S: AsRef<str> + Send>(name: S) -> usize {
+    name.as_ref().len()
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_generic_a() {
+    assert_eq!(cached_generic_a("abc").await, 3);
+    assert_eq!(cached_generic_a(String::from("abc")).await, 3);
+    assert_eq!(CACHED_GENERIC_A.lock().await.cache_hits(), Some(1));
+}
+
+#[cached(size = 10, key = "String", convert = r#"{ name.to_lowercase() }"#)]
+fn cached_helpers(name: &str) -> usize {
+    name.len()
+}
+
+#[test]
+fn test_cached_helpers() {
+    assert!(!cached_helpers_cache_contains("Ferris"));
+    assert_eq!(cached_helpers("Ferris"), 6);
+    // the key is built with the same convert block
+    assert!(cached_helpers_cache_contains("FERRIS"));
+    assert_eq!(CACHED_HELPERS.lock().unwrap().cache_hits(), Some(0));
+
+    assert_eq!(cached_helpers_cache_remove("ferris"), Some(6));
+    assert_eq!(cached_helpers_cache_remove("ferris"), None);
+    assert!(!cached_helpers_cache_contains("Ferris"));
+
+    cached_helpers("a");
+    cached_helpers("b");
+    cached_helpers_cache_clear();
+    assert_eq!(CACHED_HELPERS.lock().unwrap().cache_size(), 0);
+}
+
+#[cached(shards = 2, time = 100)]
+fn cached_sharded_helpers(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_cached_sharded_helpers() {
+    cached_sharded_helpers(1);
+    cached_sharded_helpers(2);
+    assert!(cached_sharded_helpers_cache_contains(1));
+    assert_eq!(cached_sharded_helpers_cache_remove(1), Some(1));
+    assert!(!cached_sharded_helpers_cache_contains(1));
+    cached_sharded_helpers_cache_clear();
+    assert_eq!(CACHED_SHARDED_HELPERS.cache_size(), 0);
+}
+
+#[cfg(feature = "async")]
+#[cached(result = true)]
+async fn cached_helpers_a(n: u32) -> Result<u32, ()> {
+    Ok(n)
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_helpers_a() {
+    assert_eq!(cached_helpers_a(1).await, Ok(1));
+    assert!(cached_helpers_a_cache_contains(1).await);
+    assert_eq!(cached_helpers_a_cache_remove(1).await, Some(1));
+    assert!(!cached_helpers_a_cache_contains(1).await);
+    assert_eq!(cached_helpers_a(2).await, Ok(2));
+    cached_helpers_a_cache_clear().await;
+    assert_eq!(CACHED_HELPERS_A.lock().await.cache_size(), 0);
+}
+
+#[cached(cache_if = "|names| !names.is_empty()")]
+fn cached_if_not_empty(s: String) -> Vec<String> {
+    s.split_whitespace().map(String::from).collect()
+}
+
+#[derive(Clone)]
+struct Page {
+    retry_later: bool,
+}
+
+fn is_complete(page: &Page) -> bool {
+    !page.retry_later
+}
+
+#[cached(result = true, cache_if = "is_complete")]
+fn cached_if_complete(retry_later: bool) -> Result<Page, ()> {
+    Ok(Page { retry_later })
+}
+
+#[test]
+fn test_cached_if() {
+    assert!(cached_if_not_empty(" ".to_string()).is_empty());
+    assert_eq!(cached_if_not_empty("a b".to_string()), ["a", "b"]);
+    assert!(!cached_if_not_empty_cache_contains(" ".to_string()));
+    assert!(cached_if_not_empty_cache_contains("a b".to_string()));
+
+    assert!(cached_if_complete(true).unwrap().retry_later);
+    assert!(!cached_if_complete(false).unwrap().retry_later);
+    assert_eq!(CACHED_IF_COMPLETE.lock().unwrap().cache_size(), 1);
+    assert!(cached_if_complete_cache_contains(false));
+}
+
+static ONCE_IF_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[once(option = true, cache_if = "|n| *n > 1")]
+fn once_if() -> Option<u32> {
+    Some(ONCE_IF_CALLS.fetch_add(1, Ordering::SeqCst))
+}
+
+#[test]
+fn test_once_if() {
+    assert_eq!(once_if(), Some(0));
+    assert_eq!(once_if(), Some(1));
+    assert_eq!(once_if(), Some(2));
+    assert_eq!(once_if(), Some(2));
+    assert_eq!(ONCE_IF_CALLS.load(Ordering::SeqCst), 3);
+}
+
+#[cfg(feature = "async")]
+#[cached(cache_if = "|n| n % 2 == 0")]
+async fn cached_if_even_a(n: u32) -> u32 {
+    n
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_if_a() {
+    assert_eq!(cached_if_even_a(1).await, 1);
+    assert_eq!(cached_if_even_a(2).await, 2);
+    assert!(!cached_if_even_a_cache_contains(1).await);
+    assert!(cached_if_even_a_cache_contains(2).await);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Token {
+    id: u32,
+    expires_in: Duration,
+}
+
+#[cached(result = true, ttl_from = "|token: &Token| token.expires_in")]
+fn cached_ttl_from(id: u32, expires_in: u64) -> Result<Token, ()> {
+    Ok(Token {
+        id,
+        expires_in: Duration::from_secs(expires_in),
+    })
+}
+
+static TTL_FROM_CLOCK: cached::once_cell::sync::Lazy<MockClock> =
+    cached::once_cell::sync::Lazy::new(MockClock::new);
+
+#[cached(
+    type = "cached::TtlCache<u32, Token, MockClock>",
+    create = "{ cached::TtlCache::with_default_ttl_and_clock(Duration::from_secs(1), TTL_FROM_CLOCK.clone()) }",
+    ttl_from = "|token| token.expires_in"
+)]
+fn cached_ttl_from_clock(id: u32) -> Token {
+    Token {
+        id,
+        expires_in: Duration::from_secs(u64::from(id)),
+    }
+}
+
+#[test]
+fn test_cached_ttl_from() {
+    assert_eq!(cached_ttl_from(1, 60).unwrap().id, 1);
+    assert_eq!(cached_ttl_from(2, 3600).unwrap().id, 2);
+    {
+        let cache = CACHED_TTL_FROM.lock().unwrap();
+        assert!(cache.ttl(&(1, 60)).unwrap() <= Duration::from_secs(60));
+        assert!(cache.ttl(&(2, 3600)).unwrap() > Duration::from_secs(60));
+    }
+
+    cached_ttl_from_clock(10);
+    cached_ttl_from_clock(20);
+    TTL_FROM_CLOCK.advance(Duration::from_secs(10));
+    assert!(!cached_ttl_from_clock_cache_contains(10));
+    assert!(cached_ttl_from_clock_cache_contains(20));
+}
+
+#[cached(
+    key = Vec<String>,
+    convert = { words.split_whitespace().map(String::from).collect::<Vec<_>>() },
+    cache_if = |count: &usize| *count > 0
+)]
+fn syntax_word_count(words: &str) -> usize {
+    words.split_whitespace().count()
+}
+
+#[cached(
+    type = SizedCache<(u32, u32), u32>,
+    create = { SizedCache::with_size(2) },
+    key = "(u32, u32)",
+    convert = r#"{ (a, b) }"#
+)]
+fn syntax_sized_sum(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+#[cached(max_weight = 4, weigher = |_k: &u32, v: &String| v.len())]
+fn syntax_weighted(n: u32) -> String {
+    "a".repeat(n as usize)
+}
+
+static SYNTAX_CLOCK: cached::once_cell::sync::Lazy<MockClock> =
+    cached::once_cell::sync::Lazy::new(MockClock::new);
+
+#[once(time = 10, clock = SYNTAX_CLOCK, cache_if = |n| *n > 0)]
+fn syntax_once(n: u32) -> u32 {
+    n
+}
+
+#[test]
+fn test_cached_rust_syntax() {
+    assert_eq!(syntax_word_count("a b"), 2);
+    assert_eq!(syntax_word_count(" "), 0);
+    assert!(syntax_word_count_cache_contains(" a  b "));
+    assert!(!syntax_word_count_cache_contains(""));
+
+    syntax_sized_sum(1, 2);
+    syntax_sized_sum(2, 3);
+    syntax_sized_sum(3, 4);
+    assert_eq!(SYNTAX_SIZED_SUM.lock().unwrap().cache_size(), 2);
+    assert!(!syntax_sized_sum_cache_contains(1, 2));
+
+    syntax_weighted(3);
+    syntax_weighted(2);
+    assert_eq!(SYNTAX_WEIGHTED.lock().unwrap().cache_weight(), Some(2));
+
+    assert_eq!(syntax_once(0), 0);
+    assert_eq!(syntax_once(1), 1);
+    assert_eq!(syntax_once(2), 1);
+    SYNTAX_CLOCK.advance(Duration::from_secs(10));
+    assert_eq!(syntax_once(2), 2);
+}
+
+struct Multiplier {
+    factor: u32,
+    cache: std::sync::Mutex<UnboundCache<u32, u32>>,
+    label: std::sync::RwLock<Option<String>>,
+}
+
+impl Multiplier {
+    fn new(factor: u32) -> Self {
+        Multiplier {
+            factor,
+            cache: std::sync::Mutex::new(UnboundCache::new()),
+            label: std::sync::RwLock::new(None),
+        }
+    }
+
+    #[cached(in_impl = true)]
+    fn square(n: u32) -> u32 {
+        n * n
+    }
+
+    #[cached(ignore_self = true, size = 10)]
+    fn shared(&self, n: u32) -> u32 {
+        n * self.factor
+    }
+
+    #[cached(key = "(u32, u32)", convert = "{ (self.factor, n) }")]
+    fn by_factor(&self, n: u32) -> u32 {
+        n * self.factor
+    }
+
+    #[cached(cache_field = "cache")]
+    fn per_instance(&self, n: u32) -> u32 {
+        n * self.factor
+    }
+
+    #[once(ignore_self = true)]
+    fn first_factor(&self) -> u32 {
+        self.factor
+    }
+
+    #[once(cache_field = "label")]
+    fn label(&self) -> String {
+        format!("x{}", self.factor)
+    }
+}
+
+#[test]
+fn test_cached_methods() {
+    let two = Multiplier::new(2);
+    let three = Multiplier::new(3);
+
+    assert_eq!(Multiplier::square(4), 16);
+    assert_eq!(Multiplier::square_prime_cache(4), 16);
+    assert_eq!(Multiplier::SQUARE().lock().unwrap().cache_misses(), Some(1));
+
+    // the cache is shared between all instances
+    assert_eq!(two.shared(5), 10);
+    assert_eq!(three.shared(5), 10);
+    assert_eq!(three.shared_no_cache(5), 15);
+
+    // the key includes a field of `self`
+    assert_eq!(two.by_factor(5), 10);
+    assert_eq!(three.by_factor(5), 15);
+    assert_eq!(Multiplier::BY_FACTOR().lock().unwrap().cache_size(), 2);
+    assert_eq!(three.by_factor_cache_remove(5), Some(15));
+    assert!(two.by_factor_cache_contains(5));
+    assert!(!three.by_factor_cache_contains(5));
+
+    // each instance has its own cache
+    assert_eq!(two.per_instance(5), 10);
+    assert_eq!(three.per_instance(5), 15);
+    assert_eq!(two.cache.lock().unwrap().cache_hits(), Some(0));
+    assert_eq!(two.per_instance(5), 10);
+    assert_eq!(two.cache.lock().unwrap().cache_hits(), Some(1));
+    assert_eq!(three.cache.lock().unwrap().cache_size(), 1);
+    three.per_instance_cache_clear();
+    assert_eq!(three.cache.lock().unwrap().cache_size(), 0);
+    assert!(two.per_instance_cache_contains(5));
+
+    assert_eq!(two.first_factor(), 2);
+    assert_eq!(three.first_factor(), 2);
+    assert_eq!(three.label(), "x3");
+    assert_eq!(*three.label.read().unwrap(), Some("x3".to_string()));
+    assert_eq!(two.label(), "x2");
+}
+
+#[cfg(feature = "async")]
+struct AsyncMultiplier {
+    factor: u32,
+    cache: cached::async_sync::Mutex<UnboundCache<u32, u32>>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncMultiplier {
+    #[cached(ignore_self = true)]
+    async fn shared_a(&self, n: u32) -> u32 {
+        n * self.factor
+    }
+
+    #[cached(cache_field = "cache")]
+    async fn per_instance_a(&self, n: u32) -> u32 {
+        n * self.factor
+    }
+
+    #[once(in_impl = true)]
+    async fn once_a() -> u32 {
+        1
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_cached_methods_a() {
+    let two = AsyncMultiplier {
+        factor: 2,
+        cache: cached::async_sync::Mutex::new(UnboundCache::new()),
+    };
+    assert_eq!(two.shared_a(5).await, 10);
+    assert_eq!(two.shared_a(5).await, 10);
+    assert_eq!(
+        AsyncMultiplier::SHARED_A().lock().await.cache_hits(),
+        Some(1)
+    );
+    assert_eq!(two.per_instance_a(5).await, 10);
+    assert_eq!(two.cache.lock().await.cache_size(), 1);
+    assert_eq!(AsyncMultiplier::once_a().await, 1);
+}
+
 #[cfg(feature = "redis_store")]
 mod redis_tests {
     use super::*;
     use cached::proc_macro::io_cached;
-    use cached::RedisCache;
+    use cached::{IOCached, RedisCache};
     use thiserror::Error;
 
-    #[derive(Error, Debug, PartialEq, Clone)]
+    #[derive(Error, Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
     enum TestError {
         #[error("error with redis cache `{0}`")]
         RedisError(String),
@@ -1216,6 +2118,29 @@ mod redis_tests {
         assert_eq!(cached_redis(1), Ok(1));
         assert_eq!(cached_redis(5), Err(TestError::Count(5)));
         assert_eq!(cached_redis(6), Err(TestError::Count(6)));
+        assert_eq!(cached_redis_cache_contains(1), Ok(true));
+        assert_eq!(cached_redis_cache_contains(5), Ok(false));
+        assert_eq!(cached_redis_cache_remove(1), Ok(Some(1)));
+        assert_eq!(cached_redis_cache_contains(1), Ok(false));
+    }
+
+    #[io_cached(
+        redis = true,
+        time_ms = 1500,
+        cache_prefix_block = "{ \"__cached_redis_proc_macro_test_fn_cached_redis_time_ms\" }",
+        map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+    )]
+    fn cached_redis_time_ms(n: u32) -> Result<u32, TestError> {
+        Ok(n)
+    }
+
+    #[test]
+    fn test_cached_redis_time_ms() {
+        assert_eq!(cached_redis_time_ms(1), Ok(1));
+        assert_eq!(
+            CACHED_REDIS_TIME_MS.cache_lifespan_duration(),
+            Some(Duration::from_millis(1500))
+        );
     }
 
     #[io_cached(
@@ -1232,6 +2157,272 @@ mod redis_tests {
         }
     }
 
+    #[io_cached(
+        redis = true,
+        time = 1,
+        key = String,
+        convert = { format!("{}:{}", a, b) },
+        cache_prefix_block = { "__cached_redis_proc_macro_test_fn_cached_redis_syntax" },
+        map_error = |e| TestError::RedisError(format!("{:?}", e))
+    )]
+    fn cached_redis_syntax(a: u32, b: u32) -> Result<u32, TestError> {
+        Ok(a + b)
+    }
+
+    #[test]
+    fn test_cached_redis_syntax() {
+        assert_eq!(cached_redis_syntax(1, 2), Ok(3));
+        assert_eq!(cached_redis_syntax_cache_contains(1, 2), Ok(true));
+        assert_eq!(
+            CACHED_REDIS_SYNTAX.cache_get(&"1:2".to_string()).unwrap(),
+            Some(3)
+        );
+    }
+
+    #[io_cached(
+        redis = true,
+        time = 1,
+        cache_if = "|n| n % 2 == 0",
+        map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+    )]
+    fn cached_redis_if_even(n: u32) -> Result<u32, TestError> {
+        Ok(n)
+    }
+
+    #[io_cached(
+        redis = true,
+        ttl_from = "|n: &u32| Duration::from_secs(u64::from(*n))",
+        map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+    )]
+    fn cached_redis_ttl_from(n: u32) -> Result<u32, TestError> {
+        Ok(n)
+    }
+
+    #[test]
+    fn test_cached_redis_ttl_from() {
+        assert_eq!(cached_redis_ttl_from(1), Ok(1));
+        assert_eq!(cached_redis_ttl_from_cache_contains(1), Ok(true));
+        sleep(Duration::from_millis(1100));
+        assert_eq!(cached_redis_ttl_from_cache_contains(1), Ok(false));
+    }
+
+    #[test]
+    fn test_cached_redis_ttl_from_store_set() {
+        // values set outside of `ttl_from` get the default lifespan instead of expiring at once
+        assert_eq!(
+            CACHED_REDIS_TTL_FROM.cache_lifespan_duration(),
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(CACHED_REDIS_TTL_FROM.cache_set(100, 100).unwrap(), None);
+        let (value, ttl) = CACHED_REDIS_TTL_FROM
+            .cache_get_with_ttl(&100)
+            .unwrap()
+            .unwrap();
+        assert_eq!(value, 100);
+        assert!(ttl.unwrap() > Duration::from_secs(3500));
+        assert_eq!(cached_redis_ttl_from_cache_contains(100), Ok(true));
+    }
+
+    static REDIS_ERR_CALLS: AtomicU32 = AtomicU32::new(0);
+
+    #[io_cached(
+        redis = true,
+        time = 1,
+        cache_err_for = 1,
+        map_error = |e| TestError::RedisError(format!("{:?}", e))
+    )]
+    fn cached_redis_cache_err_for(n: u32) -> Result<u32, TestError> {
+        REDIS_ERR_CALLS.fetch_add(1, Ordering::SeqCst);
+        if n % 2 == 1 {
+            Err(TestError::Count(n))
+        } else {
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_cached_redis_cache_err_for() {
+        assert_eq!(cached_redis_cache_err_for(1), Err(TestError::Count(1)));
+        assert_eq!(cached_redis_cache_err_for(1), Err(TestError::Count(1)));
+        assert_eq!(REDIS_ERR_CALLS.load(Ordering::SeqCst), 1);
+        assert_eq!(cached_redis_cache_err_for_cache_remove(1), Ok(None));
+        assert_eq!(cached_redis_cache_err_for(1), Err(TestError::Count(1)));
+        assert_eq!(REDIS_ERR_CALLS.load(Ordering::SeqCst), 2);
+        sleep(Duration::from_millis(1100));
+        assert_eq!(cached_redis_cache_err_for(1), Err(TestError::Count(1)));
+        assert_eq!(REDIS_ERR_CALLS.load(Ordering::SeqCst), 3);
+        assert_eq!(cached_redis_cache_err_for(2), Ok(2));
+        assert!(cached_redis_cache_err_for_cache_clear().unwrap().is_some());
+        assert_eq!(cached_redis_cache_err_for_cache_contains(2), Ok(false));
+        assert_eq!(cached_redis_cache_err_for(1), Err(TestError::Count(1)));
+        assert_eq!(REDIS_ERR_CALLS.load(Ordering::SeqCst), 5);
+    }
+
+    #[io_cached(
+        redis = true,
+        time = 30,
+        cache_err_for = 30,
+        map_error = |e| TestError::RedisError(format!("{:?}", e))
+    )]
+    fn cached_redis_shared(n: u32) -> Result<u32, TestError> {
+        if n % 2 == 1 {
+            Ok(n)
+        } else {
+            Err(TestError::Count(n))
+        }
+    }
+
+    #[io_cached(
+        redis = true,
+        time = 30,
+        map_error = |e| TestError::RedisError(format!("{:?}", e))
+    )]
+    fn cached_redis_shared_name(n: u32) -> Result<u32, TestError> {
+        Ok(n)
+    }
+
+    #[test]
+    fn test_cached_redis_shared_name_prefix() {
+        cached_redis_shared_cache_clear().unwrap();
+        cached_redis_shared_name_cache_clear().unwrap();
+        assert_eq!(cached_redis_shared(1), Ok(1));
+        assert_eq!(cached_redis_shared(2), Err(TestError::Count(2)));
+        assert_eq!(cached_redis_shared_name(1), Ok(1));
+        // neither the other function's values nor the cached errors are under the prefix
+        assert_eq!(cached_redis_shared_cache_clear(), Ok(Some(1)));
+        assert_eq!(cached_redis_shared_name_cache_contains(1), Ok(true));
+        assert_eq!(cached_redis_shared_name_cache_clear(), Ok(Some(1)));
+    }
+
+    #[io_cached(
+        redis = true,
+        time = 30,
+        key = String,
+        convert = { name.as_ref().to_string() },
+        map_error = |e| TestError::RedisError(format!("{:?}", e))
+    )]
+    fn cached_redis_generic<S: AsRef<str>>(name: S) -> Result<usize, TestError> {
+        Ok(name.as_ref().len())
+    }
+
+    #[test]
+    fn test_cached_redis_generic() {
+        assert_eq!(cached_redis_generic("one"), Ok(3));
+        assert_eq!(cached_redis_generic(String::from("one")), Ok(3));
+        assert_eq!(cached_redis_generic_cache_contains("one"), Ok(true));
+        // clearing doesn't need the generic parameters
+        assert!(cached_redis_generic_cache_clear().unwrap().is_some());
+        assert_eq!(cached_redis_generic_cache_contains("one"), Ok(false));
+    }
+
+    #[io_cached(
+        redis = true,
+        time = 30,
+        evict_undeserializable = true,
+        cache_prefix_block = { "__cached_redis_proc_macro_test_fn_cached_redis_evict" },
+        map_error = |e| TestError::RedisError(format!("{:?}", e))
+    )]
+    fn cached_redis_evict_undeserializable(n: u32) -> Result<u32, TestError> {
+        Ok(n)
+    }
+
+    #[test]
+    fn test_cached_redis_evict_undeserializable() {
+        // a value written with another type under the same prefix
+        let strings: RedisCache<u32, String> =
+            RedisCache::new("__cached_redis_proc_macro_test_fn_cached_redis_evict", 30)
+                .build()
+                .unwrap();
+        strings.cache_set(1, "one".to_string()).unwrap();
+        assert_eq!(cached_redis_evict_undeserializable(1), Ok(1));
+        assert_eq!(
+            cached_redis_evict_undeserializable_cache_contains(1),
+            Ok(true)
+        );
+        assert!(strings.cache_get(&1).is_err());
+    }
+
+    static REDIS_STALE_FAIL: AtomicBool = AtomicBool::new(false);
+
+    #[io_cached(
+        redis = true,
+        time = 1,
+        serve_stale_on_error = 2,
+        with_cached_flag = true,
+        map_error = |e| TestError::RedisError(format!("{:?}", e))
+    )]
+    fn cached_redis_serve_stale(n: u32) -> Result<cached::Return<u32>, TestError> {
+        if REDIS_STALE_FAIL.load(Ordering::SeqCst) {
+            Err(TestError::Count(n))
+        } else {
+            Ok(cached::Return::new(n))
+        }
+    }
+
+    #[test]
+    fn test_cached_redis_serve_stale() {
+        assert!(!cached_redis_serve_stale(1).unwrap().was_cached);
+        REDIS_STALE_FAIL.store(true, Ordering::SeqCst);
+        let r = cached_redis_serve_stale(1).unwrap();
+        assert!(r.was_cached && !r.was_stale);
+        sleep(Duration::from_millis(1100));
+        let r = cached_redis_serve_stale(1).unwrap();
+        assert!(r.was_cached && r.was_stale);
+        assert_eq!(*r, 1);
+        assert!(matches!(
+            cached_redis_serve_stale(2),
+            Err(TestError::Count(2))
+        ));
+        REDIS_STALE_FAIL.store(false, Ordering::SeqCst);
+        assert!(!cached_redis_serve_stale(1).unwrap().was_cached);
+    }
+
+    #[test]
+    fn test_cached_redis_if_even() {
+        assert_eq!(cached_redis_if_even(1), Ok(1));
+        assert_eq!(cached_redis_if_even(2), Ok(2));
+        assert_eq!(cached_redis_if_even_cache_contains(1), Ok(false));
+        assert_eq!(cached_redis_if_even_cache_contains(2), Ok(true));
+    }
+
+    struct RedisMultiplier {
+        factor: u32,
+        cache: RedisCache<u32, u32>,
+    }
+
+    impl RedisMultiplier {
+        #[io_cached(
+            redis = true,
+            time = 1,
+            ignore_self = true,
+            map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+        )]
+        fn shared(&self, n: u32) -> Result<u32, TestError> {
+            Ok(n * self.factor)
+        }
+
+        #[io_cached(
+            cache_field = "cache",
+            map_error = r##"|e| TestError::RedisError(format!("{:?}", e))"##
+        )]
+        fn per_instance(&self, n: u32) -> Result<u32, TestError> {
+            Ok(n * self.factor)
+        }
+    }
+
+    #[test]
+    fn test_cached_redis_methods() {
+        let two = RedisMultiplier {
+            factor: 2,
+            cache: RedisCache::new("__cached_redis_proc_macro_test_per_instance", 1)
+                .build()
+                .unwrap(),
+        };
+        assert_eq!(two.shared(5), Ok(10));
+        assert_eq!(two.per_instance(5), Ok(10));
+        assert_eq!(two.per_instance(5), Ok(10));
+    }
+
     #[test]
     fn test_cached_redis_cached_flag() {
         assert!(!cached_redis_cached_flag(1).unwrap().was_cached);
@@ -1336,6 +2527,61 @@ mod redis_tests {
                 Err(TestError::Count(6))
             );
         }
+
+        static ASYNC_REDIS_ERR_CALLS: AtomicU32 = AtomicU32::new(0);
+
+        #[io_cached(
+            redis = true,
+            time = 1,
+            cache_err_for = 60,
+            map_error = |e| TestError::RedisError(format!("{:?}", e))
+        )]
+        async fn async_cached_redis_cache_err_for(n: u32) -> Result<u32, TestError> {
+            ASYNC_REDIS_ERR_CALLS.fetch_add(1, Ordering::SeqCst);
+            Err(TestError::Count(n))
+        }
+
+        #[tokio::test]
+        async fn test_async_cached_redis_cache_err_for() {
+            assert_eq!(
+                async_cached_redis_cache_err_for(1).await,
+                Err(TestError::Count(1))
+            );
+            assert_eq!(
+                async_cached_redis_cache_err_for(1).await,
+                Err(TestError::Count(1))
+            );
+            assert_eq!(ASYNC_REDIS_ERR_CALLS.load(Ordering::SeqCst), 1);
+            assert_eq!(
+                async_cached_redis_cache_err_for_cache_remove(1).await,
+                Ok(None)
+            );
+        }
+
+        static ASYNC_REDIS_STALE_FAIL: AtomicBool = AtomicBool::new(false);
+
+        #[io_cached(
+            redis = true,
+            time = 1,
+            serve_stale_on_error = 2,
+            map_error = |e| TestError::RedisError(format!("{:?}", e))
+        )]
+        async fn async_cached_redis_serve_stale(n: u32) -> Result<u32, TestError> {
+            if ASYNC_REDIS_STALE_FAIL.load(Ordering::SeqCst) {
+                Err(TestError::Count(n))
+            } else {
+                Ok(n)
+            }
+        }
+
+        #[tokio::test]
+        async fn test_async_cached_redis_serve_stale() {
+            assert_eq!(async_cached_redis_serve_stale(1).await, Ok(1));
+            ASYNC_REDIS_STALE_FAIL.store(true, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(1100)).await;
+            assert_eq!(async_cached_redis_serve_stale(1).await, Ok(1));
+            ASYNC_REDIS_STALE_FAIL.store(false, Ordering::SeqCst);
+        }
     }
 }
 
@@ -0,0 +1,10 @@
+/*!
+Misuses of the proc macros that must fail to compile with a helpful error
+*/
+
+#[cfg(feature = "proc_macro")]
+#[test]
+fn compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
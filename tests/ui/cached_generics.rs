@@ -0,0 +1,18 @@
+use cached::proc_macro::cached;
+
+#[cached]
+fn generic_without_convert<T: ToString>(a: T) -> String {
+    a.to_string()
+}
+
+#[cached]
+fn impl_trait_without_convert(a: impl ToString) -> String {
+    a.to_string()
+}
+
+#[cached(key = String, convert = { a.to_string() })]
+fn generic_value<T: ToString + Clone>(a: T) -> T {
+    a
+}
+
+fn main() {}
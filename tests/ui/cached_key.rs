@@ -0,0 +1,23 @@
+use cached::proc_macro::cached;
+
+#[cached(key = String)]
+fn key_without_convert(a: u32) -> u32 {
+    a
+}
+
+#[cached(convert = { a.to_string() })]
+fn convert_without_key(a: u32) -> u32 {
+    a
+}
+
+#[cached(key = Vec<, convert = { vec![a] })]
+fn invalid_key(a: u32) -> u32 {
+    a
+}
+
+#[cached(unknown = true)]
+fn unknown_attribute(a: u32) -> u32 {
+    a
+}
+
+fn main() {}
@@ -0,0 +1,58 @@
+use cached::proc_macro::cached;
+
+#[cached(time = 1, time_ms = 1000)]
+fn time_and_time_ms(a: u32) -> u32 {
+    a
+}
+
+#[cached(flush_interval = 1)]
+fn flush_interval_without_time(a: u32) -> u32 {
+    a
+}
+
+#[cached(time = 1, flush_interval = 0)]
+fn zero_flush_interval(a: u32) -> u32 {
+    a
+}
+
+#[cached(stale_while_revalidate = 10)]
+fn stale_while_revalidate_without_time(a: u32) -> u32 {
+    a
+}
+
+#[cached(time = 1, stale_while_revalidate = 0)]
+fn zero_stale_while_revalidate(a: u32) -> u32 {
+    a
+}
+
+#[cached(time = 1, time_refresh = true, stale_while_revalidate = 10)]
+fn stale_while_revalidate_and_time_refresh(a: u32) -> u32 {
+    a
+}
+
+#[cached(time = 1, shards = 2, stale_while_revalidate = 10)]
+fn stale_while_revalidate_and_shards(a: u32) -> u32 {
+    a
+}
+
+#[cached(size = 10, ttl_from = |_v: &u32| std::time::Duration::from_secs(1))]
+fn ttl_from_and_size(a: u32) -> u32 {
+    a
+}
+
+#[cached(serve_stale_on_error = 10, result = true)]
+fn serve_stale_on_error_without_time(a: u32) -> Result<u32, ()> {
+    Ok(a)
+}
+
+#[cached(time = 1, serve_stale_on_error = 10)]
+fn serve_stale_on_error_without_result(a: u32) -> u32 {
+    a
+}
+
+#[cached(time = 1, stale_while_revalidate = 10, serve_stale_on_error = 10, result = true)]
+fn serve_stale_on_error_and_stale_while_revalidate(a: u32) -> Result<u32, ()> {
+    Ok(a)
+}
+
+fn main() {}
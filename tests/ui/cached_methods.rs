@@ -0,0 +1,34 @@
+use cached::proc_macro::cached;
+
+struct Counter {
+    cache: std::sync::Mutex<cached::UnboundCache<u32, u32>>,
+}
+
+impl Counter {
+    #[cached]
+    fn keyed_on_self(&self, a: u32) -> u32 {
+        a
+    }
+
+    #[cached(cache_field = cache, size = 10)]
+    fn cache_field_and_size(&self, a: u32) -> u32 {
+        a
+    }
+
+    #[cached(ignore_self = true, stale_while_revalidate = 10, time = 1)]
+    fn stale_while_revalidate_on_method(&self, a: u32) -> u32 {
+        a
+    }
+}
+
+#[cached(ignore_self = true)]
+fn ignore_self_without_self(a: u32) -> u32 {
+    a
+}
+
+#[cached(cache_field = cache)]
+fn cache_field_without_self(a: u32) -> u32 {
+    a
+}
+
+fn main() {}
@@ -0,0 +1,41 @@
+use cached::proc_macro::cached;
+
+#[cached(result = true, option = true)]
+fn result_and_option(a: u32) -> Result<u32, ()> {
+    Ok(a)
+}
+
+#[cached(result = true)]
+fn result_without_return(_a: u32) {}
+
+#[cached(option = true)]
+fn option_without_inner_type(a: u32) -> u32 {
+    a
+}
+
+#[cached(result = true)]
+fn result_of_reference(_a: u32) -> &'static Result<u32, ()> {
+    &Ok(1)
+}
+
+#[cached(with_cached_flag = true)]
+fn cached_flag_without_return(a: u32) -> u32 {
+    a
+}
+
+#[cached(cache_err_for = 10)]
+fn cache_err_for_without_result(a: u32) -> Result<u32, ()> {
+    Ok(a)
+}
+
+#[cached(result = true, cache_err_for = 10)]
+fn cache_err_for_without_error_type(a: u32) -> std::io::Result<u32> {
+    Ok(a)
+}
+
+#[cached(result = true, cache_err_for = 10, key = u32, convert = { 1 })]
+fn cache_err_for_generic_error<E>(_e: E) -> Result<u32, E> {
+    Ok(1)
+}
+
+fn main() {}
@@ -0,0 +1,48 @@
+use cached::proc_macro::cached;
+
+#[cached(unbound, size = 10)]
+fn unbound_and_size(a: u32) -> u32 {
+    a
+}
+
+#[cached(type = cached::UnboundCache<u32, u32>)]
+fn type_without_create(a: u32) -> u32 {
+    a
+}
+
+#[cached(create = { cached::UnboundCache::new() })]
+fn create_without_type(a: u32) -> u32 {
+    a
+}
+
+#[cached(policy = "lfu")]
+fn policy_without_size(a: u32) -> u32 {
+    a
+}
+
+#[cached(size = 10, policy = "fifo")]
+fn unknown_policy(a: u32) -> u32 {
+    a
+}
+
+#[cached(max_weight = 10)]
+fn max_weight_without_weigher(a: u32) -> u32 {
+    a
+}
+
+#[cached(size = 10, max_weight = 10, weigher = |_k: &u32, _v: &u32| 1)]
+fn max_weight_and_size(a: u32) -> u32 {
+    a
+}
+
+#[cached(shards = 0)]
+fn zero_shards(a: u32) -> u32 {
+    a
+}
+
+#[cached(shards = 2, sync_writes = true)]
+async fn sharded_async_sync_writes(a: u32) -> u32 {
+    a
+}
+
+fn main() {}
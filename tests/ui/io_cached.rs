@@ -0,0 +1,53 @@
+use cached::proc_macro::io_cached;
+
+#[io_cached(map_error = |e| e, type = cached::UnboundCache<u32, u32>, create = { cached::UnboundCache::new() })]
+fn not_a_result(a: u32) -> u32 {
+    a
+}
+
+#[io_cached(map_error = |e| e, redis = true)]
+fn redis_without_time(a: u32) -> Result<u32, String> {
+    Ok(a)
+}
+
+#[io_cached(map_error = |e| e, type = cached::UnboundCache<u32, u32>)]
+fn type_without_create(a: u32) -> Result<u32, String> {
+    Ok(a)
+}
+
+#[io_cached(map_error = |e| e, time = 1, type = cached::UnboundCache<u32, u32>, create = { cached::UnboundCache::new() })]
+fn create_and_time(a: u32) -> Result<u32, String> {
+    Ok(a)
+}
+
+#[io_cached(map_error = |e| e, redis = true, time_refresh = true, ttl_from = |_v: &u32| std::time::Duration::from_secs(1))]
+fn ttl_from_and_time_refresh(a: u32) -> Result<u32, String> {
+    Ok(a)
+}
+
+#[io_cached(map_error = |e| e, serve_stale_on_error = 10, type = cached::UnboundCache<u32, u32>, create = { cached::UnboundCache::new() })]
+fn serve_stale_on_error_without_redis(a: u32) -> Result<u32, String> {
+    Ok(a)
+}
+
+#[io_cached(map_error = |e| e, redis = true, time = 1, serve_stale_on_error = 0)]
+fn zero_serve_stale_on_error(a: u32) -> Result<u32, String> {
+    Ok(a)
+}
+
+#[io_cached(map_error = |e| e, cache_err_for = 10, type = cached::UnboundCache<u32, u32>, create = { cached::UnboundCache::new() })]
+fn cache_err_for_without_redis(a: u32) -> Result<u32, String> {
+    Ok(a)
+}
+
+#[io_cached(map_error = |e| e, evict_undeserializable = true, type = cached::UnboundCache<u32, u32>, create = { cached::UnboundCache::new() })]
+fn evict_undeserializable_without_redis(a: u32) -> Result<u32, String> {
+    Ok(a)
+}
+
+#[io_cached(redis = true, time = 1)]
+fn missing_map_error(a: u32) -> Result<u32, String> {
+    Ok(a)
+}
+
+fn main() {}
@@ -0,0 +1,32 @@
+use cached::proc_macro::once;
+
+#[once(clock = cached::MockClock::new())]
+fn clock_without_time() -> u32 {
+    1
+}
+
+#[once(cache_field = cache)]
+fn cache_field_without_self() -> u32 {
+    1
+}
+
+#[once(result = true, option = true)]
+fn result_and_option() -> Result<u32, ()> {
+    Ok(1)
+}
+
+struct Counter;
+
+impl Counter {
+    #[once]
+    fn receiver_without_ignore_self(&self) -> u32 {
+        1
+    }
+
+    #[once(ignore_self = true)]
+    fn ignore_self_without_receiver() -> u32 {
+        1
+    }
+}
+
+fn main() {}